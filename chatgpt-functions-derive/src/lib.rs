@@ -0,0 +1,484 @@
+//! Derive macros for chatgpt-functions.
+//!
+//! `#[derive(ChatFunction)]` generates the FunctionSpecification of a struct, so the functions
+//! sent to the model can't drift out of sync with the Rust types that handle the calls.
+//! `#[derive(ChatParameter)]` lets a type be used as a field: a struct is sent as a nested object,
+//! and an enum of unit variants as a string with the names of the variants as `enum`.
+//!
+//! Use them through the `derive` feature of chatgpt-functions, not directly.
+//!
+//! # Attributes
+//! - Doc comments are used as the descriptions of the function and its parameters
+//! - `#[chat_function(name = "...")]` sets the name of the function.
+//!   If not provided, it is the name of the struct in snake_case.
+//!   It is an error on the fields, or on the types deriving ChatParameter
+//! - `#[chat_function(description = "...")]` overrides the doc comment of the struct or a field
+//! - `#[serde(rename = "...")]`, `#[serde(rename_all = "...")]`, `#[serde(default)]`
+//!   (on the struct or its fields) and `#[serde(skip)]` are followed,
+//!   so the specification matches what serde deserializes. For `rename(deserialize = "...")`
+//!   the deserialize name is used. `#[serde(flatten)]` is not supported, it is an error
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Fields, LitStr, Token};
+
+#[proc_macro_derive(ChatFunction, attributes(chat_function))]
+pub fn derive_chat_function(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_chat_function(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(ChatParameter, attributes(chat_function))]
+pub fn derive_chat_parameter(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_chat_parameter(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand_chat_function(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let parameters = match &input.data {
+        Data::Struct(data) => field_properties(&input, &data.fields, "ChatFunction")?,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "ChatFunction can only be derived for structs",
+            ))
+        }
+    };
+
+    let attributes = Attributes::parse(&input.attrs)?;
+    let ident = &input.ident;
+    let name = match &attributes.name {
+        Some(name) => name.value(),
+        None => rename_variant(&unraw(ident), Some("snake_case")),
+    };
+    let description = description_tokens(attributes.description());
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::chatgpt_functions::chat_function::ChatFunction
+            for #ident #ty_generics #where_clause
+        {
+            fn function_specification(
+            ) -> ::chatgpt_functions::function_specification::FunctionSpecification {
+                #[allow(unused_mut)]
+                let mut properties = ::std::collections::HashMap::new();
+                #[allow(unused_mut)]
+                let mut required: ::std::vec::Vec<::std::string::String> = ::std::vec::Vec::new();
+                #(#parameters)*
+                ::chatgpt_functions::function_specification::FunctionSpecification {
+                    name: #name.to_string(),
+                    description: #description,
+                    parameters: ::std::option::Option::Some(
+                        ::chatgpt_functions::function_specification::Parameters {
+                            type_: "object".to_string(),
+                            properties,
+                            required,
+                        },
+                    ),
+                }
+            }
+        }
+    })
+}
+
+fn expand_chat_parameter(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let container = Attributes::parse(&input.attrs)?;
+    container.reject_name()?;
+    let description = description_tokens(container.description());
+    let body = match &input.data {
+        // An enum of unit variants is a string with the variants as enum
+        Data::Enum(data) => {
+            let mut values = Vec::new();
+            for variant in &data.variants {
+                if !matches!(variant.fields, Fields::Unit) {
+                    return Err(syn::Error::new_spanned(
+                        variant,
+                        "ChatParameter can only be derived for enums with unit variants",
+                    ));
+                }
+                let attributes = Attributes::parse(&variant.attrs)?;
+                attributes.reject_name()?;
+                if attributes.skip {
+                    continue;
+                }
+                values.push(match attributes.rename {
+                    Some(rename) => rename,
+                    None => rename_variant(&unraw(&variant.ident), container.rename_all.as_deref()),
+                });
+            }
+            quote! {
+                property.type_ = "string".to_string();
+                property.description = #description;
+                property.enum_ = ::std::option::Option::Some(
+                    ::std::vec![#(::std::convert::Into::into(#values)),*],
+                );
+            }
+        }
+        // A struct is a nested object, with its fields as properties
+        Data::Struct(data) => {
+            let fields = field_properties(&input, &data.fields, "ChatParameter")?;
+            quote! {
+                #[allow(unused_mut)]
+                let mut properties = ::std::collections::HashMap::new();
+                #[allow(unused_mut)]
+                let mut required: ::std::vec::Vec<::std::string::String> = ::std::vec::Vec::new();
+                #(#fields)*
+                property.type_ = "object".to_string();
+                property.description = #description;
+                property.properties = ::std::option::Option::Some(properties);
+                if !required.is_empty() {
+                    property.required = ::std::option::Option::Some(required);
+                }
+            }
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "ChatParameter can only be derived for structs and enums with unit variants",
+            ))
+        }
+    };
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::chatgpt_functions::chat_function::ChatParameter
+            for #ident #ty_generics #where_clause
+        {
+            fn property() -> ::chatgpt_functions::function_specification::Property {
+                let mut property =
+                    ::chatgpt_functions::function_specification::Property::default();
+                #body
+                property
+            }
+        }
+    })
+}
+
+// Generates the code that inserts every field of the struct in `properties`,
+// and pushes the ones that are required to `required`
+fn field_properties(
+    input: &DeriveInput,
+    fields: &Fields,
+    derive: &str,
+) -> syn::Result<Vec<TokenStream2>> {
+    let fields = match fields {
+        Fields::Named(fields) => &fields.named,
+        Fields::Unit => return Ok(Vec::new()),
+        Fields::Unnamed(_) => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                format!(
+                    "{} can only be derived for structs with named fields",
+                    derive
+                ),
+            ))
+        }
+    };
+
+    let container = Attributes::parse(&input.attrs)?;
+    let mut properties = Vec::new();
+    for field in fields {
+        let attributes = Attributes::parse(&field.attrs)?;
+        attributes.reject_name()?;
+        if attributes.skip {
+            continue;
+        }
+        let ident = field.ident.as_ref().expect("Named fields have an ident");
+        let name = match &attributes.rename {
+            Some(rename) => rename.clone(),
+            None => rename_field(&unraw(ident), container.rename_all.as_deref()),
+        };
+        let ty = &field.ty;
+        let description = description_tokens(attributes.description());
+        // A field with a default value can be left out by the model,
+        // like all the fields of a struct with a default value
+        let required = if attributes.default || container.default {
+            quote! {}
+        } else {
+            quote! {
+                if <#ty as ::chatgpt_functions::chat_function::ChatParameter>::is_required() {
+                    required.push(#name.to_string());
+                }
+            }
+        };
+        properties.push(quote! {
+            {
+                let mut property =
+                    <#ty as ::chatgpt_functions::chat_function::ChatParameter>::property();
+                if let ::std::option::Option::Some(description) = #description {
+                    property.description = ::std::option::Option::Some(description);
+                }
+                properties.insert(#name.to_string(), property);
+                #required
+            }
+        });
+    }
+    Ok(properties)
+}
+
+// The attributes of a struct, field, enum or variant that change the specification
+#[derive(Default)]
+struct Attributes {
+    doc: Vec<String>,
+    name: Option<LitStr>,
+    description: Option<String>,
+    rename: Option<String>,
+    rename_all: Option<String>,
+    default: bool,
+    skip: bool,
+}
+
+impl Attributes {
+    fn parse(attrs: &[Attribute]) -> syn::Result<Attributes> {
+        let mut attributes = Attributes::default();
+        for attr in attrs {
+            if attr.path().is_ident("doc") {
+                if let syn::Meta::NameValue(meta) = &attr.meta {
+                    if let syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(doc),
+                        ..
+                    }) = &meta.value
+                    {
+                        attributes.doc.push(doc.value());
+                    }
+                }
+            } else if attr.path().is_ident("chat_function") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("name") {
+                        attributes.name = Some(meta.value()?.parse::<LitStr>()?);
+                        Ok(())
+                    } else if meta.path.is_ident("description") {
+                        attributes.description = Some(meta.value()?.parse::<LitStr>()?.value());
+                        Ok(())
+                    } else {
+                        Err(meta.error("expected `name` or `description`"))
+                    }
+                })?;
+            } else if attr.path().is_ident("serde") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("rename") {
+                        if let Some(rename) = deserialize_name(&meta)? {
+                            attributes.rename = Some(rename);
+                        }
+                    } else if meta.path.is_ident("rename_all") {
+                        if let Some(rename_all) = deserialize_name(&meta)? {
+                            attributes.rename_all = Some(rename_all);
+                        }
+                    } else if meta.path.is_ident("flatten") {
+                        // The fields would have to be merged in the parent, which is not supported
+                        return Err(meta.error(
+                            "`#[serde(flatten)]` is not supported, the specification would not match what serde deserializes",
+                        ));
+                    } else {
+                        if meta.path.is_ident("default") {
+                            attributes.default = true;
+                        } else if meta.path.is_ident("skip")
+                            || meta.path.is_ident("skip_deserializing")
+                        {
+                            attributes.skip = true;
+                        }
+                        // The rest of the serde attributes don't change the specification
+                        if meta.input.peek(Token![=]) {
+                            meta.value()?.parse::<syn::Expr>()?;
+                        } else if meta.input.peek(syn::token::Paren) {
+                            let _content;
+                            syn::parenthesized!(_content in meta.input);
+                        }
+                    }
+                    Ok(())
+                })?;
+            }
+        }
+        Ok(attributes)
+    }
+
+    // `name` is the name of a function, it would be ignored anywhere else
+    fn reject_name(&self) -> syn::Result<()> {
+        match &self.name {
+            Some(name) => Err(syn::Error::new_spanned(
+                name,
+                "`name` can only be set on a struct that derives ChatFunction, use `#[serde(rename = \"...\")]` for the parameters",
+            )),
+            None => Ok(()),
+        }
+    }
+
+    // The description set with the attribute, or the doc comments joined in a single line
+    fn description(&self) -> Option<String> {
+        if self.description.is_some() {
+            return self.description.clone();
+        }
+        let lines: Vec<&str> = self
+            .doc
+            .iter()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .collect();
+        if lines.is_empty() {
+            None
+        } else {
+            Some(lines.join(" "))
+        }
+    }
+}
+
+// The name of a serde `rename` or `rename_all`: `rename = "..."`, or the deserialize one
+// of `rename(serialize = "...", deserialize = "...")`, as the model's arguments are deserialized
+fn deserialize_name(meta: &syn::meta::ParseNestedMeta) -> syn::Result<Option<String>> {
+    if meta.input.peek(Token![=]) {
+        return Ok(Some(meta.value()?.parse::<LitStr>()?.value()));
+    }
+    let mut name = None;
+    meta.parse_nested_meta(|nested| {
+        let value = nested.value()?.parse::<LitStr>()?.value();
+        if nested.path.is_ident("deserialize") {
+            name = Some(value);
+        }
+        Ok(())
+    })?;
+    Ok(name)
+}
+
+fn description_tokens(description: Option<String>) -> TokenStream2 {
+    match description {
+        Some(description) => {
+            quote! { ::std::option::Option::Some(#description.to_string()) }
+        }
+        None => quote! { ::std::option::Option::None },
+    }
+}
+
+fn unraw(ident: &syn::Ident) -> String {
+    ident.to_string().trim_start_matches("r#").to_string()
+}
+
+// Applies a serde rename_all rule to a variant name, which is in PascalCase
+fn rename_variant(name: &str, rule: Option<&str>) -> String {
+    let snake = || {
+        let mut snake = String::new();
+        for (i, c) in name.char_indices() {
+            if c.is_uppercase() && i > 0 {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        }
+        snake
+    };
+    match rule {
+        Some("lowercase") => name.to_ascii_lowercase(),
+        Some("UPPERCASE") => name.to_ascii_uppercase(),
+        Some("camelCase") => lowercase_first(name),
+        Some("snake_case") => snake(),
+        Some("SCREAMING_SNAKE_CASE") => snake().to_ascii_uppercase(),
+        Some("kebab-case") => snake().replace('_', "-"),
+        Some("SCREAMING-KEBAB-CASE") => snake().replace('_', "-").to_ascii_uppercase(),
+        _ => name.to_string(),
+    }
+}
+
+// Applies a serde rename_all rule to a field name, which is in snake_case
+fn rename_field(name: &str, rule: Option<&str>) -> String {
+    let pascal = || {
+        name.split('_')
+            .map(|word| {
+                let mut chars = word.chars();
+                match chars.next() {
+                    Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                    None => String::new(),
+                }
+            })
+            .collect::<String>()
+    };
+    match rule {
+        Some("UPPERCASE") | Some("SCREAMING_SNAKE_CASE") => name.to_ascii_uppercase(),
+        Some("PascalCase") => pascal(),
+        Some("camelCase") => lowercase_first(&pascal()),
+        Some("kebab-case") => name.replace('_', "-"),
+        Some("SCREAMING-KEBAB-CASE") => name.replace('_', "-").to_ascii_uppercase(),
+        _ => name.to_string(),
+    }
+}
+
+// Lowercases the first char if it is ASCII, like serde. The name may be empty, e.g. a field `__`
+fn lowercase_first(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rename_non_ascii_and_empty_names() {
+        assert_eq!(rename_variant("Été", Some("camelCase")), "Été");
+        assert_eq!(rename_variant("ÜberMode", Some("snake_case")), "Über_mode");
+        assert_eq!(rename_variant("MiddleRow", Some("camelCase")), "middleRow");
+        assert_eq!(rename_field("__", Some("camelCase")), "");
+        assert_eq!(rename_field("__", Some("PascalCase")), "");
+        assert_eq!(rename_field("école_nom", Some("camelCase")), "écoleNom");
+        assert_eq!(
+            rename_field("from_airport", Some("camelCase")),
+            "fromAirport"
+        );
+    }
+
+    #[test]
+    fn test_flatten_is_rejected() {
+        let input: DeriveInput = syn::parse_quote! {
+            struct SearchOrders {
+                #[serde(flatten)]
+                filter: OrderFilter,
+            }
+        };
+        let error = expand_chat_function(input).expect_err("flatten is not supported");
+        assert!(error.to_string().contains("flatten"), "{}", error);
+    }
+
+    #[test]
+    fn test_name_is_only_accepted_on_functions() {
+        let field = expand_chat_function(syn::parse_quote! {
+            struct BookFlight {
+                #[chat_function(name = "origin")]
+                from_airport: String,
+            }
+        });
+        let parameter = expand_chat_parameter(syn::parse_quote! {
+            #[chat_function(name = "filter")]
+            struct OrderFilter {
+                customer_ids: Vec<u64>,
+            }
+        });
+        let variant = expand_chat_parameter(syn::parse_quote! {
+            enum Seat {
+                #[chat_function(name = "window")]
+                Window,
+            }
+        });
+        for result in [field, parameter, variant] {
+            let error = result.expect_err("`name` is not valid there");
+            assert!(
+                error.to_string().contains("`name` can only be set"),
+                "{}",
+                error
+            );
+        }
+        let function = expand_chat_function(syn::parse_quote! {
+            #[chat_function(name = "book")]
+            struct BookFlight {
+                from_airport: String,
+            }
+        });
+        assert!(function.is_ok());
+    }
+}
@@ -0,0 +1,213 @@
+use chatgpt_functions::{
+    function_specification::{Parameters, Property},
+    message::FunctionCall,
+    ChatFunction, ChatParameter, Error,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Debug, Deserialize, PartialEq, ChatParameter)]
+#[serde(rename_all = "lowercase")]
+enum Unit {
+    Celsius,
+    Fahrenheit,
+}
+
+/// Get the current weather
+/// in a given location
+#[derive(Debug, Deserialize, PartialEq, ChatFunction)]
+struct GetCurrentWeather {
+    /// The city and state, e.g. San Francisco, CA
+    location: String,
+    /// The unit of the temperature
+    unit: Option<Unit>,
+    days: Vec<u32>,
+}
+
+#[test]
+fn test_function_specification() {
+    let specification = GetCurrentWeather::function_specification();
+    assert_eq!(specification.name, "get_current_weather");
+    assert_eq!(
+        specification.description,
+        Some("Get the current weather in a given location".to_string())
+    );
+
+    let parameters = specification.parameters.expect("There are no parameters");
+    assert_eq!(parameters.type_, "object");
+    assert_eq!(
+        parameters.required,
+        vec!["location".to_string(), "days".to_string()]
+    );
+    assert_eq!(
+        parameters.properties["location"],
+        Property {
+            type_: "string".to_string(),
+            description: Some("The city and state, e.g. San Francisco, CA".to_string()),
+            ..Property::default()
+        }
+    );
+    assert_eq!(
+        parameters.properties["unit"],
+        Property {
+            type_: "string".to_string(),
+            description: Some("The unit of the temperature".to_string()),
+            enum_: Some(vec![json!("celsius"), json!("fahrenheit")]),
+            ..Property::default()
+        }
+    );
+    assert_eq!(
+        parameters.properties["days"],
+        Property {
+            type_: "array".to_string(),
+            items: Some(Box::new(Property::new("integer".to_string()))),
+            ..Property::default()
+        }
+    );
+}
+
+#[test]
+fn test_from_function_call() {
+    let call = GetCurrentWeather::from_function_call(&FunctionCall {
+        name: "get_current_weather".to_string(),
+        arguments: r#"{"location": "Madrid", "unit": "celsius", "days": [1, 2]}"#.to_string(),
+    })
+    .expect("Failed to deserialize the arguments");
+    assert_eq!(
+        call,
+        GetCurrentWeather {
+            location: "Madrid".to_string(),
+            unit: Some(Unit::Celsius),
+            days: vec![1, 2],
+        }
+    );
+
+    let error = GetCurrentWeather::from_arguments(r#"{"unit": "kelvin"}"#)
+        .expect_err("The arguments are not valid");
+    assert!(matches!(error, Error::Deserialize { .. }));
+}
+
+/// Books a flight
+#[derive(Deserialize, ChatFunction)]
+#[chat_function(name = "book")]
+#[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
+struct BookFlight {
+    /// Ignored, the description is set with the attribute
+    #[chat_function(description = "IATA code of the origin")]
+    from_airport: String,
+    #[serde(rename = "to")]
+    to_airport: String,
+    #[serde(rename(serialize = "class", deserialize = "cabin"))]
+    cabin_class: Option<String>,
+    #[serde(default)]
+    passengers: u8,
+    #[serde(skip)]
+    confirmed: bool,
+    seat: Option<Seat>,
+}
+
+#[derive(Deserialize, ChatParameter)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[allow(dead_code)]
+enum Seat {
+    Window,
+    #[serde(rename = "aisle")]
+    Aisle,
+    MiddleRow,
+}
+
+#[test]
+fn test_serde_attributes() {
+    let specification = BookFlight::function_specification();
+    assert_eq!(specification.name, "book");
+
+    let Parameters {
+        properties,
+        required,
+        ..
+    } = specification.parameters.expect("There are no parameters");
+    assert_eq!(required, vec!["fromAirport".to_string(), "to".to_string()]);
+    let mut names: Vec<&String> = properties.keys().collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec!["cabin", "fromAirport", "passengers", "seat", "to"]
+    );
+    assert_eq!(
+        properties["fromAirport"].description,
+        Some("IATA code of the origin".to_string())
+    );
+    assert_eq!(
+        properties["seat"].enum_,
+        Some(vec![json!("WINDOW"), json!("aisle"), json!("MIDDLE_ROW")])
+    );
+}
+
+#[test]
+fn test_chat_parameter() {
+    assert_eq!(Unit::property().type_, "string");
+    assert!(Unit::is_required());
+    assert!(!Option::<Unit>::is_required());
+}
+
+/// Lists the flights
+#[derive(Default, Deserialize, ChatFunction)]
+#[serde(default)]
+#[allow(dead_code)]
+struct ListFlights {
+    origin: String,
+    #[serde(rename = "max")]
+    limit: u32,
+}
+
+#[test]
+fn test_container_default() {
+    let parameters = ListFlights::function_specification()
+        .parameters
+        .expect("There are no parameters");
+    assert!(parameters.required.is_empty());
+    assert_eq!(parameters.properties.len(), 2);
+    let flights = ListFlights::from_arguments("{}").expect("The fields have a default value");
+    assert_eq!(flights.limit, 0);
+}
+
+/// The orders to search
+#[derive(Deserialize, ChatParameter)]
+#[allow(dead_code)]
+struct OrderFilter {
+    /// Only the orders of these customers
+    customer_ids: Vec<u64>,
+    status: Option<Unit>,
+}
+
+/// Search the orders
+#[derive(Deserialize, ChatFunction)]
+#[allow(dead_code)]
+struct SearchOrders {
+    filter: OrderFilter,
+    limit: Option<u32>,
+}
+
+#[test]
+fn test_nested_object() {
+    let parameters = SearchOrders::function_specification()
+        .parameters
+        .expect("There are no parameters");
+    assert_eq!(parameters.required, vec!["filter".to_string()]);
+
+    let filter = &parameters.properties["filter"];
+    assert_eq!(filter.type_, "object");
+    assert_eq!(filter.description, Some("The orders to search".to_string()));
+    assert_eq!(filter.required, Some(vec!["customer_ids".to_string()]));
+    let properties = filter.properties.as_ref().expect("There are no properties");
+    assert_eq!(
+        properties["customer_ids"].description,
+        Some("Only the orders of these customers".to_string())
+    );
+    assert_eq!(
+        properties["customer_ids"].items,
+        Some(Box::new(Property::new("integer".to_string())))
+    );
+    assert_eq!(properties["status"].type_, "string");
+}
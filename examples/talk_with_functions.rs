@@ -3,6 +3,7 @@ use std::collections::HashMap;
 use anyhow::{Context, Result};
 use chatgpt_functions::{
     chat_gpt::ChatGPTBuilder,
+    function_registry::FunctionRegistry,
     function_specification::{FunctionSpecification, Parameters, Property},
 };
 use dotenv::dotenv;
@@ -20,7 +21,7 @@ async fn main() -> Result<()> {
         Property {
             type_: "string".to_string(),
             description: Some("The city and state, e.g. San Francisco, CA".to_string()),
-            enum_: None,
+            ..Property::default()
         },
     );
     let function = FunctionSpecification {
@@ -28,12 +29,22 @@ async fn main() -> Result<()> {
         description: Some("Get the current weather in a given location".to_string()),
         parameters: Some(Parameters {
             type_: "object".to_string(),
-            properties: properties,
+            properties,
             required: vec!["location".to_string()],
         }),
     };
 
-    gpt.push_function(function);
+    // The model asks for the weather, the registry runs this function and sends the result back
+    let mut registry = FunctionRegistry::new();
+    registry.register(function, |arguments| async move {
+        let arguments: serde_json::Value = serde_json::from_str(&arguments)?;
+        let location = arguments["location"].as_str().unwrap_or("somewhere");
+        Ok(format!(
+            "{{\"location\": \"{}\", \"temperature\": 22, \"unit\": \"celsius\", \"forecast\": \"sunny\"}}",
+            location
+        ))
+    });
+    gpt.set_functions(registry.specifications());
 
     println!("Initialised chatbot. Enter your message to start a conversation.");
     println!("Using:");
@@ -51,7 +62,7 @@ async fn main() -> Result<()> {
 
         println!("- AI:");
         // println!("Request: {}", chat_context);
-        let answer = gpt.completion_managed(input).await?;
+        let answer = gpt.run_until_answer(input, &registry).await?;
         // println!("Full answer: {}", answer.to_string());
         print_answer(&answer);
         println!("--------------------------------------");
@@ -60,23 +71,14 @@ async fn main() -> Result<()> {
 
 fn print_answer(answer: &chatgpt_functions::chat_response::ChatResponse) {
     for choice in &answer.choices {
-        match choice.message.content {
-            Some(ref content) => {
-                println!("Answer: {}", content);
-            }
-            None => (),
-        };
-        match choice.message.name {
-            Some(ref name) => {
-                println!("Name: {}", name);
-            }
-            None => (),
-        };
-        match choice.message.function_call {
-            Some(ref function_call) => {
-                println!("Function call: {}", function_call);
-            }
-            None => (),
-        };
+        if let Some(ref content) = choice.message.content {
+            println!("Answer: {}", content);
+        }
+        if let Some(ref name) = choice.message.name {
+            println!("Name: {}", name);
+        }
+        if let Some(ref function_call) = choice.message.function_call {
+            println!("Function call: {}", function_call);
+        }
     }
 }
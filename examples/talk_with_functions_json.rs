@@ -69,23 +69,14 @@ async fn main() -> Result<()> {
 
 fn print_answer(answer: &chatgpt_functions::chat_response::ChatResponse) {
     for choice in &answer.choices {
-        match choice.message.content {
-            Some(ref content) => {
-                println!("Answer: {}", content);
-            }
-            None => (),
-        };
-        match choice.message.name {
-            Some(ref name) => {
-                println!("Name: {}", name);
-            }
-            None => (),
-        };
-        match choice.message.function_call {
-            Some(ref function_call) => {
-                println!("Function call: {}", function_call);
-            }
-            None => (),
-        };
+        if let Some(ref content) = choice.message.content {
+            println!("Answer: {}", content);
+        }
+        if let Some(ref name) = choice.message.name {
+            println!("Name: {}", name);
+        }
+        if let Some(ref function_call) = choice.message.function_call {
+            println!("Function call: {}", function_call);
+        }
     }
 }
@@ -0,0 +1,446 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::anyhow;
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    conversation_store::write_atomically,
+    error::{Error, Result},
+    transport::{
+        ReqwestTransport, Transport, TransportFuture, TransportRequest, TransportResponse,
+    },
+};
+
+// Written instead of the API token, wherever it appears in the interactions
+const REDACTED: &str = "REDACTED";
+// Response headers that are not written to the cassette
+const SKIPPED_HEADERS: [&str; 1] = ["set-cookie"];
+
+/// The requests and responses recorded by a CassetteTransport, saved as a JSON file
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+/// A request sent to the API and the response it got
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct RecordedRequest {
+    pub url: String,
+    /// The serialized ChatContext, as JSON so the cassette is readable and diffs well
+    pub body: serde_json::Value,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// The raw body, e.g. the server-sent events of a streamed response
+    pub body: String,
+}
+
+impl Cassette {
+    pub fn load(path: impl AsRef<Path>) -> Result<Cassette> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path)
+            .map_err(|e| Error::Cassette(anyhow!("Could not read {}: {}", path.display(), e)))?;
+        serde_json::from_str(&json).map_err(|source| Error::Deserialize { source, body: json })
+    }
+
+    /// Saves the cassette as pretty printed JSON, creating the directories of the path
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let json = serde_json::to_string_pretty(self).map_err(|e| Error::Cassette(e.into()))?;
+        path.parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| write_atomically(path, &json))
+            .map_err(|e| Error::Cassette(anyhow!("Could not write {}: {}", path.display(), e)))
+    }
+}
+
+/// How a CassetteTransport handles the requests
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Sends the requests to the API and records them with their responses,
+    /// replacing the previous content of the cassette
+    Record,
+    /// Replies with the recorded responses, without network access.
+    /// A request can be replayed more than once
+    Replay,
+    /// Like Replay, but every interaction is replayed once at most,
+    /// and CassetteTransport::verify fails if some of them were not replayed
+    Strict,
+}
+
+/// A transport that records the interactions with the API to a cassette file,
+/// and replays them later, to run the tests of a chatbot offline and deterministically
+///
+/// The requests are matched with the recorded ones by their body, the serialized ChatContext,
+/// compared as JSON. The URL is not compared, so a cassette recorded against a proxy can be
+/// replayed for another base URL. When the same request was recorded more than once,
+/// the responses are replayed in the order they were recorded.
+/// A request that doesn't match any interaction fails with Error::Cassette.
+///
+/// The API token is never written to the cassette, nor is it needed to replay it.
+///
+/// # Example
+/// ```no_run
+/// use std::sync::Arc;
+/// use chatgpt_functions::{
+///     cassette::{CassetteMode, CassetteTransport},
+///     chat_gpt::ChatGPTBuilder,
+/// };
+///
+/// # #[tokio::main]
+/// # async fn main() -> chatgpt_functions::Result<()> {
+/// // Record with RECORD=1 and a real API key, replay in CI
+/// let mode = if std::env::var("RECORD").is_ok() {
+///     CassetteMode::Record
+/// } else {
+///     CassetteMode::Strict
+/// };
+/// let cassette = Arc::new(CassetteTransport::new("tests/cassettes/greeting.json", mode)?);
+/// let mut gpt = ChatGPTBuilder::new()
+///     .openai_api_token(std::env::var("OPENAI_API_KEY").unwrap_or_default())
+///     .transport(cassette.clone())
+///     .build()?;
+///
+/// gpt.completion_managed("Hello".to_string()).await?;
+/// cassette.verify()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct CassetteTransport {
+    path: PathBuf,
+    mode: CassetteMode,
+    inner: Arc<dyn Transport>,
+    state: Mutex<State>,
+}
+
+struct State {
+    cassette: Cassette,
+    // Whether each interaction was replayed
+    used: Vec<bool>,
+}
+
+impl CassetteTransport {
+    /// In Record mode, it starts an empty cassette and sends the requests with ReqwestTransport.
+    /// In the replay modes, it loads the cassette
+    /// # Errors
+    /// It returns an error if the cassette can't be written or read
+    pub fn new(path: impl Into<PathBuf>, mode: CassetteMode) -> Result<CassetteTransport> {
+        let path = path.into();
+        let cassette = if mode == CassetteMode::Record {
+            let cassette = Cassette::default();
+            cassette.save(&path)?;
+            cassette
+        } else {
+            Cassette::load(&path)?
+        };
+        let used = vec![false; cassette.interactions.len()];
+        Ok(CassetteTransport {
+            path,
+            mode,
+            inner: Arc::new(ReqwestTransport::default()),
+            state: Mutex::new(State { cassette, used }),
+        })
+    }
+
+    /// The transport used to send the requests in Record mode. By default it is ReqwestTransport
+    pub fn inner(mut self, inner: Arc<dyn Transport>) -> CassetteTransport {
+        self.inner = inner;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    /// The interactions recorded, or loaded to be replayed
+    pub fn cassette(&self) -> Cassette {
+        self.lock().cassette.clone()
+    }
+
+    /// The indexes of the interactions of the cassette that were not replayed
+    pub fn unused(&self) -> Vec<usize> {
+        let state = self.lock();
+        (0..state.used.len()).filter(|i| !state.used[*i]).collect()
+    }
+
+    /// Checks that every interaction of the cassette was replayed, in Strict mode.
+    /// Call it at the end of a test. It does nothing in the other modes
+    /// # Errors
+    /// It returns Error::Cassette with the requests that were not replayed
+    pub fn verify(&self) -> Result<()> {
+        if self.mode != CassetteMode::Strict {
+            return Ok(());
+        }
+        let unused = self.unused();
+        if unused.is_empty() {
+            return Ok(());
+        }
+        let state = self.lock();
+        let requests: Vec<String> = unused
+            .iter()
+            .map(|i| state.cassette.interactions[*i].request.body.to_string())
+            .collect();
+        Err(Error::Cassette(anyhow!(
+            "{} interactions of {} were not replayed:\n{}",
+            unused.len(),
+            self.path.display(),
+            requests.join("\n")
+        )))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    async fn record(&self, request: TransportRequest) -> Result<TransportResponse> {
+        let api_key = request.api_key.clone();
+        let url = request.url.clone();
+        let request_body = request.body.clone();
+        let response = self.inner.send(request).await?;
+        let status = response.status;
+        let headers = response.headers.clone();
+        let body = response.text().await?;
+
+        let redact = |text: &str| {
+            if api_key.is_empty() {
+                text.to_string()
+            } else {
+                text.replace(&api_key, REDACTED)
+            }
+        };
+        let interaction = Interaction {
+            request: RecordedRequest {
+                url: redact(&url),
+                body: parse_body(&redact(&request_body)),
+            },
+            response: RecordedResponse {
+                status,
+                headers: recorded_headers(&headers)
+                    .into_iter()
+                    .map(|(name, value)| (name, redact(&value)))
+                    .collect(),
+                body: redact(&body),
+            },
+        };
+        {
+            let mut state = self.lock();
+            state.cassette.interactions.push(interaction);
+            state.used.push(true);
+            state.cassette.save(&self.path)?;
+        }
+
+        let mut replayed = TransportResponse::new(status, body);
+        replayed.headers = headers;
+        Ok(replayed)
+    }
+
+    fn replay(&self, request: &TransportRequest) -> Result<TransportResponse> {
+        let body = parse_body(&request.body);
+        let mut state = self.lock();
+        let matching: Vec<usize> = (0..state.cassette.interactions.len())
+            .filter(|i| state.cassette.interactions[*i].request.body == body)
+            .collect();
+        let unused = matching.iter().find(|i| !state.used[**i]);
+        let index = match (unused, self.mode) {
+            (Some(i), _) => *i,
+            (None, CassetteMode::Replay) if !matching.is_empty() => matching[matching.len() - 1],
+            (None, _) => {
+                let reason = if matching.is_empty() {
+                    "No interaction matches"
+                } else {
+                    "Every interaction was already replayed for"
+                };
+                return Err(Error::Cassette(anyhow!(
+                    "{} of {}. The request: {}",
+                    reason,
+                    self.path.display(),
+                    body
+                )));
+            }
+        };
+        state.used[index] = true;
+
+        let recorded = &state.cassette.interactions[index].response;
+        Ok(recorded.headers.iter().fold(
+            TransportResponse::new(recorded.status, recorded.body.clone()),
+            |response, (name, value)| response.header(name, value),
+        ))
+    }
+}
+
+impl Transport for CassetteTransport {
+    fn send(&self, request: TransportRequest) -> TransportFuture<'_> {
+        Box::pin(async move {
+            match self.mode {
+                CassetteMode::Record => self.record(request).await,
+                CassetteMode::Replay | CassetteMode::Strict => self.replay(&request),
+            }
+        })
+    }
+}
+
+// The body is stored as JSON when it is, so the keys are compared regardless of the spacing
+fn parse_body(body: &str) -> serde_json::Value {
+    serde_json::from_str(body).unwrap_or_else(|_| serde_json::Value::String(body.to_string()))
+}
+
+fn recorded_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .filter(|(name, _)| !SKIPPED_HEADERS.contains(&name.as_str()))
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chat_gpt::ChatGPTBuilder,
+        mock_server::{answer, usage},
+        transport::ScriptedTransport,
+    };
+
+    fn temporary_cassette(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("chatgpt-functions-{}", uuid::Uuid::new_v4()))
+            .join(format!("{}.json", name))
+    }
+
+    fn chat_gpt(transport: Arc<dyn Transport>) -> crate::chat_gpt::ChatGPT {
+        ChatGPTBuilder::new()
+            .openai_api_token("sk-secret".to_string())
+            .transport(transport)
+            .build()
+            .expect("Failed to create ChatGPT")
+    }
+
+    async fn record(path: &Path) {
+        let scripted = ScriptedTransport::new()
+            .response(
+                TransportResponse::new(200, answer("Hi!", usage(9, 2))).header("x-request-id", "1"),
+            )
+            .response(TransportResponse::new(
+                200,
+                answer("Bye, sk-secret!", usage(20, 2)),
+            ));
+        let cassette = Arc::new(
+            CassetteTransport::new(path, CassetteMode::Record)
+                .expect("Failed to start the cassette")
+                .inner(Arc::new(scripted)),
+        );
+        let mut gpt = chat_gpt(cassette.clone());
+        gpt.completion_managed("Hello".to_string())
+            .await
+            .expect("Failed to record");
+        gpt.completion_managed("Bye".to_string())
+            .await
+            .expect("Failed to record");
+        cassette.verify().expect("Recording is never verified");
+    }
+
+    #[tokio::test]
+    async fn test_record() {
+        let path = temporary_cassette("record");
+        record(&path).await;
+
+        let cassette = Cassette::load(&path).expect("Failed to load the cassette");
+        assert_eq!(cassette.interactions.len(), 2);
+        let first = &cassette.interactions[0];
+        assert_eq!(
+            first.request.url,
+            "https://api.openai.com/v1/chat/completions"
+        );
+        assert_eq!(
+            first.request.body["messages"][0]["content"],
+            serde_json::json!("Hello")
+        );
+        assert_eq!(first.response.status, 200);
+        assert_eq!(first.response.headers["x-request-id"], "1");
+        assert_eq!(first.response.body, answer("Hi!", usage(9, 2)));
+        let json = fs::read_to_string(&path).expect("Failed to read the cassette");
+        assert!(!json.contains("sk-secret"));
+        assert!(json.contains("Bye, REDACTED!"));
+        fs::remove_dir_all(path.parent().expect("The cassette is in a directory"))
+            .expect("Failed to remove the directory");
+    }
+
+    #[tokio::test]
+    async fn test_replay() {
+        let path = temporary_cassette("replay");
+        record(&path).await;
+
+        let cassette =
+            Arc::new(CassetteTransport::new(&path, CassetteMode::Replay).expect("Failed to load"));
+        let mut gpt = chat_gpt(cassette.clone());
+        let answer = gpt
+            .completion_managed("Hello".to_string())
+            .await
+            .expect("Failed to replay");
+        assert_eq!(answer.content(), Some("Hi!".to_string()));
+        // Not replayed in order, and more than once
+        gpt.chat_context.set_messages(Vec::new());
+        gpt.completion_managed("Hello".to_string())
+            .await
+            .expect("Failed to replay again");
+        assert_eq!(cassette.unused(), vec![1]);
+        cassette.verify().expect("Only the Strict mode is verified");
+
+        let error = gpt
+            .completion_managed("Something else".to_string())
+            .await
+            .expect_err("The request is not in the cassette");
+        assert!(matches!(error, Error::Cassette(_)));
+        fs::remove_dir_all(path.parent().expect("The cassette is in a directory"))
+            .expect("Failed to remove the directory");
+    }
+
+    #[tokio::test]
+    async fn test_replay_strict() {
+        let path = temporary_cassette("strict");
+        record(&path).await;
+
+        let cassette =
+            Arc::new(CassetteTransport::new(&path, CassetteMode::Strict).expect("Failed to load"));
+        let mut gpt = chat_gpt(cassette.clone());
+        gpt.completion_managed("Hello".to_string())
+            .await
+            .expect("Failed to replay");
+        assert!(matches!(cassette.verify(), Err(Error::Cassette(_))));
+        let answer = gpt
+            .completion_managed("Bye".to_string())
+            .await
+            .expect("Failed to replay");
+        assert_eq!(answer.content(), Some("Bye, REDACTED!".to_string()));
+        cassette.verify().expect("Every interaction was replayed");
+
+        gpt.chat_context.set_messages(Vec::new());
+        let error = gpt
+            .completion_managed("Hello".to_string())
+            .await
+            .expect_err("The interaction was already replayed");
+        assert!(matches!(error, Error::Cassette(_)));
+        fs::remove_dir_all(path.parent().expect("The cassette is in a directory"))
+            .expect("Failed to remove the directory");
+    }
+}
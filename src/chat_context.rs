@@ -1,15 +1,84 @@
-use std::fmt;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
-use serde::{Deserialize, Serialize};
-
-use crate::{function_specification::FunctionSpecification, message::Message};
+use crate::{
+    chat_response::Usage,
+    completion_parameters::CompletionParameters,
+    function_specification::{FunctionSpecification, Tool},
+    message::{FunctionCall, Message, Role, ToolCall},
+    tokenizer::Tokenizer,
+    validation::{validate_arguments, Violation, ViolationKind},
+};
 
+/// The context of the conversation: the model, the messages and the functions,
+/// with the parameters of the completion
+///
+/// It is printed as the body of the request sent to the API
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ChatContext {
     pub model: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub messages: Vec<Message>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub functions: Vec<FunctionSpecification>,
-    pub function_call: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function_call: Option<FunctionCallMode>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<Tool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+    #[serde(flatten)]
+    pub parameters: CompletionParameters,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+    /// The tokens used by the completions of the session so far, added up by ChatGPT.
+    /// It is not sent to the API, but it is saved with the context by the conversation stores
+    #[serde(skip)]
+    pub usage: Usage,
+    /// The cost in dollars of the completions of the session so far, of the models in the PriceTable.
+    /// It is saved with the context like the usage
+    #[serde(skip)]
+    pub cost: f64,
+}
+
+/// The options of a streamed completion
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct StreamOptions {
+    /// The API sends the usage of the completion in a last chunk, without choices
+    #[serde(default)]
+    pub include_usage: bool,
+}
+
+/// Controls how the model calls the functions of the context
+///
+/// * `Auto` - The model decides whether to call a function or not. The default when there are functions
+/// * `None` - The model does not call any function. The default when there are no functions
+/// * `Named` - The model is forced to call the function with that name
+#[derive(Clone, Debug, PartialEq)]
+pub enum FunctionCallMode {
+    Auto,
+    None,
+    Named(String),
+}
+
+/// Controls how the model calls the tools of the context
+///
+/// * `Auto` - The model decides whether to call tools or not. The default when there are tools
+/// * `None` - The model does not call any tool. The default when there are no tools
+/// * `Required` - The model has to call one or more tools
+/// * `Function` - The model is forced to call the function with that name
+#[derive(Clone, Debug, PartialEq)]
+pub enum ToolChoice {
+    Auto,
+    None,
+    Required,
+    Function(String),
+}
+
+// Other system messages, like the summaries of the compaction, have a name
+fn is_system_prompt(message: &Message) -> bool {
+    message.role == Role::System && message.name.is_none()
 }
 
 impl ChatContext {
@@ -21,9 +90,22 @@ impl ChatContext {
             messages: Vec::new(),
             functions: Vec::new(),
             function_call: None,
+            tools: Vec::new(),
+            tool_choice: None,
+            parameters: CompletionParameters::default(),
+            stream: None,
+            stream_options: None,
+            usage: Usage::default(),
+            cost: 0.0,
         }
     }
 
+    /// Counts the tokens of the prompt, with the encoding of the model of the context
+    /// See Tokenizer::count_context
+    pub fn count_tokens(&self) -> usize {
+        Tokenizer::for_model(&self.model).count_context(self)
+    }
+
     /// Pushes a message in the chat context
     /// as a Message. This is an internal function used by other functions.
     /// It is recommended to use ChatGPT.push_message()
@@ -34,8 +116,34 @@ impl ChatContext {
     /// Sets the messages in the chat context
     /// as a vector of Message.
     /// This is an internal function used by other functions.
+    /// The system prompt is kept at the start, unless the messages start with their own
     pub fn set_messages(&mut self, messages: Vec<Message>) {
-        self.messages = messages;
+        let system_prompt = match messages.first() {
+            Some(first) if is_system_prompt(first) => None,
+            _ => self.system_prompt_message().cloned(),
+        };
+        self.messages = system_prompt.into_iter().chain(messages).collect();
+    }
+
+    /// Returns the system prompt: the first message, if it is a system message without a name
+    pub fn system_prompt(&self) -> Option<&str> {
+        self.system_prompt_message()
+            .and_then(|m| m.content.as_deref())
+    }
+
+    /// Sets the system prompt, replacing the one set before.
+    /// It is kept as the first message of the context
+    pub fn set_system_prompt(&mut self, content: String) {
+        let message = Message::system(content);
+        if self.system_prompt_message().is_some() {
+            self.messages[0] = message;
+        } else {
+            self.messages.insert(0, message);
+        }
+    }
+
+    fn system_prompt_message(&self) -> Option<&Message> {
+        self.messages.first().filter(|m| is_system_prompt(m))
     }
 
     /// Pushes a function in the chat context
@@ -53,9 +161,36 @@ impl ChatContext {
         self.functions = functions;
     }
 
-    /// Sets the last message sent by the user or the bot
-    /// as a string. This is an internal function used by other functions.
-    pub fn set_function_call(&mut self, function_call: String) {
+    /// Sets the sampling parameters of the chat context,
+    /// replacing the ones set before.
+    pub fn set_parameters(&mut self, parameters: CompletionParameters) {
+        self.parameters = parameters;
+    }
+
+    /// Pushes a tool in the chat context
+    /// as a Tool.
+    /// This is an internal function used by other functions.
+    /// It is recommended to use ChatGPT.push_tool()
+    pub fn push_tool(&mut self, tool: Tool) {
+        self.tools.push(tool);
+    }
+
+    /// Sets the tools in the chat context
+    /// as a vector of Tool.
+    /// This is an internal function used by other functions.
+    pub fn set_tools(&mut self, tools: Vec<Tool>) {
+        self.tools = tools;
+    }
+
+    /// Sets how the model calls the tools of the context
+    /// as a ToolChoice. This is an internal function used by other functions.
+    pub fn set_tool_choice(&mut self, tool_choice: ToolChoice) {
+        self.tool_choice = Some(tool_choice);
+    }
+
+    /// Sets how the model calls the functions of the context
+    /// as a FunctionCallMode. This is an internal function used by other functions.
+    pub fn set_function_call(&mut self, function_call: FunctionCallMode) {
         self.function_call = Some(function_call);
     }
 
@@ -64,13 +199,7 @@ impl ChatContext {
     /// It is recommended to use ChatGPT.last_content()
     pub fn last_content(&self) -> Option<String> {
         match self.messages.last() {
-            Some(message) => {
-                if let Some(c) = message.content.clone() {
-                    Some(c)
-                } else {
-                    None
-                }
-            }
+            Some(message) => message.content.clone(),
             None => None,
         }
     }
@@ -91,38 +220,142 @@ impl ChatContext {
             None => None,
         }
     }
+
+    /// Returns the tool calls of the last message in the chat context.
+    /// This is an internal function used by other functions.
+    /// It is recommended to use ChatGPT.last_tool_calls()
+    pub fn last_tool_calls(&self) -> Option<Vec<ToolCall>> {
+        match self.messages.last() {
+            Some(message) => message.tool_calls.clone(),
+            None => None,
+        }
+    }
+
+    /// Returns the specification of the function with this name,
+    /// looking in the functions and in the tools of the context
+    pub fn function_specification(&self, name: &str) -> Option<&FunctionSpecification> {
+        self.functions
+            .iter()
+            .chain(self.tools.iter().map(|tool| &tool.function))
+            .find(|function| function.name == name)
+    }
+
+    /// Checks the arguments of a function call against the specification of the function in the context
+    /// It returns every violation found, the call is valid if it is empty
+    /// # Arguments
+    /// * `function_call` - The function call, as sent by the model
+    pub fn validate_function_call(&self, function_call: &FunctionCall) -> Vec<Violation> {
+        match self.function_specification(&function_call.name) {
+            Some(specification) => validate_arguments(specification, &function_call.arguments),
+            None => vec![Violation {
+                path: String::new(),
+                kind: ViolationKind::UnknownFunction(function_call.name.clone()),
+            }],
+        }
+    }
 }
 
-// Print valid JSON for ChatContext, no commas if last field
-impl fmt::Display for ChatContext {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{{\"model\":\"{}\"", self.model)?;
-        if !self.messages.is_empty() {
-            write!(f, ",\"messages\":[")?;
-            for (i, message) in self.messages.iter().enumerate() {
-                write!(f, "{}", message)?;
-                if i < self.messages.len() - 1 {
-                    write!(f, ",")?;
-                }
-            }
-            write!(f, "]")?;
+impl From<&str> for FunctionCallMode {
+    fn from(value: &str) -> Self {
+        match value {
+            "auto" => FunctionCallMode::Auto,
+            "none" => FunctionCallMode::None,
+            name => FunctionCallMode::Named(name.to_string()),
         }
-        if self.functions.len() > 0 {
-            write!(f, ",\"functions\":[")?;
-            for (i, function) in self.functions.iter().enumerate() {
-                write!(f, "{}", function)?;
-                if i < self.functions.len() - 1 {
-                    write!(f, ",")?;
-                }
+    }
+}
+
+// "auto" and "none" are sent as strings, a named function as {"name": "function_name"}
+impl Serialize for FunctionCallMode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Named<'a> {
+            name: &'a str,
+        }
+        match self {
+            FunctionCallMode::Auto => serializer.serialize_str("auto"),
+            FunctionCallMode::None => serializer.serialize_str("none"),
+            FunctionCallMode::Named(name) => Named { name }.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for FunctionCallMode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Mode(String),
+            Named { name: String },
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Mode(mode) if mode == "auto" => Ok(FunctionCallMode::Auto),
+            Repr::Mode(mode) if mode == "none" => Ok(FunctionCallMode::None),
+            Repr::Mode(mode) => Err(de::Error::invalid_value(
+                de::Unexpected::Str(&mode),
+                &"\"auto\", \"none\" or {\"name\": ...}",
+            )),
+            Repr::Named { name } => Ok(FunctionCallMode::Named(name)),
+        }
+    }
+}
+
+// "auto", "none" and "required" are sent as strings,
+// a function as {"type": "function", "function": {"name": "function_name"}}
+impl Serialize for ToolChoice {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Name<'a> {
+            name: &'a str,
+        }
+        #[derive(Serialize)]
+        struct Function<'a> {
+            #[serde(rename = "type")]
+            type_: &'a str,
+            function: Name<'a>,
+        }
+        match self {
+            ToolChoice::Auto => serializer.serialize_str("auto"),
+            ToolChoice::None => serializer.serialize_str("none"),
+            ToolChoice::Required => serializer.serialize_str("required"),
+            ToolChoice::Function(name) => Function {
+                type_: "function",
+                function: Name { name },
             }
-            write!(f, "]")?;
+            .serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ToolChoice {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Name {
+            name: String,
         }
-        if let Some(function_call) = &self.function_call {
-            write!(f, ",\"function_call\":\"{}\"", function_call)?;
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Mode(String),
+            Function { function: Name },
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Mode(mode) => match mode.as_str() {
+                "auto" => Ok(ToolChoice::Auto),
+                "none" => Ok(ToolChoice::None),
+                "required" => Ok(ToolChoice::Required),
+                _ => Err(de::Error::invalid_value(
+                    de::Unexpected::Str(&mode),
+                    &"\"auto\", \"none\", \"required\" or {\"type\": \"function\", ...}",
+                )),
+            },
+            Repr::Function { function } => Ok(ToolChoice::Function(function.name)),
         }
-        write!(f, "}}")
     }
 }
+
+display_as_json!(ChatContext, FunctionCallMode, ToolChoice);
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
@@ -130,27 +363,27 @@ mod tests {
     use super::*;
     use crate::{
         function_specification::{Parameters, Property},
-        message::MessageBuilder,
+        message::{MessageBuilder, Role},
     };
 
     #[test]
     fn test_display_for_chat_context() {
         let mut chat_context = ChatContext::new("test_model".to_string());
         let message = MessageBuilder::new()
-            .role("role".to_string())
+            .role(Role::User)
             .content("Hello".to_string())
             .build()
             .expect("Failed to build message");
         chat_context.push_message(message);
         let message = MessageBuilder::new()
-            .role("bot".to_string())
+            .role(Role::Assistant)
             .content("Hi".to_string())
             .build()
             .expect("Failed to build message");
         chat_context.push_message(message);
         assert_eq!(
             chat_context.to_string(),
-            "{\"model\":\"test_model\",\"messages\":[{\"role\":\"role\",\"content\":\"Hello\"},{\"role\":\"bot\",\"content\":\"Hi\"}]}"
+            "{\"model\":\"test_model\",\"messages\":[{\"role\":\"user\",\"content\":\"Hello\"},{\"role\":\"assistant\",\"content\":\"Hi\"}]}"
         );
     }
 
@@ -166,6 +399,7 @@ mod tests {
                 type_: "string".to_string(),
                 description: Some("a dummy string".to_string()),
                 enum_: None,
+                ..Property::default()
             },
         );
         let function = FunctionSpecification {
@@ -181,7 +415,7 @@ mod tests {
 
         // Add a message to the chat context
         let message = MessageBuilder::new()
-            .role("test".to_string())
+            .role(Role::User)
             .content("hi".to_string())
             .name("test_function".to_string())
             .build()
@@ -191,10 +425,218 @@ mod tests {
         // Print the chat context, with the model, the messages, the functions, and the function_call
         assert_eq!(
             chat_context.to_string(),
-            "{\"model\":\"test_model\",\"messages\":[{\"role\":\"test\",\"content\":\"hi\",\"name\":\"test_function\"}],\"functions\":[{\"name\":\"test_function\",\"description\":\"a dummy function to test the chat context\",\"parameters\":{\"type\":\"object\",\"properties\":{\"location\":{\"type\":\"string\",\"description\":\"a dummy string\"}},\"required\":[\"location\"]}}]}"
+            "{\"model\":\"test_model\",\"messages\":[{\"role\":\"user\",\"content\":\"hi\",\"name\":\"test_function\"}],\"functions\":[{\"name\":\"test_function\",\"description\":\"a dummy function to test the chat context\",\"parameters\":{\"type\":\"object\",\"properties\":{\"location\":{\"type\":\"string\",\"description\":\"a dummy string\"}},\"required\":[\"location\"]}}]}"
+        );
+    }
+
+    #[test]
+    fn test_display_chat_context_round_trip() {
+        let mut chat_context = ChatContext::new("test_model".to_string());
+        chat_context.push_message(Message::new_user_message(
+            "What is \"this\"?\nA \\ backslash".to_string(),
+        ));
+        chat_context.push_function(
+            serde_json::from_str(
+                r#"{"name":"quote","description":"Says \"hi\"\nto you","parameters":{"type":"object","properties":{"the \"key\"":{"type":"string","description":"a\tb","enum":["\"a\"","b\\c"]}},"required":["the \"key\""]}}"#,
+            )
+            .expect("Invalid specification"),
+        );
+        chat_context.set_parameters(CompletionParameters::new().stop(vec!["\"END\"".to_string()]));
+
+        let parsed: ChatContext =
+            serde_json::from_str(&chat_context.to_string()).expect("Invalid JSON");
+        assert_eq!(parsed.messages, chat_context.messages);
+        assert_eq!(parsed.functions, chat_context.functions);
+        assert_eq!(parsed.parameters, chat_context.parameters);
+        assert_eq!(
+            parsed.messages[0].content,
+            Some("What is \"this\"?\nA \\ backslash".to_string())
+        );
+    }
+
+    #[test]
+    fn test_display_chat_context_with_stream() {
+        let mut chat_context = ChatContext::new("test_model".to_string());
+        chat_context.stream = Some(true);
+        assert_eq!(
+            chat_context.to_string(),
+            "{\"model\":\"test_model\",\"stream\":true}"
+        );
+    }
+
+    #[test]
+    fn test_display_chat_context_with_parameters() {
+        let mut chat_context = ChatContext::new("test_model".to_string());
+        chat_context.set_parameters(
+            CompletionParameters::new()
+                .temperature(0.2)
+                .top_p(0.9)
+                .max_tokens(100)
+                .n(2)
+                .stop(vec!["\"END\"".to_string(), "\n".to_string()])
+                .presence_penalty(0.5)
+                .frequency_penalty(-0.5)
+                .logit_bias([(50256, -100), (1234, 5)].into_iter().collect())
+                .user("user-1".to_string())
+                .seed(42),
+        );
+        let expected = "{\"model\":\"test_model\",\"temperature\":0.2,\"top_p\":0.9,\"max_tokens\":100,\"n\":2,\"stop\":[\"\\\"END\\\"\",\"\\n\"],\"presence_penalty\":0.5,\"frequency_penalty\":-0.5,\"logit_bias\":{\"1234\":5,\"50256\":-100},\"user\":\"user-1\",\"seed\":42}";
+        assert_eq!(chat_context.to_string(), expected);
+
+        // The serde representation has the same fields
+        let json: serde_json::Value =
+            serde_json::from_str(&chat_context.to_string()).expect("Invalid JSON");
+        assert_eq!(json["logit_bias"]["50256"], -100);
+        let serialized = serde_json::to_value(&chat_context).expect("Failed to serialize");
+        assert_eq!(serialized["seed"], 42);
+        assert_eq!(serialized.get("temperature"), json.get("temperature"));
+    }
+
+    #[test]
+    fn test_display_chat_context_without_parameters() {
+        let chat_context = ChatContext::new("test_model".to_string());
+        assert_eq!(chat_context.to_string(), "{\"model\":\"test_model\"}");
+    }
+
+    #[test]
+    fn test_display_chat_context_with_function_call_mode() {
+        let mut chat_context = ChatContext::new("test_model".to_string());
+        chat_context.set_function_call(FunctionCallMode::Auto);
+        assert_eq!(
+            chat_context.to_string(),
+            "{\"model\":\"test_model\",\"function_call\":\"auto\"}"
+        );
+        chat_context.set_function_call(FunctionCallMode::None);
+        assert_eq!(
+            chat_context.to_string(),
+            "{\"model\":\"test_model\",\"function_call\":\"none\"}"
+        );
+        chat_context.set_function_call(FunctionCallMode::Named("get_current_weather".to_string()));
+        assert_eq!(
+            chat_context.to_string(),
+            "{\"model\":\"test_model\",\"function_call\":{\"name\":\"get_current_weather\"}}"
+        );
+    }
+
+    #[test]
+    fn test_serde_function_call_mode() {
+        for (mode, json) in [
+            (FunctionCallMode::Auto, "\"auto\""),
+            (FunctionCallMode::None, "\"none\""),
+            (
+                FunctionCallMode::Named("get_current_weather".to_string()),
+                "{\"name\":\"get_current_weather\"}",
+            ),
+        ] {
+            assert_eq!(
+                serde_json::to_string(&mode).expect("Failed to serialize"),
+                json
+            );
+            assert_eq!(
+                serde_json::from_str::<FunctionCallMode>(json).expect("Failed to parse"),
+                mode
+            );
+            assert_eq!(mode.to_string(), json);
+        }
+        assert!(serde_json::from_str::<FunctionCallMode>("\"sometimes\"").is_err());
+        assert_eq!(
+            FunctionCallMode::from("get_current_weather"),
+            FunctionCallMode::Named("get_current_weather".to_string())
+        );
+    }
+
+    #[test]
+    fn test_display_chat_context_with_tools() {
+        let mut chat_context = ChatContext::new("test_model".to_string());
+        chat_context.push_tool(Tool::function(FunctionSpecification::new(
+            "get_current_weather".to_string(),
+            Some("Get the current weather".to_string()),
+            None,
+        )));
+        chat_context.set_tool_choice(ToolChoice::Function("get_current_weather".to_string()));
+        assert_eq!(
+            chat_context.to_string(),
+            "{\"model\":\"test_model\",\"tools\":[{\"type\":\"function\",\"function\":{\"name\":\"get_current_weather\",\"description\":\"Get the current weather\",\"parameters\":{\"type\":\"object\",\"properties\":{}}}}],\"tool_choice\":{\"type\":\"function\",\"function\":{\"name\":\"get_current_weather\"}}}"
         );
     }
 
+    #[test]
+    fn test_serde_tool_choice() {
+        for (choice, json) in [
+            (ToolChoice::Auto, "\"auto\""),
+            (ToolChoice::None, "\"none\""),
+            (ToolChoice::Required, "\"required\""),
+            (
+                ToolChoice::Function("get_current_weather".to_string()),
+                "{\"type\":\"function\",\"function\":{\"name\":\"get_current_weather\"}}",
+            ),
+        ] {
+            assert_eq!(
+                serde_json::to_string(&choice).expect("Failed to serialize"),
+                json
+            );
+            assert_eq!(
+                serde_json::from_str::<ToolChoice>(json).expect("Failed to parse"),
+                choice
+            );
+            assert_eq!(choice.to_string(), json);
+        }
+    }
+
+    #[test]
+    fn test_last_tool_calls() {
+        use crate::message::FunctionCall;
+
+        let mut chat_context = ChatContext::new("model".to_string());
+        assert_eq!(chat_context.last_tool_calls(), None);
+
+        let tool_call = ToolCall::new(
+            "call_1".to_string(),
+            FunctionCall {
+                name: "function".to_string(),
+                arguments: "{}".to_string(),
+            },
+        );
+        let message = MessageBuilder::new()
+            .role(Role::Assistant)
+            .tool_calls(vec![tool_call.clone()])
+            .build()
+            .expect("Failed to build message");
+        chat_context.push_message(message);
+        assert_eq!(chat_context.last_tool_calls(), Some(vec![tool_call]));
+    }
+
+    #[test]
+    fn test_system_prompt() {
+        let mut chat_context = ChatContext::new("model".to_string());
+        assert_eq!(chat_context.system_prompt(), None);
+        chat_context.push_message(Message::user("Hi".to_string()));
+        chat_context.set_system_prompt("Be brief".to_string());
+        chat_context.set_system_prompt("Be very brief".to_string());
+        assert_eq!(chat_context.system_prompt(), Some("Be very brief"));
+        assert_eq!(chat_context.messages.len(), 2);
+
+        // set_messages keeps it, unless the messages bring their own
+        chat_context.set_messages(vec![Message::user("Bye".to_string())]);
+        assert_eq!(chat_context.messages.len(), 2);
+        assert_eq!(chat_context.system_prompt(), Some("Be very brief"));
+        chat_context.set_messages(vec![
+            Message::system("Be kind".to_string()),
+            Message::user("Bye".to_string()),
+        ]);
+        assert_eq!(chat_context.messages.len(), 2);
+        assert_eq!(chat_context.system_prompt(), Some("Be kind"));
+
+        // A summary is not the system prompt
+        let mut summary = Message::system("The user said hi".to_string());
+        summary.set_name("conversation_summary".to_string());
+        chat_context.set_messages(vec![summary]);
+        chat_context.messages.remove(0);
+        assert_eq!(chat_context.system_prompt(), None);
+        chat_context.set_system_prompt("Be brief".to_string());
+        assert_eq!(chat_context.messages.len(), 2);
+    }
+
     #[test]
     fn test_last_content() {
         let mut chat_context = ChatContext::new("model".to_string());
@@ -203,17 +645,14 @@ mod tests {
         assert_eq!(chat_context.last_content(), None);
 
         // Test with a message with no content
-        let message = MessageBuilder::new()
-            .role("role".to_string())
-            .name("name".to_string())
-            .build()
-            .expect("Failed to build message");
+        let mut message = Message::new(Role::Assistant);
+        message.set_name("name".to_string());
         chat_context.push_message(message);
         assert_eq!(chat_context.last_content(), None);
 
         // Test with a message with content
         let message = MessageBuilder::new()
-            .role("role".to_string())
+            .role(Role::User)
             .content("content".to_string())
             .build()
             .expect("Failed to build message");
@@ -229,18 +668,15 @@ mod tests {
         assert_eq!(chat_context.last_content(), None);
 
         // Test with a message with no function call
-        let message = MessageBuilder::new()
-            .role("role".to_string())
-            .name("name".to_string())
-            .build()
-            .expect("Failed to build message");
+        let mut message = Message::new(Role::Assistant);
+        message.set_name("name".to_string());
         chat_context.push_message(message);
         assert_eq!(chat_context.last_content(), None);
 
         // Test with a message with function call
         use crate::message::FunctionCall;
         let message = MessageBuilder::new()
-            .role("role".to_string())
+            .role(Role::Assistant)
             .function_call(FunctionCall {
                 name: "function".to_string(),
                 arguments: "arguments".to_string(),
@@ -253,4 +689,36 @@ mod tests {
             Some(("function".to_string(), "arguments".to_string()))
         );
     }
+
+    #[test]
+    fn test_validate_function_call() {
+        let mut chat_context = ChatContext::new("test_model".to_string());
+        chat_context.push_tool(Tool::function(
+            serde_json::from_str(
+                r#"{"name":"get_current_weather","parameters":{"type":"object","properties":{"unit":{"type":"string","enum":["celsius","fahrenheit"]}},"required":["unit"]}}"#,
+            )
+            .expect("Invalid specification"),
+        ));
+
+        let call = |name: &str, arguments: &str| FunctionCall {
+            name: name.to_string(),
+            arguments: arguments.to_string(),
+        };
+        assert!(chat_context
+            .validate_function_call(&call("get_current_weather", r#"{"unit":"celsius"}"#))
+            .is_empty());
+        assert_eq!(
+            chat_context
+                .validate_function_call(&call("get_current_weather", r#"{"unit":"kelvin"}"#))[0]
+                .to_string(),
+            "`unit` must be one of \"celsius\", \"fahrenheit\""
+        );
+        assert_eq!(
+            chat_context.validate_function_call(&call("book_flight", "{}")),
+            vec![Violation {
+                path: String::new(),
+                kind: ViolationKind::UnknownFunction("book_flight".to_string()),
+            }]
+        );
+    }
 }
@@ -0,0 +1,157 @@
+use serde::de::DeserializeOwned;
+
+use crate::{
+    error::{Error, Result},
+    function_specification::{FunctionSpecification, Property},
+    message::FunctionCall,
+};
+
+/// A Rust type that describes a function the model can call, with its arguments as fields
+///
+/// The specification is generated from the type with `#[derive(ChatFunction)]`
+/// (feature `derive`), so it can't drift out of sync with the type that receives the arguments.
+/// Doc comments become the descriptions, `Option` fields are not required,
+/// and enums deriving ChatParameter are sent with their variants as `enum`.
+///
+/// # Example
+/// ```
+/// # #[cfg(feature = "derive")]
+/// # fn main() {
+/// use chatgpt_functions::{ChatFunction, ChatParameter, message::FunctionCall};
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize, ChatParameter)]
+/// #[serde(rename_all = "lowercase")]
+/// enum Unit {
+///     Celsius,
+///     Fahrenheit,
+/// }
+///
+/// /// Get the current weather in a given location
+/// #[derive(Deserialize, ChatFunction)]
+/// struct GetCurrentWeather {
+///     /// The city and state, e.g. San Francisco, CA
+///     location: String,
+///     unit: Option<Unit>,
+/// }
+///
+/// let specification = GetCurrentWeather::function_specification();
+/// assert_eq!(specification.name, "get_current_weather");
+/// assert_eq!(specification.parameters.unwrap().required, vec!["location".to_string()]);
+///
+/// let call = GetCurrentWeather::from_function_call(&FunctionCall {
+///     name: "get_current_weather".to_string(),
+///     arguments: "{\"location\": \"Madrid\", \"unit\": \"celsius\"}".to_string(),
+/// })
+/// .unwrap();
+/// assert_eq!(call.location, "Madrid");
+/// # }
+/// # #[cfg(not(feature = "derive"))]
+/// # fn main() {}
+/// ```
+pub trait ChatFunction: DeserializeOwned {
+    /// The specification of the function, to be sent to the model
+    fn function_specification() -> FunctionSpecification;
+
+    /// Deserializes the arguments of a call to this function
+    /// # Errors
+    /// It returns Error::Deserialize if the arguments don't match the type
+    fn from_function_call(function_call: &FunctionCall) -> Result<Self> {
+        Self::from_arguments(&function_call.arguments)
+    }
+
+    /// Deserializes the arguments of a call to this function, as sent by the model
+    /// # Errors
+    /// It returns Error::Deserialize if the arguments don't match the type
+    fn from_arguments(arguments: &str) -> Result<Self> {
+        serde_json::from_str(arguments).map_err(|source| Error::Deserialize {
+            source,
+            body: arguments.to_string(),
+        })
+    }
+}
+
+/// A Rust type that can be a parameter of a ChatFunction
+///
+/// It is implemented for the primitive types, String, Vec and Option.
+/// Structs, sent as nested objects, and enums of unit variants can derive it
+/// with `#[derive(ChatParameter)]` (feature `derive`)
+pub trait ChatParameter {
+    /// The property that describes the parameter in the specification
+    fn property() -> Property;
+
+    /// Whether the model must always send the parameter
+    fn is_required() -> bool {
+        true
+    }
+}
+
+macro_rules! impl_chat_parameter {
+    ($type_:literal: $($t:ty),*) => {
+        $(
+            impl ChatParameter for $t {
+                fn property() -> Property {
+                    Property::new($type_.to_string())
+                }
+            }
+        )*
+    };
+}
+
+impl_chat_parameter!("string": String, char);
+impl_chat_parameter!("boolean": bool);
+impl_chat_parameter!("integer": i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+impl_chat_parameter!("number": f32, f64);
+
+impl<T: ChatParameter> ChatParameter for Vec<T> {
+    fn property() -> Property {
+        Property {
+            items: Some(Box::new(T::property())),
+            ..Property::new("array".to_string())
+        }
+    }
+}
+
+impl<T: ChatParameter> ChatParameter for Option<T> {
+    fn property() -> Property {
+        T::property()
+    }
+
+    fn is_required() -> bool {
+        false
+    }
+}
+
+impl<T: ChatParameter> ChatParameter for Box<T> {
+    fn property() -> Property {
+        T::property()
+    }
+
+    fn is_required() -> bool {
+        T::is_required()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_primitive_properties() {
+        assert_eq!(String::property().type_, "string");
+        assert_eq!(u8::property().type_, "integer");
+        assert_eq!(f32::property().type_, "number");
+        assert_eq!(bool::property().type_, "boolean");
+        assert_eq!(
+            Vec::<u64>::property(),
+            Property {
+                items: Some(Box::new(Property::new("integer".to_string()))),
+                ..Property::new("array".to_string())
+            }
+        );
+        assert_eq!(Option::<i64>::property().type_, "integer");
+        assert!(i64::is_required());
+        assert!(!Option::<i64>::is_required());
+        assert!(!Box::<Option<i64>>::is_required());
+    }
+}
@@ -1,13 +1,31 @@
-use anyhow::{Context, Result};
+use std::{collections::BTreeMap, sync::Arc};
+
+use futures_util::{future::try_join_all, StreamExt};
 use uuid::Uuid;
 
 use crate::{
-    chat_context::ChatContext, chat_response::ChatResponse,
-    function_specification::FunctionSpecification, message::Message,
+    chat_context::{ChatContext, FunctionCallMode, StreamOptions, ToolChoice},
+    chat_response::{ChatResponse, Choice, ChoiceSelector, Usage},
+    chat_stream::{ChatResponseChunk, ChatStream},
+    compaction::Compaction,
+    completion_parameters::CompletionParameters,
+    conversation_store::ConversationStore,
+    error::{Error, RateLimit, Result},
+    function_registry::FunctionRegistry,
+    function_specification::{FunctionSpecification, Tool},
+    message::{FunctionCall, Message, ToolCall},
+    pricing::PriceTable,
+    retry::RetryPolicy,
+    system_prompt::SystemPrompt,
+    transport::{ReqwestTransport, Transport, TransportRequest, TransportResponse},
+    truncation::TruncationStrategy,
+    validation::{correction_message, ValidationMode},
 };
 
 const DEFAULT_MODEL: &str = "gpt-3.5-turbo-0613";
-const URL: &str = "https://api.openai.com/v1/chat/completions";
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+const DEFAULT_PATH: &str = "/chat/completions";
+const DEFAULT_MAX_FUNCTION_ITERATIONS: u32 = 10;
 
 // Builder for ChatGPT
 pub struct ChatGPTBuilder {
@@ -15,6 +33,27 @@ pub struct ChatGPTBuilder {
     openai_api_token: Option<String>,
     session_id: Option<String>,
     chat_context: Option<ChatContext>,
+    base_url: Option<String>,
+    path: Option<String>,
+    retry_policy: Option<RetryPolicy>,
+    max_function_iterations: Option<u32>,
+    validation_mode: ValidationMode,
+    truncation_strategies: Vec<Box<dyn TruncationStrategy>>,
+    compaction: Option<Compaction>,
+    conversation_store: Option<Arc<dyn ConversationStore>>,
+    resume_session: bool,
+    system_prompt: Option<SystemPrompt>,
+    transport: Option<Arc<dyn Transport>>,
+    price_table: PriceTable,
+    budget: Option<f64>,
+    choice_selector: Option<ChoiceSelector>,
+    parameters: CompletionParameters,
+}
+
+impl Default for ChatGPTBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ChatGPTBuilder {
@@ -24,6 +63,21 @@ impl ChatGPTBuilder {
             openai_api_token: None,
             session_id: None,
             chat_context: None,
+            base_url: None,
+            path: None,
+            retry_policy: None,
+            max_function_iterations: None,
+            validation_mode: ValidationMode::Off,
+            truncation_strategies: Vec::new(),
+            compaction: None,
+            conversation_store: None,
+            resume_session: false,
+            system_prompt: None,
+            transport: None,
+            price_table: PriceTable::new(),
+            budget: None,
+            choice_selector: None,
+            parameters: CompletionParameters::default(),
         }
     }
 
@@ -42,36 +96,249 @@ impl ChatGPTBuilder {
         self
     }
 
+    /// Resumes the conversation of the session saved in the conversation store.
+    /// If the store has no conversation for the session, a new one is started with that session_id.
+    /// The context loaded takes the place of the one set with chat_context
+    pub fn resume_session(mut self, session_id: String) -> Self {
+        self.session_id = Some(session_id);
+        self.resume_session = true;
+        self
+    }
+
+    /// Sets the system prompt, the instructions of the conversation.
+    /// It can have `{{variable}}` placeholders, see system_prompt_variable.
+    /// It replaces the system prompt of the context set with chat_context or resumed from the store
+    pub fn system_prompt(mut self, template: String) -> Self {
+        self.system_prompt
+            .get_or_insert_with(SystemPrompt::default)
+            .template = template;
+        self
+    }
+
+    /// Sets the value of a `{{variable}}` placeholder of the system prompt
+    pub fn system_prompt_variable(mut self, name: String, value: String) -> Self {
+        self.system_prompt
+            .get_or_insert_with(SystemPrompt::default)
+            .set_variable(name, value);
+        self
+    }
+
     pub fn chat_context(mut self, chat_context: ChatContext) -> Self {
         self.chat_context = Some(chat_context);
         self
     }
 
+    /// The base URL of the API, e.g. `http://localhost:8000/v1` for an OpenAI compatible server.
+    /// Optional. If not provided, it will use the OpenAI API
+    pub fn base_url(mut self, base_url: String) -> Self {
+        self.base_url = Some(base_url);
+        self
+    }
+
+    /// The transport that sends the requests, e.g. a ScriptedTransport in the tests.
+    /// Optional. If not provided, it will send them over HTTP with ReqwestTransport
+    pub fn transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// The path of the chat completions endpoint, appended to the base URL.
+    /// Optional. If not provided, it will use `/chat/completions`
+    pub fn path(mut self, path: String) -> Self {
+        self.path = Some(path);
+        self
+    }
+
+    /// The policy used to retry the requests that fail with a transient error.
+    /// Optional. If not provided, the requests are not retried
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    /// The prices of the models, to compute the cost of the completions.
+    /// Optional. If not provided, the cost of the session is not computed
+    pub fn price_table(mut self, price_table: PriceTable) -> Self {
+        self.price_table = price_table;
+        self
+    }
+
+    /// The maximum cost in dollars of the session. A request that could exceed it fails
+    /// with Error::BudgetExceeded before it is sent, see ChatGPT::set_budget.
+    /// Optional. If not provided, there is no limit
+    pub fn budget(mut self, budget: f64) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Chooses which choice of the responses is committed to the context by the managed completions,
+    /// when several are requested with `n`. It receives the choices and returns the index of one of them.
+    /// Optional. If not provided, it will commit the first choice
+    pub fn choice_selector<F>(mut self, choice_selector: F) -> Self
+    where
+        F: Fn(&[Choice]) -> u64 + Send + Sync + 'static,
+    {
+        self.choice_selector = Some(Box::new(choice_selector));
+        self
+    }
+
+    /// The maximum number of rounds of function calls that run_until_answer executes
+    /// before giving up with Error::MaxIterationsReached.
+    /// Optional. If not provided, it will use 10
+    pub fn max_function_iterations(mut self, max_function_iterations: u32) -> Self {
+        self.max_function_iterations = Some(max_function_iterations);
+        self
+    }
+
+    /// How run_until_answer handles the function calls with arguments that don't match
+    /// the specifications in the context.
+    /// Optional. If not provided, the arguments are not validated
+    pub fn validation_mode(mut self, validation_mode: ValidationMode) -> Self {
+        self.validation_mode = validation_mode;
+        self
+    }
+
+    /// Adds a strategy to remove messages from the context before every request,
+    /// e.g. to keep long conversations within the context window of the model.
+    /// The strategies are applied in the order they are added. By default no message is removed
+    pub fn truncation_strategy(mut self, strategy: impl TruncationStrategy + 'static) -> Self {
+        self.truncation_strategies.push(Box::new(strategy));
+        self
+    }
+
+    /// Summarizes the older messages of the context before a request when it grows too long,
+    /// see Compaction. It is applied before the truncation strategies. By default it is off
+    pub fn compaction(mut self, compaction: Compaction) -> Self {
+        self.compaction = Some(compaction);
+        self
+    }
+
+    /// Saves the context in the store after every turn of the managed completions,
+    /// see ConversationStore. By default the conversations are not saved
+    pub fn conversation_store(mut self, conversation_store: Arc<dyn ConversationStore>) -> Self {
+        self.conversation_store = Some(conversation_store);
+        self
+    }
+
+    /// Sets all the sampling parameters at once.
+    /// The parameters are not sent to the API unless they are set
+    pub fn parameters(mut self, parameters: CompletionParameters) -> Self {
+        self.parameters = parameters;
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f64) -> Self {
+        self.parameters.temperature = Some(temperature);
+        self
+    }
+
+    pub fn top_p(mut self, top_p: f64) -> Self {
+        self.parameters.top_p = Some(top_p);
+        self
+    }
+
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.parameters.max_tokens = Some(max_tokens);
+        self
+    }
+
+    pub fn n(mut self, n: u32) -> Self {
+        self.parameters.n = Some(n);
+        self
+    }
+
+    pub fn stop(mut self, stop: Vec<String>) -> Self {
+        self.parameters.stop = Some(stop);
+        self
+    }
+
+    pub fn presence_penalty(mut self, presence_penalty: f64) -> Self {
+        self.parameters.presence_penalty = Some(presence_penalty);
+        self
+    }
+
+    pub fn frequency_penalty(mut self, frequency_penalty: f64) -> Self {
+        self.parameters.frequency_penalty = Some(frequency_penalty);
+        self
+    }
+
+    pub fn logit_bias(mut self, logit_bias: BTreeMap<u32, i32>) -> Self {
+        self.parameters.logit_bias = Some(logit_bias);
+        self
+    }
+
+    pub fn user(mut self, user: String) -> Self {
+        self.parameters.user = Some(user);
+        self
+    }
+
+    pub fn seed(mut self, seed: i64) -> Self {
+        self.parameters.seed = Some(seed);
+        self
+    }
+
     pub fn build(self) -> Result<ChatGPT> {
-        let client = reqwest::Client::new();
         let model = if let Some(m) = self.model {
             m
         } else {
             DEFAULT_MODEL.to_string()
         };
-        let openai_api_token = self
-            .openai_api_token
-            .context("OpenAI API token is missing")?;
+        let openai_api_token = self.openai_api_token.ok_or_else(|| {
+            Error::MissingConfiguration("OpenAI API token is missing".to_string())
+        })?;
         let session_id = if let Some(s) = self.session_id {
             s
         } else {
             Uuid::new_v4().to_string()
         };
-        let chat_context = if let Some(c) = self.chat_context {
+        let resumed = match (&self.conversation_store, self.resume_session) {
+            (Some(store), true) => store.load(&session_id)?,
+            (None, true) => {
+                return Err(Error::MissingConfiguration(
+                    "A conversation store is needed to resume a session".to_string(),
+                ))
+            }
+            _ => None,
+        };
+        let mut chat_context = if let Some(c) = resumed.or(self.chat_context) {
             c
         } else {
             let mut c = ChatContext::new(model.clone());
             c.model = model.clone();
             c
         };
+        // The parameters set in the builder take precedence over the ones in the context
+        chat_context.parameters = chat_context.parameters.merge(&self.parameters);
+        if let Some(system_prompt) = &self.system_prompt {
+            chat_context.set_system_prompt(system_prompt.render()?);
+        }
+        let url = endpoint_url(
+            self.base_url.as_deref().unwrap_or(DEFAULT_BASE_URL),
+            self.path.as_deref().unwrap_or(DEFAULT_PATH),
+        );
+
+        let retry_policy = self.retry_policy.unwrap_or_else(RetryPolicy::none);
+        let max_function_iterations = self
+            .max_function_iterations
+            .unwrap_or(DEFAULT_MAX_FUNCTION_ITERATIONS);
+
+        let transport = self
+            .transport
+            .unwrap_or_else(|| Arc::new(ReqwestTransport::default()));
 
         Ok(ChatGPT {
-            client,
+            transport,
+            url,
+            retry_policy,
+            max_function_iterations,
+            validation_mode: self.validation_mode,
+            truncation_strategies: self.truncation_strategies,
+            compaction: self.compaction,
+            conversation_store: self.conversation_store,
+            system_prompt: self.system_prompt,
+            price_table: self.price_table,
+            budget: self.budget,
+            choice_selector: self.choice_selector,
             model,
             openai_api_token,
             session_id,
@@ -82,7 +349,18 @@ impl ChatGPTBuilder {
 
 /// The ChatGPT object
 pub struct ChatGPT {
-    client: reqwest::Client,
+    transport: Arc<dyn Transport>,
+    url: String,
+    retry_policy: RetryPolicy,
+    max_function_iterations: u32,
+    validation_mode: ValidationMode,
+    truncation_strategies: Vec<Box<dyn TruncationStrategy>>,
+    compaction: Option<Compaction>,
+    conversation_store: Option<Arc<dyn ConversationStore>>,
+    system_prompt: Option<SystemPrompt>,
+    price_table: PriceTable,
+    budget: Option<f64>,
+    choice_selector: Option<ChoiceSelector>,
     pub model: String,
     openai_api_token: String,
     pub session_id: String,
@@ -94,9 +372,9 @@ impl ChatGPT {
     /// # Arguments
     /// * `openai_api_token` - The API token from OpenAI
     /// * `chat_context` - The context of the chatbot.
-    /// Optional. If not provided, it will start a new context with the default model
+    ///   Optional. If not provided, it will start a new context with the default model
     /// * `session_id` - The session ID of the chatbot.
-    /// Optional. If not provided, it will generate a new session ID. This will be useful to track the conversation history
+    ///   Optional. If not provided, it will generate a new session ID. It is the key of the conversation in the ConversationStore
     /// # Example
     /// ```
     /// use chatgpt_functions::chat_gpt::ChatGPTBuilder;
@@ -109,12 +387,11 @@ impl ChatGPT {
     ///     Ok(())
     /// }
     /// ```
-    /// # Errors
-    /// It returns an error if the API token is not valid
-    /// # Panics
-    /// It panics if the API token is not provided
     /// # Remarks
     /// The API token can be found on the [OpenAI API keys](https://platform.openai.com/account/api-keys)
+    /// It uses the OpenAI endpoint, use ChatGPTBuilder::base_url to point to another server.
+    /// The requests are sent with the client provided, use ChatGPT::with_transport to send them
+    /// with another transport
     pub fn new(
         client: reqwest::Client,
         model: String,
@@ -122,42 +399,386 @@ impl ChatGPT {
         session_id: String,
         chat_context: ChatContext,
     ) -> Result<ChatGPT> {
-        Ok(ChatGPT {
-            client,
+        Ok(ChatGPT::with_transport(
+            Arc::new(ReqwestTransport::new(client)),
             model,
             openai_api_token,
             session_id,
             chat_context,
-        })
+        ))
+    }
+
+    /// Creates a new ChatGPT object that sends the requests with the transport provided,
+    /// e.g. a ScriptedTransport in the tests or a CassetteTransport. See ChatGPT::new
+    pub fn with_transport(
+        transport: Arc<dyn Transport>,
+        model: String,
+        openai_api_token: String,
+        session_id: String,
+        chat_context: ChatContext,
+    ) -> ChatGPT {
+        ChatGPT {
+            transport,
+            url: endpoint_url(DEFAULT_BASE_URL, DEFAULT_PATH),
+            retry_policy: RetryPolicy::none(),
+            max_function_iterations: DEFAULT_MAX_FUNCTION_ITERATIONS,
+            validation_mode: ValidationMode::Off,
+            truncation_strategies: Vec::new(),
+            compaction: None,
+            conversation_store: None,
+            system_prompt: None,
+            price_table: PriceTable::new(),
+            budget: None,
+            choice_selector: None,
+            model,
+            openai_api_token,
+            session_id,
+            chat_context,
+        }
+    }
+
+    /// The URL used for every request to the chat completions endpoint
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Sets the transport that sends the requests
+    pub fn set_transport(&mut self, transport: Arc<dyn Transport>) {
+        self.transport = transport;
+    }
+
+    /// Sets the policy used to retry the requests that fail with a transient error
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    /// Sets the maximum number of rounds of function calls that run_until_answer executes
+    pub fn set_max_function_iterations(&mut self, max_function_iterations: u32) {
+        self.max_function_iterations = max_function_iterations;
+    }
+
+    /// Sets how run_until_answer handles the function calls with invalid arguments
+    pub fn set_validation_mode(&mut self, validation_mode: ValidationMode) {
+        self.validation_mode = validation_mode;
+    }
+
+    /// Sets the strategies to remove messages from the context before every request,
+    /// replacing the ones set before
+    pub fn set_truncation_strategies(&mut self, strategies: Vec<Box<dyn TruncationStrategy>>) {
+        self.truncation_strategies = strategies;
+    }
+
+    /// Sets the compaction of the context, None to turn it off
+    pub fn set_compaction(&mut self, compaction: Option<Compaction>) {
+        self.compaction = compaction;
+    }
+
+    /// Summarizes the older messages of the context with the model, replacing them with the summary,
+    /// even if the context is below the threshold of the compaction.
+    /// It returns whether the context was compacted, it is not when there are no older messages
+    /// # Errors
+    /// It returns Error::MissingConfiguration if there is no compaction set
+    /// It returns an error if the request fails, or Error::UnexpectedResponse if the model does not answer with a summary
+    /// # Remarks
+    /// The truncation strategies are not applied to the summary request
+    pub async fn compact(&mut self) -> Result<bool> {
+        let compaction = self
+            .compaction
+            .clone()
+            .ok_or_else(|| Error::MissingConfiguration("There is no compaction set".to_string()))?;
+        let indexes = compaction.messages_to_summarize(&self.chat_context);
+        if indexes.is_empty() {
+            return Ok(false);
+        }
+        let request = compaction.summary_request(&self.chat_context, &indexes);
+        let summary = self.request(&request).await?.content().ok_or_else(|| {
+            Error::UnexpectedResponse("The model did not answer with a summary".to_string())
+        })?;
+        compaction.replace_with_summary(&mut self.chat_context, &indexes, summary);
+        Ok(true)
+    }
+
+    /// Sets the system prompt, the first message of the context, replacing the one set before.
+    /// The `{{variable}}` placeholders are replaced with the values set with
+    /// ChatGPTBuilder::system_prompt_variable or set_system_prompt_variable
+    /// # Errors
+    /// It returns Error::InvalidMessage if a placeholder has no value. The context is not modified
+    pub fn set_system_prompt(&mut self, template: String) -> Result<()> {
+        let mut system_prompt = self.system_prompt.clone().unwrap_or_default();
+        system_prompt.template = template;
+        self.chat_context.set_system_prompt(system_prompt.render()?);
+        self.system_prompt = Some(system_prompt);
+        Ok(())
+    }
+
+    /// Sets the value of a `{{variable}}` placeholder of the system prompt,
+    /// and updates the system prompt of the context with it
+    /// # Errors
+    /// It returns Error::InvalidMessage if another placeholder has no value. The context is not modified
+    pub fn set_system_prompt_variable(&mut self, name: String, value: String) -> Result<()> {
+        let mut system_prompt = self.system_prompt.clone().unwrap_or_default();
+        system_prompt.set_variable(name, value);
+        if !system_prompt.template.is_empty() {
+            self.chat_context.set_system_prompt(system_prompt.render()?);
+        }
+        self.system_prompt = Some(system_prompt);
+        Ok(())
+    }
+
+    /// The tokens used by all the completions of the session, including the summaries of the compaction.
+    /// They are saved with the context by the conversation stores, so they add up across resumed sessions
+    pub fn usage(&self) -> Usage {
+        self.chat_context.usage
+    }
+
+    /// Starts counting the usage and the cost of the session from zero
+    pub fn reset_usage(&mut self) {
+        self.chat_context.usage = Usage::default();
+        self.chat_context.cost = 0.0;
+    }
+
+    /// The cost in dollars of all the completions of the session, including the summaries of the compaction.
+    /// Only the completions of the models in the price table are counted
+    pub fn cost(&self) -> f64 {
+        self.chat_context.cost
+    }
+
+    /// The cost in dollars of a completion of the model of the context, if it is in the price table
+    pub fn response_cost(&self, response: &ChatResponse) -> Option<f64> {
+        self.price_table
+            .cost(&self.chat_context.model, &response.usage)
+    }
+
+    /// Sets the prices of the models, used to compute the cost of the completions
+    pub fn set_price_table(&mut self, price_table: PriceTable) {
+        self.price_table = price_table;
+    }
+
+    /// Sets the maximum cost in dollars of the session, or removes it with None
+    ///
+    /// Before every request, the cost of the session plus the highest cost the request could have
+    /// is compared with the budget: the prompt counted offline, and `max_tokens` of completion.
+    /// Without `max_tokens` the cost of the completion can't be known, and the last request can
+    /// go over the budget. Use reset_usage to start a new budget period.
+    /// The streamed turns of completion_managed_stream count like the others, but the streams
+    /// of completion_stream are not added to the session, as ChatGPT doesn't see their end.
+    pub fn set_budget(&mut self, budget: Option<f64>) {
+        self.budget = budget;
+    }
+
+    /// Sets how the managed completions choose the choice committed to the context,
+    /// or commits the first one with None. See ChatGPTBuilder::choice_selector
+    pub fn set_choice_selector(&mut self, choice_selector: Option<ChoiceSelector>) {
+        self.choice_selector = choice_selector;
+    }
+
+    /// Commits the choice with the given index of the response to the context,
+    /// e.g. after getting several candidates with completion_with_user_content and `n`.
+    /// It returns the message of the choice
+    /// # Errors
+    /// It returns Error::UnexpectedResponse if the response has no choice with that index
+    /// # Remarks
+    /// The context is saved in the conversation store, if there is one
+    pub fn select_choice(&mut self, response: &ChatResponse, index: u64) -> Result<Message> {
+        let message = choice_message(&response.choices, index)?;
+        self.push_message(message.clone());
+        self.autosave()?;
+        Ok(message)
+    }
+
+    // The message of the choice that the managed completions commit to the context
+    fn selected_message(&self, choices: &[Choice]) -> Result<Message> {
+        let index = match (&self.choice_selector, choices.first()) {
+            (Some(select), _) => select(choices),
+            (None, Some(first)) => first.index,
+            (None, None) => {
+                return Err(Error::UnexpectedResponse(
+                    "The response has no choices".to_string(),
+                ))
+            }
+        };
+        choice_message(choices, index)
+    }
+
+    // Fails before sending a request that could exceed the budget
+    fn check_budget(&self, context: &ChatContext) -> Result<()> {
+        let limit = match self.budget {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        let estimated = self.price_table.estimate(context).ok_or_else(|| {
+            Error::MissingConfiguration(format!(
+                "The model {} has no price, the budget of the session can't be enforced",
+                context.model
+            ))
+        })?;
+        let spent = self.chat_context.cost;
+        if spent + estimated > limit {
+            return Err(Error::BudgetExceeded {
+                limit,
+                spent,
+                estimated,
+            });
+        }
+        Ok(())
+    }
+
+    /// Sets the store where the context is saved after every turn of the managed completions
+    pub fn set_conversation_store(&mut self, conversation_store: Arc<dyn ConversationStore>) {
+        self.conversation_store = Some(conversation_store);
+    }
+
+    /// Saves the context in the conversation store, with the session_id
+    /// # Errors
+    /// It returns Error::MissingConfiguration if there is no conversation store set
+    /// It returns Error::Store if the store fails to save it
+    pub fn save_conversation(&self) -> Result<()> {
+        match &self.conversation_store {
+            Some(store) => store.save(&self.session_id, &self.chat_context),
+            None => Err(Error::MissingConfiguration(
+                "There is no conversation store set".to_string(),
+            )),
+        }
+    }
+
+    // Saves the context after a turn of a managed completion, if there is a store
+    fn autosave(&self) -> Result<()> {
+        match &self.conversation_store {
+            Some(store) => store.save(&self.session_id, &self.chat_context),
+            None => Ok(()),
+        }
+    }
+
+    // Compacts and truncates the context, before it is sent
+    async fn prepare_context(&mut self) -> Result<()> {
+        let needs_compaction = self
+            .compaction
+            .as_ref()
+            .is_some_and(|c| c.is_needed(&self.chat_context));
+        if needs_compaction {
+            self.compact().await?;
+        }
+        for strategy in &self.truncation_strategies {
+            strategy.truncate(&mut self.chat_context);
+        }
+        Ok(())
     }
 
     /// Calls the OpenAI API to get a response using the current context
-    /// # Arguments
-    /// * `message` - The message to send to the AI
     /// # Errors
     /// It returns an error if the API token is not valid
     /// It returns an error if the response from the API is not valid or if the content of the response is not valid
-    /// # Panics
-    /// It panics if the API token is not provided
+    /// It returns Error::Api when the API replies with an error status, with the error object sent by the API
+    /// and the rate limit headers (Retry-After, x-ratelimit-*) of the response
+    /// It returns Error::BudgetExceeded, without sending the request, if it could exceed the budget of the session
     /// # Remarks
-    /// The context is updated with the response from the AI
+    /// The context is not updated with the response from the AI, push the message to keep it,
+    /// or use completion_managed. The usage of the response is added to the session
+    /// Failed requests are retried according to the retry policy, the context is sent as it is on every attempt
+    /// The context is compacted and the truncation strategies are applied before sending it
     pub async fn completion(&mut self) -> Result<ChatResponse> {
-        let response = self
-            .client
-            .post(URL)
-            .bearer_auth(&self.openai_api_token)
-            .header("Content-Type", "application/json")
-            // Use Display trait to avoid sending None fields that the API would reject
-            .body(self.chat_context.to_string())
-            .send()
-            .await
-            .context(format!("Failed to receive the response from {}", URL))?
-            .text()
-            .await
-            .context("Failed to retrieve the content of the response")?;
+        self.prepare_context().await?;
+        let context = self.chat_context.clone();
+        self.request(&context).await
+    }
+
+    /// Calls the OpenAI API to get a response using the current context,
+    /// overriding some of its sampling parameters for this request only
+    /// # Arguments
+    /// * `overrides` - The parameters to use instead of the ones in the context
+    /// # Errors
+    /// It returns an error if the request fails or if the response from the API is not valid
+    /// # Remarks
+    /// The parameters of the context are not modified.
+    /// The context is not updated with the response from the AI
+    pub async fn completion_with_parameters(
+        &mut self,
+        overrides: &CompletionParameters,
+    ) -> Result<ChatResponse> {
+        self.prepare_context().await?;
+        let mut context = self.chat_context.clone();
+        context.parameters = context.parameters.merge(overrides);
+        self.request(&context).await
+    }
+
+    /// Same as completion_managed, overriding some of the sampling parameters for this request only
+    /// # Arguments
+    /// * `content` - The content of the message
+    /// * `overrides` - The parameters to use instead of the ones in the context
+    /// # Remarks
+    /// The context is updated with the message provided and the response from the AI,
+    /// but its parameters are not modified.
+    pub async fn completion_managed_with_parameters(
+        &mut self,
+        content: String,
+        overrides: &CompletionParameters,
+    ) -> Result<ChatResponse> {
+        self.push_message(Message::new_user_message(content));
+        let response = self.completion_with_parameters(overrides).await?;
+        let message = self.selected_message(&response.choices)?;
+        self.push_message(message);
+        self.autosave()?;
+        Ok(response)
+    }
+
+    /// Forces the model to call the function with the given name, using the current context
+    /// and the content provided by the user.
+    /// This is useful to extract structured data from the content, described by the parameters of the function.
+    /// # Arguments
+    /// * `function_name` - The name of the function to call, it has to be in the context
+    /// * `content` - The content of the message
+    /// # Errors
+    /// It returns an error if the request fails, or Error::UnexpectedResponse if the model does not call the function
+    /// # Remarks
+    /// The context is not updated, neither with the message provided nor with the response from the AI
+    pub async fn completion_forcing_function(
+        &mut self,
+        function_name: &str,
+        content: String,
+    ) -> Result<FunctionCall> {
+        self.prepare_context().await?;
+        let mut context = self.chat_context.clone();
+        context.push_message(Message::new_user_message(content));
+        context.set_function_call(FunctionCallMode::Named(function_name.to_string()));
+        let response = self.request(&context).await?;
+        response
+            .message()
+            .and_then(|m| m.function_call)
+            .filter(|f| f.name == function_name)
+            .ok_or_else(|| {
+                Error::UnexpectedResponse(format!(
+                    "The model did not call the function {}",
+                    function_name
+                ))
+            })
+    }
+
+    // Sends the context to the API and parses the response, adding its usage to the session
+    async fn request(&mut self, context: &ChatContext) -> Result<ChatResponse> {
+        self.check_budget(context)?;
+        let response = self.send(request_body(context)?).await?;
+        let status = response.status;
+        let rate_limit = RateLimit::from_headers(&response.headers);
+        let body = response.text().await?;
+
+        // Some proxies reply with a success status and an error object
+        if let Some(error) = Error::parse_api_error(&body) {
+            return Err(Error::Api {
+                status,
+                error,
+                rate_limit: Box::new(rate_limit),
+            });
+        }
+        let response = ChatResponse::from_json(&body)?;
+        self.add_usage(&context.model, response.usage);
+        Ok(response)
+    }
 
-        let answer = parse_removing_newlines(response)?;
-        Ok(answer)
+    fn add_usage(&mut self, model: &str, usage: Usage) {
+        self.chat_context.usage += usage;
+        if let Some(cost) = self.price_table.cost(model, &usage) {
+            self.chat_context.cost += cost;
+        }
     }
 
     /// Calls the OpenAI API to get a response using the current context, adding the content provided by the user
@@ -171,11 +792,10 @@ impl ChatGPT {
     /// # Errors
     /// It returns an error if the API token is not valid
     /// It returns an error if the response from the API is not valid or if the content of the response is not valid
-    /// # Panics
-    /// It panics if the API token is not provided
     /// # Remarks
     /// This is a fully managed function, it does update the context with the message provided,
     /// and it does update the context with the response from the AI.
+    /// The context is saved in the conversation store after the turn, if there is one
     pub async fn completion_managed(&mut self, content: String) -> Result<ChatResponse> {
         self.completion_with_user_content_updating_context(content)
             .await
@@ -233,8 +853,9 @@ impl ChatGPT {
     /// The context is updated with the message provided
     /// The context is updated with the response from the AI
     /// This function is used by the other functions of the library
-    /// It assumes that there will only be one choice in the response
-    /// It returns the response from the AI
+    /// When there are several choices, the first one is committed to the context,
+    /// or the one chosen by the choice selector, see ChatGPTBuilder::choice_selector
+    /// It returns the response from the AI, with all the choices
     pub async fn completion_with_user_content_updating_context(
         &mut self,
         content: String,
@@ -244,14 +865,15 @@ impl ChatGPT {
     }
 
     /// This function is used to update the context with the response from the AI
-    /// It assumes that there will only be one choice in the response
-    /// It returns the response from the AI
+    /// It returns the response from the AI, with all the choices
     /// It does update the context with the response from the AI
     /// # Arguments
     /// * `message` - The message to send to the AI
     /// # Errors
     /// It returns an error if the API token is not valid
     /// It returns an error if the response from the API is not valid or if the content of the response is not valid
+    /// It returns Error::UnexpectedResponse if the response has no choices, or the choice selector returns
+    /// an index that is not in the response
     /// # Remarks
     /// Important: The message received from the AI has to be modified when it is a function
     /// This is because when a function is returned the model still says that it is an assistant message.
@@ -261,62 +883,333 @@ impl ChatGPT {
     ///
     /// The context is updated with the response from the AI
     /// This function is used by the other functions of the library
-    /// It assumes that there will only be one choice in the response
-    /// It panics if there is more than one choice in the response
+    /// When there are several choices, the first one is committed to the context,
+    /// or the one chosen by the choice selector, see ChatGPTBuilder::choice_selector
     pub async fn completion_with_message_updating_context(
         &mut self,
         message: Message,
     ) -> Result<ChatResponse> {
         self.push_message(message);
         let response = self.completion().await?;
-        if let Some(choice) = response.choices.last() {
-            self.push_message(choice.message.clone());
-        };
+        let message = self.selected_message(&response.choices)?;
+        self.push_message(message);
+        self.autosave()?;
         Ok(response)
     }
 
-    /// This function is used to push a message to the context
-    /// This is a low level function, it is not recommended to use it directly
+    /// Sends the content as a user message and runs the functions called by the model
+    /// until it answers with content.
+    /// Every call is run with the handler of the registry, and its result is pushed to the context
+    /// as a function message (legacy functions) or a tool message (tools), before calling the API again.
+    /// It returns the response with the final answer
     /// # Arguments
-    /// * `message` - The message to push to the context
+    /// * `content` - The content of the message
+    /// * `registry` - The functions that the model can call
+    /// # Errors
+    /// It returns an error if a request fails or the response from the API is not valid
+    /// It returns Error::UnknownFunction if the model calls a function that is not in the registry
+    /// It returns Error::FunctionFailed if a handler returns an error
+    /// It returns Error::InvalidArguments if the arguments of a call don't match its specification
+    /// and the validation mode is ValidationMode::Reject
+    /// It returns Error::MaxIterationsReached if the model keeps calling functions
+    /// after the maximum number of iterations, see ChatGPTBuilder::max_function_iterations
     /// # Remarks
-    /// This function is used by the other functions of the library
-    pub fn push_message(&mut self, message: Message) {
-        self.chat_context.push_message(message);
+    /// The specifications of the registry are not added to the context,
+    /// use set_functions(registry.specifications()) or set_tools(registry.tools()) for that.
+    /// The tool calls of the same message are run concurrently.
+    /// With ValidationMode::Correct the calls with invalid arguments are not run,
+    /// the violations are sent back to the model instead so it can call the function again.
+    /// The context keeps every message of the exchange, including the function calls and their results
+    /// When it returns an error, the last message with calls is removed as they have no results,
+    /// so the context can still be sent
+    /// The context is saved in the conversation store once the model answers, if there is one
+    pub async fn run_until_answer(
+        &mut self,
+        content: String,
+        registry: &FunctionRegistry,
+    ) -> Result<ChatResponse> {
+        self.push_message(Message::new_user_message(content));
+        let mut iterations = 0;
+        loop {
+            let response = self.completion().await?;
+            let message = self.selected_message(&response.choices)?;
+            self.push_message(message.clone());
+            if message.function_call.is_none() && message.tool_calls.is_none() {
+                self.autosave()?;
+                return Ok(response);
+            }
+            let answered = if iterations == self.max_function_iterations {
+                Err(Error::MaxIterationsReached(iterations))
+            } else {
+                self.answer_calls(message, registry).await
+            };
+            if let Err(e) = answered {
+                // The API rejects the calls without results, the next requests would fail
+                self.chat_context.messages.pop();
+                return Err(e);
+            }
+            iterations += 1;
+        }
     }
 
-    /// This function is used to set all the messages in the context
-    /// This will override the current messages in the context
-    /// This is a low level function, it is not recommended to use it directly
-    /// # Arguments
-    /// * `messages` - The messages to set in the context
-    /// # Remarks
-    /// This function is used by the other functions of the library
-    pub fn set_messages(&mut self, messages: Vec<Message>) {
-        self.chat_context.set_messages(messages);
+    // Runs the calls of the message and pushes their results to the context.
+    // Nothing is pushed if any of the calls fails
+    async fn answer_calls(&mut self, message: Message, registry: &FunctionRegistry) -> Result<()> {
+        if let Some(tool_calls) = message.tool_calls {
+            let results = try_join_all(
+                tool_calls
+                    .iter()
+                    .map(|call| self.run_function_call(&call.function, registry)),
+            )
+            .await?;
+            for (call, result) in tool_calls.into_iter().zip(results) {
+                self.push_tool_result(call.id, result);
+            }
+        } else if let Some(function_call) = message.function_call {
+            let result = self.run_function_call(&function_call, registry).await?;
+            self.push_message(Message::function_result(function_call.name, result));
+        }
+        Ok(())
     }
 
-    /// This function is used to push a function to the context
-    /// This is a low level function, it is not recommended to use it directly
-    /// # Arguments
-    /// * `function` - The function to push to the context
-    /// # Remarks
-    /// This function is used by the other functions of the library
-    pub fn push_function(&mut self, function: FunctionSpecification) {
-        self.chat_context.push_function(function);
+    // Runs a function call with the registry, validating it first according to the validation mode.
+    // It returns the content sent back to the model
+    async fn run_function_call(
+        &self,
+        function_call: &FunctionCall,
+        registry: &FunctionRegistry,
+    ) -> Result<String> {
+        if self.validation_mode != ValidationMode::Off {
+            let violations = self.chat_context.validate_function_call(function_call);
+            if !violations.is_empty() {
+                if self.validation_mode == ValidationMode::Correct {
+                    return Ok(correction_message(&function_call.name, &violations));
+                }
+                return Err(Error::InvalidArguments {
+                    name: function_call.name.clone(),
+                    violations,
+                });
+            }
+        }
+        registry
+            .call(&function_call.name, function_call.arguments.clone())
+            .await
     }
 
-    /// This function is used to set all the functions in the context
-    /// This will override the current functions in the context
-    /// This is a low level function, it is not recommended to use it directly
-    /// # Arguments
-    /// * `functions` - The vec of functions to set in the context
+    /// Calls the OpenAI API to get a streamed response using the current context
+    /// It returns a stream of chunks, as they are generated by the model
+    /// # Errors
+    /// It returns an error if the request fails or the API replies with an error status
+    /// It returns Error::BudgetExceeded, without sending the request, if it could exceed the budget of the session
+    /// Each chunk of the stream is an error if it can't be received or parsed
     /// # Remarks
-    /// This function is used by the other functions of the library
-    pub fn set_functions(&mut self, functions: Vec<FunctionSpecification>) {
+    /// The context is not updated with the response from the AI.
+    /// Once the stream is finished, the whole message is available with ChatStream::message(),
+    /// and the tokens used with ChatStream::usage(), as the usage is requested with the stream
+    pub async fn completion_stream(&mut self) -> Result<ChatStream> {
+        self.prepare_context().await?;
+        let mut context = self.chat_context.clone();
+        context.stream = Some(true);
+        context.stream_options = Some(StreamOptions {
+            include_usage: true,
+        });
+        self.check_budget(&context)?;
+        let response = self.send(request_body(&context)?).await?;
+        Ok(ChatStream::new(response.body))
+    }
+
+    // Sends the request, retrying it according to the retry policy.
+    // It only returns the response if the API replied with a success status
+    async fn send(&self, body: String) -> Result<TransportResponse> {
+        let mut attempt = 1;
+        loop {
+            let error = match self.send_once(body.clone()).await {
+                Ok(response) => return Ok(response),
+                Err(e) => e,
+            };
+            if !self.retry_policy.should_retry(attempt, &error) {
+                return Err(error);
+            }
+            tokio::time::sleep(self.retry_policy.delay(attempt, &error)).await;
+            attempt += 1;
+        }
+    }
+
+    async fn send_once(&self, body: String) -> Result<TransportResponse> {
+        let request = TransportRequest {
+            url: self.url.clone(),
+            api_key: self.openai_api_token.clone(),
+            body,
+        };
+        let response = self.transport.send(request).await?;
+        if !response.is_success() {
+            let status = response.status;
+            let rate_limit = RateLimit::from_headers(&response.headers);
+            let body = response.text().await?;
+            return Err(Error::from_response(status, rate_limit, body));
+        }
+        Ok(response)
+    }
+
+    /// Streamed version of completion_managed.
+    /// It calls `on_chunk` with every chunk as it arrives, and returns the whole message at the end.
+    /// # Arguments
+    /// * `content` - The content of the message
+    /// * `on_chunk` - Called with every chunk received, e.g. to show the tokens as they arrive
+    /// # Errors
+    /// It returns an error if the request fails or if any of the chunks can't be received or parsed
+    /// # Remarks
+    /// The context is updated with the message provided,
+    /// and with the message put back together from the chunks once the stream is finished.
+    /// The usage and its cost are added to the session, as the usage is requested with the stream.
+    /// The context is saved in the conversation store, if there is one
+    pub async fn completion_managed_stream<F>(
+        &mut self,
+        content: String,
+        mut on_chunk: F,
+    ) -> Result<Message>
+    where
+        F: FnMut(&ChatResponseChunk),
+    {
+        self.push_message(Message::new_user_message(content));
+        let mut stream = self.completion_stream().await?;
+        while let Some(chunk) = stream.next().await {
+            on_chunk(&chunk?);
+        }
+        let message = self.selected_message(&stream.choices())?;
+        self.push_message(message.clone());
+        if let Some(usage) = stream.usage() {
+            self.add_usage(&self.chat_context.model.clone(), usage);
+        }
+        self.autosave()?;
+        Ok(message)
+    }
+
+    /// Sets the sampling parameters of the context, replacing the ones set before
+    pub fn set_parameters(&mut self, parameters: CompletionParameters) {
+        self.chat_context.set_parameters(parameters);
+    }
+
+    pub fn set_temperature(&mut self, temperature: f64) {
+        self.chat_context.parameters.temperature = Some(temperature);
+    }
+
+    pub fn set_top_p(&mut self, top_p: f64) {
+        self.chat_context.parameters.top_p = Some(top_p);
+    }
+
+    pub fn set_max_tokens(&mut self, max_tokens: u32) {
+        self.chat_context.parameters.max_tokens = Some(max_tokens);
+    }
+
+    pub fn set_n(&mut self, n: u32) {
+        self.chat_context.parameters.n = Some(n);
+    }
+
+    pub fn set_stop(&mut self, stop: Vec<String>) {
+        self.chat_context.parameters.stop = Some(stop);
+    }
+
+    pub fn set_presence_penalty(&mut self, presence_penalty: f64) {
+        self.chat_context.parameters.presence_penalty = Some(presence_penalty);
+    }
+
+    pub fn set_frequency_penalty(&mut self, frequency_penalty: f64) {
+        self.chat_context.parameters.frequency_penalty = Some(frequency_penalty);
+    }
+
+    pub fn set_logit_bias(&mut self, logit_bias: BTreeMap<u32, i32>) {
+        self.chat_context.parameters.logit_bias = Some(logit_bias);
+    }
+
+    pub fn set_user(&mut self, user: String) {
+        self.chat_context.parameters.user = Some(user);
+    }
+
+    pub fn set_seed(&mut self, seed: i64) {
+        self.chat_context.parameters.seed = Some(seed);
+    }
+
+    /// This function is used to push a message to the context
+    /// This is a low level function, it is not recommended to use it directly
+    /// # Arguments
+    /// * `message` - The message to push to the context
+    /// # Remarks
+    /// This function is used by the other functions of the library
+    pub fn push_message(&mut self, message: Message) {
+        self.chat_context.push_message(message);
+    }
+
+    /// This function is used to set all the messages in the context
+    /// This will override the current messages in the context, except the system prompt
+    /// when the messages don't start with their own
+    /// This is a low level function, it is not recommended to use it directly
+    /// # Arguments
+    /// * `messages` - The messages to set in the context
+    /// # Remarks
+    /// This function is used by the other functions of the library
+    pub fn set_messages(&mut self, messages: Vec<Message>) {
+        self.chat_context.set_messages(messages);
+    }
+
+    /// This function is used to push a function to the context
+    /// This is a low level function, it is not recommended to use it directly
+    /// # Arguments
+    /// * `function` - The function to push to the context
+    /// # Remarks
+    /// This function is used by the other functions of the library
+    pub fn push_function(&mut self, function: FunctionSpecification) {
+        self.chat_context.push_function(function);
+    }
+
+    /// This function is used to set all the functions in the context
+    /// This will override the current functions in the context
+    /// This is a low level function, it is not recommended to use it directly
+    /// # Arguments
+    /// * `functions` - The vec of functions to set in the context
+    /// # Remarks
+    /// This function is used by the other functions of the library
+    pub fn set_functions(&mut self, functions: Vec<FunctionSpecification>) {
         self.chat_context.set_functions(functions);
     }
 
+    /// This function is used to push a tool to the context
+    /// Newer models use tools instead of functions, a FunctionSpecification can be converted with Tool::function
+    /// # Arguments
+    /// * `tool` - The tool to push to the context
+    pub fn push_tool(&mut self, tool: Tool) {
+        self.chat_context.push_tool(tool);
+    }
+
+    /// This function is used to set all the tools in the context
+    /// This will override the current tools in the context
+    /// # Arguments
+    /// * `tools` - The vec of tools to set in the context
+    pub fn set_tools(&mut self, tools: Vec<Tool>) {
+        self.chat_context.set_tools(tools);
+    }
+
+    /// Sets how the model calls the tools of the context:
+    /// automatically, never, at least one, or always a specific function
+    pub fn set_tool_choice(&mut self, tool_choice: ToolChoice) {
+        self.chat_context.set_tool_choice(tool_choice);
+    }
+
+    /// This function is used to push the result of a tool call to the context
+    /// Every tool call of the assistant needs a result before calling the API again
+    /// # Arguments
+    /// * `tool_call_id` - The id of the ToolCall
+    /// * `content` - The result of the tool
+    pub fn push_tool_result(&mut self, tool_call_id: String, content: String) {
+        self.push_message(Message::tool_result(tool_call_id, content));
+    }
+
+    /// Sets how the model calls the functions of the context:
+    /// automatically, never, or always a specific function
+    pub fn set_function_call(&mut self, function_call: FunctionCallMode) {
+        self.chat_context.set_function_call(function_call);
+    }
+
     /// This function is used to retrieve the content of the last message in the context
     pub fn last_content(&self) -> Option<String> {
         self.chat_context.last_content()
@@ -326,24 +1219,54 @@ impl ChatGPT {
     pub fn last_function(&self) -> Option<(String, String)> {
         self.chat_context.last_function_call()
     }
+
+    /// This function is used to retrieve the tool calls of the last message in the context
+    pub fn last_tool_calls(&self) -> Option<Vec<ToolCall>> {
+        self.chat_context.last_tool_calls()
+    }
+}
+
+// The JSON of the context, as it is sent to the API
+fn request_body(context: &ChatContext) -> Result<String> {
+    serde_json::to_string(context).map_err(Error::Serialize)
+}
+
+// Join the base URL and the path, avoiding duplicated or missing slashes
+fn endpoint_url(base_url: &str, path: &str) -> String {
+    format!(
+        "{}/{}",
+        base_url.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    )
 }
 
-fn parse_removing_newlines(response: String) -> Result<ChatResponse> {
-    let r = response.replace("\n", "");
-    let response: ChatResponse = serde_json::from_str(&r).context(format!(
-        "Could not parse the response. The object to parse: \n{}",
-        r
-    ))?;
-    Ok(response)
+fn choice_message(choices: &[Choice], index: u64) -> Result<Message> {
+    choices
+        .iter()
+        .find(|choice| choice.index == index)
+        .map(|choice| choice.message.clone())
+        .ok_or_else(|| {
+            Error::UnexpectedResponse(format!("The response has no choice with index {}", index))
+        })
 }
 
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
 
-    use crate::{function_specification::Parameters, message::FunctionCall};
+    use crate::{
+        function_specification::Parameters,
+        message::FunctionCall,
+        mock_server::{
+            answer, completion, function_call, tool_calls, usage, MockResponse, MockServer,
+        },
+    };
 
     use super::*;
+    use crate::{
+        compaction::Compaction, conversation_store::FileStore, message::Role, pricing::ModelPrice,
+        transport::ScriptedTransport, truncation::KeepLastMessages, validation::ValidationMode,
+    };
 
     #[test]
     fn test_chat_gpt_new() {
@@ -369,6 +1292,1317 @@ mod tests {
         assert_eq!(chat_gpt.chat_context.model, "model");
     }
 
+    #[test]
+    fn test_chat_gpt_default_url() {
+        let chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .build()
+            .expect("Failed to create ChatGPT");
+        assert_eq!(chat_gpt.url(), "https://api.openai.com/v1/chat/completions");
+    }
+
+    #[test]
+    fn test_chat_gpt_custom_url() {
+        let chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url("http://localhost:8000/v1/".to_string())
+            .path("/completions".to_string())
+            .build()
+            .expect("Failed to create ChatGPT");
+        assert_eq!(chat_gpt.url(), "http://localhost:8000/v1/completions");
+
+        let chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url("http://localhost:11434/v1".to_string())
+            .build()
+            .expect("Failed to create ChatGPT");
+        assert_eq!(chat_gpt.url(), "http://localhost:11434/v1/chat/completions");
+    }
+
+    #[tokio::test]
+    async fn test_completion_uses_base_url() {
+        let server =
+            MockServer::start(vec![MockResponse::new(200, &answer("Hi!", usage(9, 2)))]).await;
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url(format!("{}/v1", server.url))
+            .build()
+            .expect("Failed to create ChatGPT");
+
+        let answer = chat_gpt
+            .completion_managed("Hello".to_string())
+            .await
+            .expect("Failed to get the completion");
+        assert_eq!(answer.content(), Some("Hi!".to_string()));
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path, "/v1/chat/completions");
+        assert_eq!(requests[0].header("authorization"), Some("Bearer key"));
+        assert_eq!(requests[0].body, chat_gpt_request_body("Hello"));
+    }
+
+    #[tokio::test]
+    async fn test_completion_with_scripted_transport() {
+        let transport = Arc::new(
+            ScriptedTransport::new()
+                .error(Error::Connection(anyhow::anyhow!("Connection reset")))
+                .response(TransportResponse::new(200, answer("Hi!", usage(9, 2)))),
+        );
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .transport(transport.clone())
+            .retry_policy(
+                RetryPolicy::new()
+                    .max_attempts(2)
+                    .base_delay(std::time::Duration::from_millis(1)),
+            )
+            .build()
+            .expect("Failed to create ChatGPT");
+
+        let answer = chat_gpt
+            .completion_managed("Hello".to_string())
+            .await
+            .expect("The completion should succeed after retrying");
+        assert_eq!(answer.content(), Some("Hi!".to_string()));
+
+        // The errors of the transport are retried like the network errors
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[1].url,
+            "https://api.openai.com/v1/chat/completions"
+        );
+        assert_eq!(requests[1].api_key, "key");
+        assert_eq!(requests[1].body, chat_gpt_request_body("Hello"));
+    }
+
+    #[tokio::test]
+    async fn test_chat_gpt_with_transport() {
+        let transport = Arc::new(
+            ScriptedTransport::new()
+                .response(TransportResponse::new(200, answer("Hi!", usage(9, 2)))),
+        );
+        let mut chat_gpt = ChatGPT::with_transport(
+            transport.clone(),
+            DEFAULT_MODEL.to_string(),
+            "key".to_string(),
+            "session".to_string(),
+            ChatContext::new(DEFAULT_MODEL.to_string()),
+        );
+
+        let answer = chat_gpt
+            .completion_managed("Hello".to_string())
+            .await
+            .expect("Failed to get the completion");
+        assert_eq!(answer.content(), Some("Hi!".to_string()));
+        assert_eq!(transport.requests()[0].api_key, "key");
+    }
+
+    #[tokio::test]
+    async fn test_completion_stream_with_scripted_transport() {
+        // An event split across the chunks of the body
+        let transport = Arc::new(ScriptedTransport::new().response(
+            TransportResponse::from_chunks(
+                200,
+                vec![
+                    r#"data: {"choices":[{"index":0,"delta":{"role":"assistant","content":"Hi"#
+                        .to_string(),
+                    "!\"},\"finish_reason\":\"stop\"}]}\n\ndata: [DONE]\n\n".to_string(),
+                ],
+            ),
+        ));
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .transport(transport.clone())
+            .build()
+            .expect("Failed to create ChatGPT");
+
+        let message = chat_gpt
+            .completion_managed_stream("Hello".to_string(), |_| {})
+            .await
+            .expect("Failed to stream the completion");
+        assert_eq!(message.content, Some("Hi!".to_string()));
+        assert!(transport.requests()[0]
+            .body
+            .ends_with(",\"stream\":true,\"stream_options\":{\"include_usage\":true}}"));
+
+        // The error statuses of the transport are errors of the request
+        transport.push(Ok(TransportResponse::new(401, "Unauthorized".to_string())));
+        let error = chat_gpt
+            .completion_stream()
+            .await
+            .err()
+            .expect("The stream should fail");
+        assert!(error.is_authentication());
+    }
+
+    #[tokio::test]
+    async fn test_usage_of_the_session() {
+        let answer = |content: &str, prompt_tokens: u32| {
+            TransportResponse::new(200, answer(content, usage(prompt_tokens, 2)))
+        };
+        let transport = Arc::new(
+            ScriptedTransport::new()
+                .response(answer("Hi!", 9))
+                .response(answer("Bye!", 20))
+                .response(answer("Hi again!", 30)),
+        );
+        let directory =
+            std::env::temp_dir().join(format!("chatgpt-functions-usage-{}", uuid::Uuid::new_v4()));
+        let store = Arc::new(FileStore::new(&directory).expect("Failed to create the store"));
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .session_id("usage".to_string())
+            .transport(transport.clone())
+            .conversation_store(store.clone())
+            .build()
+            .expect("Failed to create ChatGPT");
+        assert!(chat_gpt.usage().is_empty());
+
+        let response = chat_gpt
+            .completion_managed("Hello".to_string())
+            .await
+            .expect("Failed to get the completion");
+        assert_eq!(response.id, "chatcmpl-1");
+        assert_eq!(response.created, 1687596091);
+        assert_eq!(response.usage.total_tokens, 11);
+        chat_gpt
+            .completion_managed("Bye".to_string())
+            .await
+            .expect("Failed to get the completion");
+        assert_eq!(
+            chat_gpt.usage(),
+            Usage {
+                prompt_tokens: 29,
+                completion_tokens: 4,
+                total_tokens: 33,
+            }
+        );
+
+        // The usage is saved with the session, and keeps adding up when it is resumed
+        let mut resumed = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .transport(transport)
+            .conversation_store(store)
+            .resume_session("usage".to_string())
+            .build()
+            .expect("Failed to resume the session");
+        assert_eq!(resumed.usage().total_tokens, 33);
+        resumed
+            .completion_managed("Hello again".to_string())
+            .await
+            .expect("Failed to get the completion");
+        assert_eq!(resumed.usage().total_tokens, 65);
+
+        resumed.reset_usage();
+        assert!(resumed.usage().is_empty());
+        std::fs::remove_dir_all(directory).expect("Failed to remove the directory");
+    }
+
+    #[tokio::test]
+    async fn test_usage_of_a_streamed_completion() {
+        let events = [
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":0,"choices":[{"index":0,"delta":{"role":"assistant","content":"Hi!"},"finish_reason":"stop"}]}"#,
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":0,"choices":[],"usage":{"prompt_tokens":9,"completion_tokens":2,"total_tokens":11}}"#,
+            "[DONE]",
+        ];
+        let body: String = events.iter().map(|e| format!("data: {}\n\n", e)).collect();
+        let transport =
+            Arc::new(ScriptedTransport::new().response(TransportResponse::new(200, body)));
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .transport(transport.clone())
+            .build()
+            .expect("Failed to create ChatGPT");
+
+        chat_gpt
+            .completion_managed_stream("Hello".to_string(), |_| {})
+            .await
+            .expect("Failed to stream the completion");
+        assert_eq!(
+            chat_gpt.chat_context.usage,
+            Usage {
+                prompt_tokens: 9,
+                completion_tokens: 2,
+                total_tokens: 11,
+            }
+        );
+        // The usage is requested with the stream, the context of the session is not modified
+        assert!(transport.requests()[0]
+            .body
+            .contains("\"stream_options\":{\"include_usage\":true}"));
+        assert_eq!(chat_gpt.chat_context.stream_options, None);
+    }
+
+    #[tokio::test]
+    async fn test_budget() {
+        let transport = Arc::new(
+            ScriptedTransport::new()
+                .response(TransportResponse::new(200, answer("Hi!", usage(1000, 500)))),
+        );
+        let prices = PriceTable::new().price(DEFAULT_MODEL.to_string(), ModelPrice::new(1.0, 2.0));
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .transport(transport.clone())
+            .price_table(prices)
+            .budget(0.00201)
+            .build()
+            .expect("Failed to create ChatGPT");
+
+        let response = chat_gpt
+            .completion_managed("Hello".to_string())
+            .await
+            .expect("The first request is within the budget");
+        assert_eq!(chat_gpt.response_cost(&response), Some(0.002));
+        assert_eq!(chat_gpt.cost(), 0.002);
+
+        // The next request could exceed the budget with its prompt alone
+        let error = chat_gpt
+            .completion_managed("Tell me a long story".to_string())
+            .await
+            .expect_err("The budget is exceeded");
+        match error {
+            Error::BudgetExceeded {
+                limit,
+                spent,
+                estimated,
+            } => {
+                assert_eq!(limit, 0.00201);
+                assert_eq!(spent, 0.002);
+                assert!(spent + estimated > limit);
+            }
+            e => panic!("Unexpected error: {:?}", e),
+        }
+        assert!(chat_gpt.completion_stream().await.is_err());
+        assert_eq!(transport.requests().len(), 1);
+
+        // A budget can't be enforced without the price of the model
+        chat_gpt.reset_usage();
+        assert_eq!(chat_gpt.cost(), 0.0);
+        chat_gpt.set_price_table(PriceTable::new());
+        let error = chat_gpt
+            .completion()
+            .await
+            .expect_err("The model has no price");
+        assert!(matches!(error, Error::MissingConfiguration(_)));
+        assert_eq!(transport.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_budget_with_a_streamed_completion() {
+        let events = [
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":0,"choices":[{"index":0,"delta":{"role":"assistant","content":"Hi!"},"finish_reason":"stop"}]}"#,
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":0,"choices":[],"usage":{"prompt_tokens":1000,"completion_tokens":500,"total_tokens":1500}}"#,
+            "[DONE]",
+        ];
+        let body: String = events.iter().map(|e| format!("data: {}\n\n", e)).collect();
+        let transport =
+            Arc::new(ScriptedTransport::new().response(TransportResponse::new(200, body)));
+        let prices = PriceTable::new().price(DEFAULT_MODEL.to_string(), ModelPrice::new(1.0, 2.0));
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .transport(transport.clone())
+            .price_table(prices)
+            .budget(0.00201)
+            .build()
+            .expect("Failed to create ChatGPT");
+
+        chat_gpt
+            .completion_managed_stream("Hello".to_string(), |_| {})
+            .await
+            .expect("The first request is within the budget");
+        assert_eq!(chat_gpt.cost(), 0.002);
+
+        // The spending of the stream counts for the next requests, streamed or not
+        let error = chat_gpt
+            .completion_managed_stream("Tell me a long story".to_string(), |_| {})
+            .await
+            .expect_err("The budget is exceeded");
+        assert!(matches!(error, Error::BudgetExceeded { spent, .. } if spent == 0.002));
+        assert!(matches!(
+            chat_gpt.completion().await,
+            Err(Error::BudgetExceeded { .. })
+        ));
+        assert_eq!(transport.requests().len(), 1);
+    }
+
+    fn several_choices() -> TransportResponse {
+        let message = |content: &str| serde_json::json!({"role": "assistant", "content": content});
+        TransportResponse::new(
+            200,
+            completion(
+                &[
+                    (message("Hi!"), "stop"),
+                    (message("Hello, how can I help?"), "stop"),
+                    (message("Hey"), "length"),
+                ],
+                usage(9, 12),
+            ),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_completion_with_several_choices() {
+        let transport = Arc::new(
+            ScriptedTransport::new()
+                .response(several_choices())
+                .response(several_choices()),
+        );
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .transport(transport.clone())
+            .n(3)
+            .build()
+            .expect("Failed to create ChatGPT");
+
+        // The first choice is committed by default
+        let response = chat_gpt
+            .completion_managed("Hello".to_string())
+            .await
+            .expect("Failed to get the completion");
+        assert_eq!(response.choices.len(), 3);
+        assert_eq!(
+            response.choice(2).map(|c| c.finish_reason.as_str()),
+            Some("length")
+        );
+        assert_eq!(chat_gpt.last_content(), Some("Hi!".to_string()));
+        assert!(transport.requests()[0].body.contains("\"n\":3"));
+
+        // Or the one chosen by the selector
+        chat_gpt.set_choice_selector(Some(Box::new(|choices: &[Choice]| {
+            choices
+                .iter()
+                .filter(|c| c.finish_reason == "stop")
+                .max_by_key(|c| c.message.content.as_deref().unwrap_or_default().len())
+                .map_or(0, |c| c.index)
+        })));
+        chat_gpt
+            .completion_managed("Hello again".to_string())
+            .await
+            .expect("Failed to get the completion");
+        assert_eq!(
+            chat_gpt.last_content(),
+            Some("Hello, how can I help?".to_string())
+        );
+        assert_eq!(chat_gpt.chat_context.messages.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_select_choice() {
+        let transport = Arc::new(
+            ScriptedTransport::new()
+                .response(several_choices())
+                .response(several_choices()),
+        );
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .transport(transport)
+            .n(3)
+            .build()
+            .expect("Failed to create ChatGPT");
+
+        let response = chat_gpt
+            .completion_with_user_content("Hello".to_string())
+            .await
+            .expect("Failed to get the completion");
+        let error = chat_gpt
+            .select_choice(&response, 3)
+            .expect_err("There is no choice 3");
+        assert!(matches!(error, Error::UnexpectedResponse(_)));
+        let message = chat_gpt
+            .select_choice(&response, 2)
+            .expect("Failed to select the choice");
+        assert_eq!(message.content, Some("Hey".to_string()));
+        assert_eq!(chat_gpt.chat_context.messages.len(), 2);
+        assert_eq!(chat_gpt.last_content(), Some("Hey".to_string()));
+
+        // A selector that returns an index that is not in the response fails
+        chat_gpt.set_choice_selector(Some(Box::new(|_: &[Choice]| 7)));
+        let error = chat_gpt
+            .completion_managed("Bye".to_string())
+            .await
+            .expect_err("There is no choice 7");
+        assert!(matches!(error, Error::UnexpectedResponse(_)));
+    }
+
+    fn chat_gpt_request_body(content: &str) -> String {
+        format!(
+            "{{\"model\":\"{}\",\"messages\":[{{\"role\":\"user\",\"content\":\"{}\"}}]}}",
+            DEFAULT_MODEL, content
+        )
+    }
+
+    #[tokio::test]
+    async fn test_completion_managed_stream() {
+        let events = [
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":0,"choices":[{"index":0,"delta":{"role":"assistant","content":""},"finish_reason":null}]}"#,
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":0,"choices":[{"index":0,"delta":{"content":"Hi"},"finish_reason":null}]}"#,
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":0,"choices":[{"index":0,"delta":{"content":" there!"},"finish_reason":null}]}"#,
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":0,"choices":[{"index":0,"delta":{},"finish_reason":"stop"}]}"#,
+            "[DONE]",
+        ];
+        let body: String = events.iter().map(|e| format!("data: {}\n\n", e)).collect();
+        let server = MockServer::start(vec![MockResponse::new(200, &body)]).await;
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url(server.url.clone())
+            .build()
+            .expect("Failed to create ChatGPT");
+
+        let mut received = Vec::new();
+        let message = chat_gpt
+            .completion_managed_stream("Hello".to_string(), |chunk| {
+                if let Some(content) = chunk.content() {
+                    received.push(content.to_string());
+                }
+            })
+            .await
+            .expect("Failed to stream the completion");
+
+        assert_eq!(received, vec!["", "Hi", " there!"]);
+        assert_eq!(message.content, Some("Hi there!".to_string()));
+        assert_eq!(chat_gpt.chat_context.messages.len(), 2);
+        assert_eq!(chat_gpt.last_content(), Some("Hi there!".to_string()));
+        // The context of the session is not modified, only the request
+        assert_eq!(chat_gpt.chat_context.stream, None);
+        assert!(server.requests()[0]
+            .body
+            .ends_with(",\"stream\":true,\"stream_options\":{\"include_usage\":true}}"));
+    }
+
+    #[tokio::test]
+    async fn test_completion_stream_error_status() {
+        let server = MockServer::start(vec![MockResponse::new(
+            401,
+            r#"{"error":{"message":"Incorrect API key provided","type":"invalid_request_error","param":null,"code":"invalid_api_key"}}"#,
+        )])
+        .await;
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url(server.url.clone())
+            .build()
+            .expect("Failed to create ChatGPT");
+
+        let error = chat_gpt
+            .completion_stream()
+            .await
+            .err()
+            .expect("The stream should fail");
+        assert_eq!(error.status(), Some(401));
+        assert_eq!(
+            error.api_error().and_then(|e| e.code.clone()),
+            Some("invalid_api_key".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_completion_rate_limited() {
+        let server = MockServer::start(vec![MockResponse::new(
+            429,
+            r#"{
+    "error": {
+        "message": "Rate limit reached for default-gpt-3.5-turbo in organization org-123 on requests per min. Limit: 3 / min.",
+        "type": "requests",
+        "param": null,
+        "code": "rate_limit_exceeded"
+    }
+}"#,
+        )
+        .header("retry-after", "20")
+        .header("x-ratelimit-limit-requests", "3")
+        .header("x-ratelimit-remaining-requests", "0")
+        .header("x-ratelimit-reset-requests", "20s")])
+        .await;
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url(server.url.clone())
+            .build()
+            .expect("Failed to create ChatGPT");
+
+        let error = chat_gpt
+            .completion_managed("Hello".to_string())
+            .await
+            .expect_err("The completion should fail");
+        assert!(error.is_rate_limited());
+        assert_eq!(error.code(), Some("rate_limit_exceeded"));
+        assert_eq!(
+            error.api_error().and_then(|e| e.type_.clone()),
+            Some("requests".to_string())
+        );
+        assert_eq!(
+            error.retry_after(),
+            Some(std::time::Duration::from_secs(20))
+        );
+        let rate_limit = error.rate_limit().expect("There are no rate limits");
+        assert_eq!(rate_limit.limit_requests, Some(3));
+        assert_eq!(rate_limit.remaining_requests, Some(0));
+        assert_eq!(
+            rate_limit.reset_requests,
+            Some(std::time::Duration::from_secs(20))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_completion_context_length_exceeded() {
+        let server = MockServer::start(vec![MockResponse::new(
+            400,
+            r#"{"error":{"message":"This model's maximum context length is 4097 tokens. However, your messages resulted in 5000 tokens.","type":"invalid_request_error","param":"messages","code":"context_length_exceeded"}}"#,
+        )])
+        .await;
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url(server.url.clone())
+            .build()
+            .expect("Failed to create ChatGPT");
+
+        let error = chat_gpt
+            .completion()
+            .await
+            .expect_err("The completion should fail");
+        assert!(error.is_context_length_exceeded());
+        assert_eq!(
+            error.api_error().and_then(|e| e.param.clone()),
+            Some("messages".to_string())
+        );
+        assert_eq!(error.retry_after(), None);
+    }
+
+    #[tokio::test]
+    async fn test_completion_retries_transient_errors() {
+        let server = MockServer::start(vec![
+            MockResponse::new(503, "Service Unavailable"),
+            MockResponse::new(
+                429,
+                r#"{"error":{"message":"Rate limit reached","type":"requests","param":null,"code":"rate_limit_exceeded"}}"#,
+            )
+            .header("retry-after-ms", "10"),
+            MockResponse::new(
+                200,
+                &answer("Hi!", usage(9, 2)),
+            ),
+        ])
+        .await;
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url(server.url.clone())
+            .retry_policy(
+                RetryPolicy::new()
+                    .max_attempts(3)
+                    .base_delay(std::time::Duration::from_millis(1)),
+            )
+            .build()
+            .expect("Failed to create ChatGPT");
+
+        let answer = chat_gpt
+            .completion_managed("Hello".to_string())
+            .await
+            .expect("The completion should succeed after retrying");
+        assert_eq!(answer.content(), Some("Hi!".to_string()));
+
+        // Every attempt sends the same context, and the message is only pushed once
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests
+            .iter()
+            .all(|r| r.body == chat_gpt_request_body("Hello")));
+        assert_eq!(chat_gpt.chat_context.messages.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_completion_gives_up_after_max_attempts() {
+        let server = MockServer::start(vec![
+            MockResponse::new(500, "Internal Server Error"),
+            MockResponse::new(502, "Bad Gateway"),
+            MockResponse::new(200, "Not reached"),
+        ])
+        .await;
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url(server.url.clone())
+            .retry_policy(
+                RetryPolicy::new()
+                    .max_attempts(2)
+                    .base_delay(std::time::Duration::from_millis(1)),
+            )
+            .build()
+            .expect("Failed to create ChatGPT");
+
+        let error = chat_gpt
+            .completion_managed("Hello".to_string())
+            .await
+            .expect_err("The completion should fail");
+        assert_eq!(error.status(), Some(502));
+        assert_eq!(server.requests().len(), 2);
+        assert_eq!(chat_gpt.chat_context.messages.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_completion_does_not_retry_client_errors() {
+        let server = MockServer::start(vec![MockResponse::new(
+            401,
+            r#"{"error":{"message":"Incorrect API key provided","type":"invalid_request_error","param":null,"code":"invalid_api_key"}}"#,
+        )])
+        .await;
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url(server.url.clone())
+            .retry_policy(RetryPolicy::new().base_delay(std::time::Duration::from_millis(1)))
+            .build()
+            .expect("Failed to create ChatGPT");
+
+        let error = chat_gpt
+            .completion()
+            .await
+            .expect_err("The completion should fail");
+        assert!(error.is_authentication());
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_chat_gpt_with_parameters() {
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .temperature(0.5)
+            .max_tokens(64)
+            .seed(7)
+            .build()
+            .expect("Failed to create ChatGPT");
+        assert_eq!(
+            chat_gpt.chat_context.parameters,
+            CompletionParameters::new()
+                .temperature(0.5)
+                .max_tokens(64)
+                .seed(7)
+        );
+
+        chat_gpt.set_temperature(1.0);
+        chat_gpt.set_stop(vec!["END".to_string()]);
+        assert_eq!(chat_gpt.chat_context.parameters.temperature, Some(1.0));
+        assert_eq!(
+            chat_gpt.chat_context.parameters.stop,
+            Some(vec!["END".to_string()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_completion_with_parameters_overrides() {
+        let response = answer("Hi!", usage(9, 2));
+        let server = MockServer::start(vec![
+            MockResponse::new(200, &response),
+            MockResponse::new(200, &response),
+        ])
+        .await;
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url(server.url.clone())
+            .temperature(0.7)
+            .user("user-1".to_string())
+            .build()
+            .expect("Failed to create ChatGPT");
+
+        chat_gpt
+            .completion_managed_with_parameters(
+                "Hello".to_string(),
+                &CompletionParameters::new().temperature(0.0).max_tokens(5),
+            )
+            .await
+            .expect("Failed to get the completion");
+        chat_gpt
+            .completion()
+            .await
+            .expect("Failed to get the completion");
+
+        let requests = server.requests();
+        let first: serde_json::Value =
+            serde_json::from_str(&requests[0].body).expect("Invalid JSON");
+        assert_eq!(first["temperature"], 0.0);
+        assert_eq!(first["max_tokens"], 5);
+        assert_eq!(first["user"], "user-1");
+
+        // The overrides are not kept in the context
+        let second: serde_json::Value =
+            serde_json::from_str(&requests[1].body).expect("Invalid JSON");
+        assert_eq!(second["temperature"], 0.7);
+        assert_eq!(second.get("max_tokens"), None);
+        assert_eq!(chat_gpt.chat_context.messages.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_completion_applies_truncation_strategies() {
+        let response = answer("Hi!", usage(9, 2));
+        let server = MockServer::start(vec![
+            MockResponse::new(200, &response),
+            MockResponse::new(200, &response),
+        ])
+        .await;
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url(server.url.clone())
+            .truncation_strategy(KeepLastMessages::new(2))
+            .build()
+            .expect("Failed to create ChatGPT");
+        chat_gpt.push_message(Message::system("You are a helpful assistant".to_string()));
+
+        chat_gpt
+            .completion_managed("Hello".to_string())
+            .await
+            .expect("Failed to get the completion");
+        chat_gpt
+            .completion_managed("Bye".to_string())
+            .await
+            .expect("Failed to get the completion");
+
+        let requests = server.requests();
+        let second: serde_json::Value =
+            serde_json::from_str(&requests[1].body).expect("Invalid JSON");
+        let contents: Vec<&str> = second["messages"]
+            .as_array()
+            .expect("There are no messages")
+            .iter()
+            .map(|m| m["content"].as_str().unwrap_or_default())
+            .collect();
+        assert_eq!(contents, vec!["You are a helpful assistant", "Hi!", "Bye"]);
+        // The removed messages are not in the context anymore
+        assert_eq!(chat_gpt.chat_context.messages.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_completion_compacts_the_context() {
+        let summary = answer("The user is called Ana", usage(30, 6));
+        let answer = answer("Your name is Ana", usage(20, 5));
+        let server = MockServer::start(vec![
+            MockResponse::new(200, &summary),
+            MockResponse::new(200, &answer),
+        ])
+        .await;
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url(server.url.clone())
+            .compaction(Compaction::new(0).keep_recent(1))
+            .build()
+            .expect("Failed to create ChatGPT");
+        chat_gpt.push_message(Message::new_user_message("My name is Ana".to_string()));
+        chat_gpt.push_message(Message::assistant("Hi Ana".to_string()));
+
+        let response = chat_gpt
+            .completion_managed("What is my name?".to_string())
+            .await
+            .expect("Failed to get the completion");
+        assert_eq!(response.content(), Some("Your name is Ana".to_string()));
+
+        let requests = server.requests();
+        let summary_request: serde_json::Value =
+            serde_json::from_str(&requests[0].body).expect("Invalid JSON");
+        assert_eq!(
+            summary_request["messages"][1]["content"],
+            "user: My name is Ana\nassistant: Hi Ana"
+        );
+        let request: serde_json::Value =
+            serde_json::from_str(&requests[1].body).expect("Invalid JSON");
+        assert_eq!(
+            request["messages"],
+            serde_json::json!([
+                {"role": "system", "content": "The user is called Ana", "name": "conversation_summary"},
+                {"role": "user", "content": "What is my name?"}
+            ])
+        );
+        assert_eq!(chat_gpt.chat_context.messages.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_compact_without_compaction() {
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .build()
+            .expect("Failed to create ChatGPT");
+        let error = chat_gpt
+            .compact()
+            .await
+            .expect_err("There is no compaction");
+        assert!(matches!(error, Error::MissingConfiguration(_)));
+    }
+
+    #[tokio::test]
+    async fn test_resume_session_from_the_store() {
+        let response = answer("Hi!", usage(9, 2));
+        let server = MockServer::start(vec![
+            MockResponse::new(200, &response),
+            MockResponse::new(200, &response),
+        ])
+        .await;
+        let directory = std::env::temp_dir().join(format!("chatgpt-functions-{}", Uuid::new_v4()));
+        let store = Arc::new(FileStore::new(&directory).expect("Failed to create the store"));
+
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url(server.url.clone())
+            .conversation_store(store.clone())
+            .resume_session("user-42".to_string())
+            .build()
+            .expect("Failed to create ChatGPT");
+        assert_eq!(chat_gpt.session_id, "user-42");
+        assert!(chat_gpt.chat_context.messages.is_empty());
+        chat_gpt
+            .completion_managed("Hello".to_string())
+            .await
+            .expect("Failed to get the completion");
+        assert_eq!(store.list().expect("Failed to list"), vec!["user-42"]);
+
+        let mut resumed = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url(server.url.clone())
+            .conversation_store(store.clone())
+            .resume_session("user-42".to_string())
+            .build()
+            .expect("Failed to create ChatGPT");
+        assert_eq!(
+            resumed.chat_context.messages,
+            chat_gpt.chat_context.messages
+        );
+        resumed
+            .completion_managed("Hello again".to_string())
+            .await
+            .expect("Failed to get the completion");
+        let second: serde_json::Value =
+            serde_json::from_str(&server.requests()[1].body).expect("Invalid JSON");
+        assert_eq!(second["messages"].as_array().map(|m| m.len()), Some(3));
+        let saved = store
+            .load("user-42")
+            .expect("Failed to load")
+            .expect("The session was not saved");
+        assert_eq!(saved.messages.len(), 4);
+
+        std::fs::remove_dir_all(directory).expect("Failed to remove the directory");
+    }
+
+    #[test]
+    fn test_resume_session_without_store() {
+        let result = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .resume_session("user-42".to_string())
+            .build();
+        assert!(matches!(result, Err(Error::MissingConfiguration(_))));
+    }
+
+    #[tokio::test]
+    async fn test_system_prompt() {
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .system_prompt("You help {{user}} with their orders".to_string())
+            .system_prompt_variable("user".to_string(), "Ana".to_string())
+            .truncation_strategy(KeepLastMessages::new(1))
+            .build()
+            .expect("Failed to create ChatGPT");
+        assert_eq!(
+            chat_gpt.chat_context.system_prompt(),
+            Some("You help Ana with their orders")
+        );
+
+        chat_gpt
+            .set_system_prompt_variable("user".to_string(), "Bob".to_string())
+            .expect("Failed to set the variable");
+        chat_gpt.set_messages(vec![
+            Message::user("Hi".to_string()),
+            Message::assistant("Hello Bob".to_string()),
+        ]);
+        chat_gpt
+            .prepare_context()
+            .await
+            .expect("Failed to prepare the context");
+        assert_eq!(
+            chat_gpt.chat_context.messages,
+            vec![
+                Message::system("You help Bob with their orders".to_string()),
+                Message::assistant("Hello Bob".to_string()),
+            ]
+        );
+
+        let error = chat_gpt
+            .set_system_prompt("You help {{user}} at {{shop}}".to_string())
+            .expect_err("The shop has no value");
+        assert!(matches!(error, Error::InvalidMessage(_)));
+        assert_eq!(
+            chat_gpt.chat_context.system_prompt(),
+            Some("You help Bob with their orders")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_completion_forcing_function() {
+        let server = MockServer::start(vec![
+            MockResponse::new(
+                200,
+                &function_call(
+                    "extract_person",
+                    "{\"name\":\"Ada\",\"age\":36}",
+                    usage(60, 12),
+                ),
+            ),
+            MockResponse::new(200, &answer("I can't", usage(60, 2))),
+        ])
+        .await;
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url(server.url.clone())
+            .build()
+            .expect("Failed to create ChatGPT");
+        chat_gpt.push_function(FunctionSpecification::new(
+            "extract_person".to_string(),
+            None,
+            None,
+        ));
+
+        let function_call = chat_gpt
+            .completion_forcing_function("extract_person", "Ada is 36".to_string())
+            .await
+            .expect("Failed to get the completion");
+        assert_eq!(function_call.arguments, "{\"name\":\"Ada\",\"age\":36}");
+
+        let request: serde_json::Value =
+            serde_json::from_str(&server.requests()[0].body).expect("Invalid JSON");
+        assert_eq!(request["function_call"]["name"], "extract_person");
+        // Nothing is kept in the context
+        assert!(chat_gpt.chat_context.messages.is_empty());
+        assert_eq!(chat_gpt.chat_context.function_call, None);
+
+        let error = chat_gpt
+            .completion_forcing_function("extract_person", "Nothing".to_string())
+            .await
+            .expect_err("The model did not call the function");
+        assert!(matches!(error, Error::UnexpectedResponse(_)));
+    }
+
+    #[tokio::test]
+    async fn test_completion_with_parallel_tool_calls() {
+        let server = MockServer::start(vec![
+            MockResponse::new(
+                200,
+                &tool_calls(
+                    &[
+                        (
+                            "call_1",
+                            "get_current_weather",
+                            "{\"location\": \"Madrid\"}",
+                        ),
+                        ("call_2", "get_current_weather", "{\"location\": \"Paris\"}"),
+                    ],
+                    usage(82, 51),
+                ),
+            ),
+            MockResponse::new(200, &answer("It is sunny in both.", usage(150, 6))),
+        ])
+        .await;
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url(server.url.clone())
+            .build()
+            .expect("Failed to create ChatGPT");
+        chat_gpt.push_tool(Tool::function(FunctionSpecification::new(
+            "get_current_weather".to_string(),
+            None,
+            None,
+        )));
+
+        chat_gpt
+            .completion_managed("Weather in Madrid and Paris?".to_string())
+            .await
+            .expect("Failed to get the completion");
+        let tool_calls = chat_gpt.last_tool_calls().expect("There are no tool calls");
+        assert_eq!(tool_calls.len(), 2);
+        for tool_call in tool_calls {
+            chat_gpt.push_tool_result(tool_call.id, "sunny".to_string());
+        }
+        let answer = chat_gpt
+            .completion()
+            .await
+            .expect("Failed to get the completion");
+        assert_eq!(answer.content(), Some("It is sunny in both.".to_string()));
+
+        let request: serde_json::Value =
+            serde_json::from_str(&server.requests()[1].body).expect("Invalid JSON");
+        assert_eq!(
+            request["tools"][0]["function"]["name"],
+            "get_current_weather"
+        );
+        let messages = request["messages"].as_array().expect("No messages");
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[1]["tool_calls"][1]["id"], "call_2");
+        assert_eq!(messages[2]["role"], "tool");
+        assert_eq!(messages[2]["tool_call_id"], "call_1");
+        assert_eq!(messages[3]["tool_call_id"], "call_2");
+    }
+
+    fn weather_registry() -> FunctionRegistry {
+        let mut registry = FunctionRegistry::new();
+        registry.register(
+            FunctionSpecification::new("get_current_weather".to_string(), None, None),
+            |arguments| async move {
+                let arguments: serde_json::Value = serde_json::from_str(&arguments)?;
+                Ok(format!(
+                    "Sunny in {}",
+                    arguments["location"].as_str().unwrap_or("?")
+                ))
+            },
+        );
+        registry
+    }
+
+    #[tokio::test]
+    async fn test_run_until_answer_with_function_call() {
+        let server = MockServer::start(vec![
+            MockResponse::new(
+                200,
+                &function_call(
+                    "get_current_weather",
+                    "{\"location\":\"Madrid\"}",
+                    usage(60, 12),
+                ),
+            ),
+            MockResponse::new(200, &answer("It is sunny in Madrid.", usage(80, 6))),
+        ])
+        .await;
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url(server.url.clone())
+            .build()
+            .expect("Failed to create ChatGPT");
+        let registry = weather_registry();
+        chat_gpt.set_functions(registry.specifications());
+
+        let answer = chat_gpt
+            .run_until_answer("Weather in Madrid?".to_string(), &registry)
+            .await
+            .expect("Failed to get the answer");
+        assert_eq!(answer.content(), Some("It is sunny in Madrid.".to_string()));
+
+        let request: serde_json::Value =
+            serde_json::from_str(&server.requests()[1].body).expect("Invalid JSON");
+        let messages = request["messages"].as_array().expect("No messages");
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[2]["role"], "function");
+        assert_eq!(messages[2]["name"], "get_current_weather");
+        assert_eq!(messages[2]["content"], "Sunny in Madrid");
+        assert_eq!(chat_gpt.chat_context.messages.len(), 4);
+        assert_eq!(
+            chat_gpt.last_content(),
+            Some("It is sunny in Madrid.".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_until_answer_with_tool_calls() {
+        let server = MockServer::start(vec![
+            MockResponse::new(
+                200,
+                &tool_calls(
+                    &[
+                        (
+                            "call_1",
+                            "get_current_weather",
+                            "{\"location\": \"Madrid\"}",
+                        ),
+                        ("call_2", "get_current_weather", "{\"location\": \"Paris\"}"),
+                    ],
+                    usage(82, 51),
+                ),
+            ),
+            MockResponse::new(200, &answer("It is sunny in both.", usage(150, 6))),
+        ])
+        .await;
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url(server.url.clone())
+            .build()
+            .expect("Failed to create ChatGPT");
+        let registry = weather_registry();
+        chat_gpt.set_tools(registry.tools());
+
+        let answer = chat_gpt
+            .run_until_answer("Weather in Madrid and Paris?".to_string(), &registry)
+            .await
+            .expect("Failed to get the answer");
+        assert_eq!(answer.content(), Some("It is sunny in both.".to_string()));
+
+        let request: serde_json::Value =
+            serde_json::from_str(&server.requests()[1].body).expect("Invalid JSON");
+        let messages = request["messages"].as_array().expect("No messages");
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[2]["tool_call_id"], "call_1");
+        assert_eq!(messages[2]["content"], "Sunny in Madrid");
+        assert_eq!(messages[3]["tool_call_id"], "call_2");
+        assert_eq!(messages[3]["content"], "Sunny in Paris");
+    }
+
+    #[tokio::test]
+    async fn test_run_until_answer_max_iterations() {
+        let function_call = function_call(
+            "get_current_weather",
+            "{\"location\":\"Madrid\"}",
+            usage(60, 12),
+        );
+        let server = MockServer::start(vec![
+            MockResponse::new(200, &function_call),
+            MockResponse::new(200, &function_call),
+            MockResponse::new(200, &function_call),
+        ])
+        .await;
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url(server.url.clone())
+            .max_function_iterations(2)
+            .build()
+            .expect("Failed to create ChatGPT");
+        let registry = weather_registry();
+
+        let error = chat_gpt
+            .run_until_answer("Weather in Madrid?".to_string(), &registry)
+            .await
+            .expect_err("The model never answers");
+        assert!(matches!(error, Error::MaxIterationsReached(2)));
+        assert_eq!(server.requests().len(), 3);
+        // The calls that were run are kept with their results, the last one is removed
+        let messages = &chat_gpt.chat_context.messages;
+        assert_eq!(messages.len(), 5);
+        assert_eq!(messages[4].role, Role::Function);
+    }
+
+    #[tokio::test]
+    async fn test_run_until_answer_unknown_function() {
+        let server = MockServer::start(vec![
+            MockResponse::new(
+                200,
+                &tool_calls(
+                    &[
+                        (
+                            "call_1",
+                            "get_current_weather",
+                            "{\"location\": \"Madrid\"}",
+                        ),
+                        ("call_2", "book_flight", "{}"),
+                    ],
+                    usage(60, 12),
+                ),
+            ),
+            MockResponse::new(200, &answer("It is sunny in Madrid.", usage(20, 6))),
+        ])
+        .await;
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url(server.url.clone())
+            .build()
+            .expect("Failed to create ChatGPT");
+
+        let error = chat_gpt
+            .run_until_answer("Book a flight".to_string(), &weather_registry())
+            .await
+            .expect_err("The function is not registered");
+        assert!(matches!(error, Error::UnknownFunction(name) if name == "book_flight"));
+
+        // The calls without results are not sent with the next request
+        chat_gpt
+            .completion_managed("Only the weather then".to_string())
+            .await
+            .expect("Failed to get the completion");
+        let request: serde_json::Value =
+            serde_json::from_str(&server.requests()[1].body).expect("Invalid JSON");
+        let messages = request["messages"].as_array().expect("No messages");
+        assert_eq!(messages.len(), 2);
+        assert!(messages.iter().all(|m| m["role"] == "user"));
+    }
+
+    fn weather_specification() -> FunctionSpecification {
+        serde_json::from_str(
+            r#"{"name":"get_current_weather","parameters":{"type":"object","properties":{"location":{"type":"string"}},"required":["location"]}}"#,
+        )
+        .expect("Invalid specification")
+    }
+
+    #[tokio::test]
+    async fn test_run_until_answer_corrects_invalid_arguments() {
+        let server = MockServer::start(vec![
+            MockResponse::new(
+                200,
+                &function_call(
+                    "get_current_weather",
+                    "{\"city\":\"Madrid\"}",
+                    usage(60, 12),
+                ),
+            ),
+            MockResponse::new(
+                200,
+                &function_call(
+                    "get_current_weather",
+                    "{\"location\":\"Madrid\"}",
+                    usage(90, 12),
+                ),
+            ),
+            MockResponse::new(200, &answer("It is sunny in Madrid.", usage(110, 6))),
+        ])
+        .await;
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url(server.url.clone())
+            .validation_mode(ValidationMode::Correct)
+            .build()
+            .expect("Failed to create ChatGPT");
+        chat_gpt.push_function(weather_specification());
+
+        let answer = chat_gpt
+            .run_until_answer("Weather in Madrid?".to_string(), &weather_registry())
+            .await
+            .expect("Failed to get the answer");
+        assert_eq!(answer.content(), Some("It is sunny in Madrid.".to_string()));
+
+        let request: serde_json::Value =
+            serde_json::from_str(&server.requests()[1].body).expect("Invalid JSON");
+        let correction = request["messages"][2]["content"]
+            .as_str()
+            .expect("No correction");
+        assert!(
+            correction.contains("`location` is required"),
+            "{}",
+            correction
+        );
+        let request: serde_json::Value =
+            serde_json::from_str(&server.requests()[2].body).expect("Invalid JSON");
+        assert_eq!(request["messages"][4]["content"], "Sunny in Madrid");
+    }
+
+    #[tokio::test]
+    async fn test_run_until_answer_rejects_invalid_arguments() {
+        let server = MockServer::start(vec![MockResponse::new(
+            200,
+            &function_call("get_current_weather", "{\"location\": 42}", usage(60, 12)),
+        )])
+        .await;
+        let mut chat_gpt = ChatGPTBuilder::new()
+            .openai_api_token("key".to_string())
+            .base_url(server.url.clone())
+            .validation_mode(ValidationMode::Reject)
+            .build()
+            .expect("Failed to create ChatGPT");
+        chat_gpt.push_function(weather_specification());
+
+        let error = chat_gpt
+            .run_until_answer("Weather in Madrid?".to_string(), &weather_registry())
+            .await
+            .expect_err("The arguments are not valid");
+        assert_eq!(
+            error.to_string(),
+            "The arguments of the call to get_current_weather are not valid: `location` must be of type string, not integer"
+        );
+    }
+
+    #[test]
+    fn test_chat_gpt_missing_token() {
+        let error = ChatGPTBuilder::new()
+            .build()
+            .err()
+            .expect("The token should be required");
+        assert!(matches!(error, Error::MissingConfiguration(_)));
+    }
+
     #[test]
     fn test_chat_gpt_push_message() {
         let mut chat_gpt = ChatGPTBuilder::new()
@@ -423,7 +2657,7 @@ mod tests {
         let function = chat_gpt
             .chat_context
             .functions
-            .get(0)
+            .first()
             .expect("Failed to get the function");
         assert_eq!(function.name, "function");
         assert_eq!(
@@ -444,7 +2678,7 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_removing_newlines() {
+    fn test_parse_keeps_escaped_newlines() {
         use crate::message::FunctionCall;
 
         let r = r#"{
@@ -473,7 +2707,7 @@ mod tests {
     }
 }"#
         .to_string();
-        let response = parse_removing_newlines(r).expect("Failed to parse");
+        let response = ChatResponse::from_json(&r).expect("Failed to parse");
         let message = response
             .choices
             .first()
@@ -481,7 +2715,7 @@ mod tests {
             .message
             .clone();
 
-        assert_eq!(message.role, "assistant");
+        assert_eq!(message.role, Role::Assistant);
         assert_eq!(message.content, None);
         assert_eq!(message.name, None);
         assert_eq!(
@@ -494,13 +2728,13 @@ mod tests {
     }
 
     #[test]
-    fn test_fix_context_when_function_replied_with_content() {
+    fn test_parse_keeps_raw_newlines_in_arguments() {
         use crate::message::FunctionCall;
 
         let r = r#"{"id":"chatcmpl-7VneSVRn9qJ1crw3m0V0kmnCq8Pnn","object":"chat.completion","created":1687813384,"choices":[{"index":0,"message":{"role":"assistant","function_call":{"name":"completion_managed","arguments":"{
     \"content\": \"Hi, model!\"
 }"}},"finish_reason":"function_call"}],"usage":{"prompt_tokens":61,"completion_tokens":18,"total_tokens":79}}"#.to_string();
-        let response = parse_removing_newlines(r).expect("Failed to parse");
+        let response = ChatResponse::from_json(&r).expect("Failed to parse");
         let message = response
             .choices
             .last()
@@ -508,14 +2742,14 @@ mod tests {
             .message
             .clone();
 
-        assert_eq!(message.role, "assistant");
+        assert_eq!(message.role, Role::Assistant);
         assert_eq!(message.content, None);
         assert_eq!(message.name, None);
         assert_eq!(
             message.function_call,
             Some(FunctionCall {
                 name: "completion_managed".to_string(),
-                arguments: "{    \"content\": \"Hi, model!\"}".to_string(),
+                arguments: "{\n    \"content\": \"Hi, model!\"\n}".to_string(),
             })
         );
     }
@@ -550,19 +2784,19 @@ mod tests {
             .openai_api_token("key".to_string())
             .build()
             .expect("Failed to create ChatGPT");
-        let mut msg = Message::new("function".to_string());
+        let mut msg = Message::new(Role::Assistant);
         msg.set_function_call(FunctionCall {
             name: "function".to_string(),
             arguments: "1".to_string(),
         });
         chat_gpt.push_message(msg);
-        let mut msg = Message::new("function2".to_string());
+        let mut msg = Message::new(Role::Assistant);
         msg.set_function_call(FunctionCall {
             name: "function2".to_string(),
             arguments: "2".to_string(),
         });
         chat_gpt.push_message(msg);
-        let mut msg = Message::new("function3".to_string());
+        let mut msg = Message::new(Role::Assistant);
         msg.set_function_call(FunctionCall {
             name: "function3".to_string(),
             arguments: "3".to_string(),
@@ -1,45 +1,107 @@
+use std::ops::{Add, AddAssign};
+
 use serde::{Deserialize, Serialize};
-use std::fmt;
 
-use crate::message::Message;
+use crate::{
+    error::{Error, Result},
+    escape_json::escape_control_characters,
+    message::{Message, ToolCall},
+};
 
+/// One of the candidate answers of a response, there are `n` of them
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Choice {
-    index: u64,
+    pub index: u64,
     pub message: Message,
     pub finish_reason: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Chooses which of the choices of a response is committed to the context,
+/// returning its index. See ChatGPTBuilder::choice_selector
+pub type ChoiceSelector = Box<dyn Fn(&[Choice]) -> u64 + Send + Sync>;
+
+/// The tokens used by a completion, as counted by the API
+///
+/// The usages can be added, ChatGPT keeps the total of the session in ChatContext::usage.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Usage {
-    prompt_tokens: u32,
-    completion_tokens: u32,
-    total_tokens: u32,
+    /// The tokens of the messages and functions sent
+    pub prompt_tokens: u32,
+    /// The tokens generated by the model
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
+}
+
+impl Usage {
+    pub fn is_empty(&self) -> bool {
+        *self == Usage::default()
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Add for Usage {
+    type Output = Usage;
+
+    // The totals of a long session stop at u32::MAX instead of overflowing
+    fn add(self, other: Usage) -> Usage {
+        Usage {
+            prompt_tokens: self.prompt_tokens.saturating_add(other.prompt_tokens),
+            completion_tokens: self
+                .completion_tokens
+                .saturating_add(other.completion_tokens),
+            total_tokens: self.total_tokens.saturating_add(other.total_tokens),
+        }
+    }
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, other: Usage) {
+        *self = *self + other;
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ChatResponse {
-    id: String,
-    object: String,
-    created: u64,
+    pub id: String,
+    pub object: String,
+    /// When the completion was created, as a Unix timestamp in seconds
+    pub created: u64,
     pub choices: Vec<Choice>,
-    usage: Usage,
+    pub usage: Usage,
 }
 
 impl ChatResponse {
+    /// Parses the body of a response of the API
+    ///
+    /// The content is kept exactly as the model wrote it. When the body is not valid JSON
+    /// because the strings have raw control characters, like the newlines of code or of the arguments
+    /// of a function call, they are escaped before parsing it, so they are kept too.
+    /// # Errors
+    /// It returns Error::Deserialize if the body is not a valid response
+    pub fn from_json(body: &str) -> Result<ChatResponse> {
+        serde_json::from_str(body).or_else(|source| {
+            let escaped = escape_control_characters(body);
+            if escaped == body {
+                return Err(Error::Deserialize {
+                    source,
+                    body: body.to_string(),
+                });
+            }
+            serde_json::from_str(&escaped).map_err(|_| Error::Deserialize {
+                source,
+                body: body.to_string(),
+            })
+        })
+    }
+
+    /// Returns the content of the first choice
     pub fn content(&self) -> Option<String> {
         match self.choices.first() {
-            Some(choice) => {
-                if let Some(c) = choice.message.content.clone() {
-                    Some(c)
-                } else {
-                    None
-                }
-            }
+            Some(choice) => choice.message.content.clone(),
             None => None,
         }
     }
 
+    /// Returns the name and the arguments of the function called in the first choice
     pub fn function_call(&self) -> Option<(String, String)> {
         match self.choices.first() {
             Some(choice) => {
@@ -53,63 +115,38 @@ impl ChatResponse {
         }
     }
 
-    /// Returns the message of the first choice
-    /// This is the message that the bot will send
-    pub fn message(&self) -> Option<Message> {
+    /// Returns the tool calls of the first choice.
+    /// Newer models can call several tools in the same message
+    pub fn tool_calls(&self) -> Option<Vec<ToolCall>> {
         match self.choices.first() {
-            Some(choice) => Some(choice.message.clone()),
+            Some(choice) => choice.message.tool_calls.clone(),
             None => None,
         }
     }
-}
 
-impl fmt::Display for Choice {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{{\"index\":{},\"message\":{},\"finish_reason\":\"{}\"}}",
-            self.index, self.message, self.finish_reason
-        )
+    /// Returns the message of the first choice
+    /// This is the message that the bot will send
+    pub fn message(&self) -> Option<Message> {
+        self.choices.first().map(|choice| choice.message.clone())
     }
-}
 
-impl fmt::Display for ChatResponse {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{{\"id\":\"{}\",\"object\":\"{}\",\"created\":{},\"choices\":[",
-            self.id, self.object, self.created
-        )?;
-        for (i, choice) in self.choices.iter().enumerate() {
-            write!(
-                f,
-                "{}{}",
-                choice,
-                if i == self.choices.len() - 1 { "" } else { "," }
-            )?;
-        }
-        write!(f, "],\"usage\":{}}}", self.usage)
+    /// Returns the choice with the given index, when several are requested with `n`
+    pub fn choice(&self, index: u64) -> Option<&Choice> {
+        self.choices.iter().find(|choice| choice.index == index)
     }
 }
 
-impl fmt::Display for Usage {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{{\"prompt_tokens\":{},\"completion_tokens\":{},\"total_tokens\":{}}}",
-            self.prompt_tokens, self.completion_tokens, self.total_tokens
-        )
-    }
-}
+display_as_json!(Choice, ChatResponse, Usage);
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::message::{FunctionCall, MessageBuilder};
+    use crate::message::{FunctionCall, MessageBuilder, Role};
 
     #[test]
     fn test_last_content() {
         let message = MessageBuilder::new()
+            .role(Role::Assistant)
             .content("content".to_string())
             .build()
             .unwrap();
@@ -134,7 +171,7 @@ mod tests {
     #[test]
     fn test_last_function_call() {
         let message = MessageBuilder::new()
-            .role("role".to_string())
+            .role(Role::Assistant)
             .content("content".to_string())
             .name("name".to_string())
             .function_call(FunctionCall {
@@ -165,10 +202,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_tool_calls() {
+        let chat_response: ChatResponse = serde_json::from_str(
+            r#"{
+                "id": "chatcmpl-abc",
+                "object": "chat.completion",
+                "created": 1699896916,
+                "choices": [{
+                    "index": 0,
+                    "message": {
+                        "role": "assistant",
+                        "content": null,
+                        "tool_calls": [
+                            {"id": "call_1", "type": "function", "function": {"name": "get_current_weather", "arguments": "{\"location\": \"Madrid\"}"}},
+                            {"id": "call_2", "type": "function", "function": {"name": "get_current_weather", "arguments": "{\"location\": \"Paris\"}"}}
+                        ]
+                    },
+                    "finish_reason": "tool_calls"
+                }],
+                "usage": {"prompt_tokens": 82, "completion_tokens": 51, "total_tokens": 133}
+            }"#,
+        )
+        .expect("Failed to parse the response");
+        let tool_calls = chat_response.tool_calls().expect("There are no tool calls");
+        assert_eq!(tool_calls.len(), 2);
+        assert_eq!(tool_calls[0].id, "call_1");
+        assert_eq!(
+            tool_calls[1].function.arguments,
+            "{\"location\": \"Paris\"}"
+        );
+        assert_eq!(chat_response.function_call(), None);
+    }
+
     #[test]
     fn test_message() {
         let message = MessageBuilder::new()
-            .role("role".to_string())
+            .role(Role::Assistant)
             .content("content".to_string())
             .name("name".to_string())
             .function_call(FunctionCall {
@@ -199,7 +269,7 @@ mod tests {
     #[test]
     fn test_display_for_choice() {
         let message = MessageBuilder::new()
-            .role("role".to_string())
+            .role(Role::Assistant)
             .content("content".to_string())
             .name("name".to_string())
             .function_call(FunctionCall {
@@ -215,14 +285,14 @@ mod tests {
         };
         assert_eq!(
             format!("{}", choice),
-            "{\"index\":0,\"message\":{\"role\":\"role\",\"content\":\"content\",\"name\":\"name\",\"function_call\":{\"name\":\"name\",\"arguments\":\"{\\\"example\\\":\\\"this\\\"}\"}},\"finish_reason\":\"finish_reason\"}"
+            "{\"index\":0,\"message\":{\"role\":\"assistant\",\"content\":\"content\",\"name\":\"name\",\"function_call\":{\"name\":\"name\",\"arguments\":\"{\\\"example\\\":\\\"this\\\"}\"}},\"finish_reason\":\"finish_reason\"}"
         );
     }
 
     #[test]
     fn test_display_chat_response() {
         let message = MessageBuilder::new()
-            .role("role".to_string())
+            .role(Role::Assistant)
             .content("content".to_string())
             .name("name".to_string())
             .function_call(FunctionCall {
@@ -248,7 +318,7 @@ mod tests {
         };
         assert_eq!(
             format!("{}", chat_response),
-            "{\"id\":\"id\",\"object\":\"object\",\"created\":0,\"choices\":[{\"index\":0,\"message\":{\"role\":\"role\",\"content\":\"content\",\"name\":\"name\",\"function_call\":{\"name\":\"name\",\"arguments\":\"{\\\"example\\\":\\\"this\\\"}\"}},\"finish_reason\":\"finish_reason\"}],\"usage\":{\"prompt_tokens\":0,\"completion_tokens\":0,\"total_tokens\":0}}"
+            "{\"id\":\"id\",\"object\":\"object\",\"created\":0,\"choices\":[{\"index\":0,\"message\":{\"role\":\"assistant\",\"content\":\"content\",\"name\":\"name\",\"function_call\":{\"name\":\"name\",\"arguments\":\"{\\\"example\\\":\\\"this\\\"}\"}},\"finish_reason\":\"finish_reason\"}],\"usage\":{\"prompt_tokens\":0,\"completion_tokens\":0,\"total_tokens\":0}}"
         );
     }
 
@@ -264,4 +334,56 @@ mod tests {
             "{\"prompt_tokens\":0,\"completion_tokens\":0,\"total_tokens\":0}"
         );
     }
+
+    #[test]
+    fn test_choice() {
+        let response = ChatResponse::from_json(
+            r#"{"id":"1","object":"chat.completion","created":0,"choices":[{"index":0,"message":{"role":"assistant","content":"Hi"},"finish_reason":"stop"},{"index":1,"message":{"role":"assistant","content":"Hello"},"finish_reason":"length"}],"usage":{"prompt_tokens":9,"completion_tokens":4,"total_tokens":13}}"#,
+        )
+        .expect("Failed to parse the response");
+        assert_eq!(response.content(), Some("Hi".to_string()));
+        let second = response.choice(1).expect("There are two choices");
+        assert_eq!(second.message.content, Some("Hello".to_string()));
+        assert_eq!(second.finish_reason, "length");
+        assert!(response.choice(2).is_none());
+    }
+
+    #[test]
+    fn test_add_usage() {
+        let mut usage = Usage::default();
+        assert!(usage.is_empty());
+        usage += Usage {
+            prompt_tokens: 9,
+            completion_tokens: 2,
+            total_tokens: 11,
+        };
+        let total = usage
+            + Usage {
+                prompt_tokens: 20,
+                completion_tokens: 5,
+                total_tokens: 25,
+            };
+        assert_eq!(
+            total,
+            Usage {
+                prompt_tokens: 29,
+                completion_tokens: 7,
+                total_tokens: 36,
+            }
+        );
+
+        let full = Usage {
+            prompt_tokens: u32::MAX,
+            completion_tokens: u32::MAX - 1,
+            total_tokens: u32::MAX,
+        };
+        assert_eq!(
+            full + total,
+            Usage {
+                prompt_tokens: u32::MAX,
+                completion_tokens: u32::MAX,
+                total_tokens: u32::MAX,
+            }
+        );
+    }
 }
@@ -0,0 +1,511 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    pin::Pin,
+    task::{Context as TaskContext, Poll},
+};
+
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chat_response::{Choice, Usage},
+    error::{Error, Result},
+    message::{FunctionCall, Message, Role, ToolCall},
+    transport::ByteStream,
+};
+
+/// A chunk of a streamed chat completion, sent by the API as a server-sent event
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ChatResponseChunk {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub object: String,
+    #[serde(default)]
+    pub created: u64,
+    pub choices: Vec<ChunkChoice>,
+    /// Only sent in the last chunk, when the request asks for it with `stream_options`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ChunkChoice {
+    pub index: u64,
+    pub delta: Delta,
+    pub finish_reason: Option<String>,
+}
+
+/// The part of the message sent in a chunk.
+/// Every field is optional, the message is the concatenation of all the deltas
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Delta {
+    pub role: Option<Role>,
+    pub content: Option<String>,
+    pub function_call: Option<FunctionCallDelta>,
+    #[serde(default)]
+    pub tool_calls: Option<Vec<ToolCallDelta>>,
+}
+
+/// A fragment of a function call. The arguments arrive split over several chunks
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct FunctionCallDelta {
+    pub name: Option<String>,
+    pub arguments: Option<String>,
+}
+
+/// A fragment of a tool call. The first fragment of each call has the id and the name,
+/// the next ones with the same index have pieces of the arguments
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ToolCallDelta {
+    pub index: u64,
+    pub id: Option<String>,
+    #[serde(rename = "type")]
+    pub type_: Option<String>,
+    pub function: Option<FunctionCallDelta>,
+}
+
+impl ChatResponseChunk {
+    /// Returns the content of the delta of the first choice, if any
+    pub fn content(&self) -> Option<&str> {
+        self.choices
+            .first()
+            .and_then(|choice| choice.delta.content.as_deref())
+    }
+}
+
+/// Puts the final message back together from the deltas of one choice, the first one by default
+#[derive(Clone, Debug, Default)]
+pub struct MessageAccumulator {
+    index: u64,
+    role: Option<Role>,
+    content: Option<String>,
+    function_name: Option<String>,
+    function_arguments: Option<String>,
+    tool_calls: BTreeMap<u64, ToolCall>,
+    finish_reason: Option<String>,
+    usage: Option<Usage>,
+}
+
+impl MessageAccumulator {
+    pub fn new() -> MessageAccumulator {
+        MessageAccumulator::default()
+    }
+
+    /// Accumulates the choice with the given index, when several are requested with `n`
+    pub fn for_choice(index: u64) -> MessageAccumulator {
+        MessageAccumulator {
+            index,
+            ..MessageAccumulator::default()
+        }
+    }
+
+    /// Adds the delta of the choice of the accumulator to the message
+    pub fn push(&mut self, chunk: &ChatResponseChunk) {
+        if let Some(usage) = chunk.usage {
+            self.usage = Some(usage);
+        }
+        let Some(choice) = chunk.choices.iter().find(|c| c.index == self.index) else {
+            return;
+        };
+        if let Some(role) = &choice.delta.role {
+            self.role = Some(*role);
+        }
+        if let Some(content) = &choice.delta.content {
+            self.content
+                .get_or_insert_with(String::new)
+                .push_str(content);
+        }
+        if let Some(function_call) = &choice.delta.function_call {
+            if let Some(name) = &function_call.name {
+                self.function_name
+                    .get_or_insert_with(String::new)
+                    .push_str(name);
+            }
+            if let Some(arguments) = &function_call.arguments {
+                self.function_arguments
+                    .get_or_insert_with(String::new)
+                    .push_str(arguments);
+            }
+        }
+        for tool_call in choice.delta.tool_calls.iter().flatten() {
+            let call = self.tool_calls.entry(tool_call.index).or_insert_with(|| {
+                ToolCall::new(
+                    String::new(),
+                    FunctionCall {
+                        name: String::new(),
+                        arguments: String::new(),
+                    },
+                )
+            });
+            if let Some(id) = &tool_call.id {
+                call.id.push_str(id);
+            }
+            if let Some(type_) = &tool_call.type_ {
+                call.type_ = type_.clone();
+            }
+            if let Some(function) = &tool_call.function {
+                if let Some(name) = &function.name {
+                    call.function.name.push_str(name);
+                }
+                if let Some(arguments) = &function.arguments {
+                    call.function.arguments.push_str(arguments);
+                }
+            }
+        }
+        if let Some(finish_reason) = &choice.finish_reason {
+            self.finish_reason = Some(finish_reason.clone());
+        }
+    }
+
+    /// The message received so far
+    pub fn message(&self) -> Message {
+        let mut message = Message::new(self.role.unwrap_or(Role::Assistant));
+        message.content = self.content.clone();
+        if self.function_name.is_some() || self.function_arguments.is_some() {
+            message.set_function_call(FunctionCall {
+                name: self.function_name.clone().unwrap_or_default(),
+                arguments: self.function_arguments.clone().unwrap_or_default(),
+            });
+        }
+        if !self.tool_calls.is_empty() {
+            message.set_tool_calls(self.tool_calls.values().cloned().collect());
+        }
+        message
+    }
+
+    /// The reason why the model stopped, only available once the last chunk is received
+    pub fn finish_reason(&self) -> Option<String> {
+        self.finish_reason.clone()
+    }
+
+    /// The tokens used by the completion, if the API sent them
+    pub fn usage(&self) -> Option<Usage> {
+        self.usage
+    }
+}
+
+/// Splits a byte stream in server-sent events, returning the `data:` payload of each event
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    pub fn new() -> SseParser {
+        SseParser::default()
+    }
+
+    /// Feeds bytes to the parser, returning the data of the events completed by them
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some(position) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=position).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                // A blank line dispatches the event
+                if !self.data.is_empty() {
+                    events.push(self.data.join("\n"));
+                    self.data.clear();
+                }
+            } else if let Some(data) = line.strip_prefix("data:") {
+                self.data
+                    .push(data.strip_prefix(' ').unwrap_or(data).to_string());
+            }
+            // Comments (starting with ':') and other fields (event, id, retry) are ignored
+        }
+        events
+    }
+
+    /// Returns the last event if the stream ended without a blank line
+    pub fn finish(&mut self) -> Option<String> {
+        let rest = std::mem::take(&mut self.buffer);
+        let mut events = self.push(&rest);
+        events.extend(self.push(b"\n\n"));
+        events.pop()
+    }
+}
+
+/// A stream of chunks of a chat completion.
+///
+/// While the chunks are consumed, the message is put back together,
+/// and it can be retrieved with `message()` once the stream is finished.
+/// When several choices are requested with `n`, their chunks are interleaved,
+/// and every message is put back together, see `choices()`.
+pub struct ChatStream {
+    bytes: ByteStream,
+    parser: SseParser,
+    pending: VecDeque<Result<ChatResponseChunk>>,
+    accumulators: BTreeMap<u64, MessageAccumulator>,
+    usage: Option<Usage>,
+    done: bool,
+}
+
+impl ChatStream {
+    pub(crate) fn new(bytes: ByteStream) -> ChatStream {
+        ChatStream {
+            bytes,
+            parser: SseParser::new(),
+            pending: VecDeque::new(),
+            accumulators: BTreeMap::new(),
+            usage: None,
+            done: false,
+        }
+    }
+
+    /// The message of the first choice received so far, complete once the stream is finished
+    pub fn message(&self) -> Message {
+        self.accumulators
+            .get(&0)
+            .map_or_else(|| MessageAccumulator::new().message(), |a| a.message())
+    }
+
+    /// The reason why the model stopped the first choice, only available once the stream is finished
+    pub fn finish_reason(&self) -> Option<String> {
+        self.accumulators.get(&0).and_then(|a| a.finish_reason())
+    }
+
+    /// Every choice received so far, by index, complete once the stream is finished
+    pub fn choices(&self) -> Vec<Choice> {
+        self.accumulators
+            .iter()
+            .map(|(index, accumulator)| Choice {
+                index: *index,
+                message: accumulator.message(),
+                finish_reason: accumulator.finish_reason().unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// The tokens used by the completion, once the stream is finished.
+    /// The API only sends them when the request sets `stream_options: {"include_usage": true}`
+    pub fn usage(&self) -> Option<Usage> {
+        self.usage
+    }
+
+    fn handle_event(&mut self, data: String) {
+        if data.trim() == "[DONE]" {
+            self.done = true;
+            return;
+        }
+        let chunk = serde_json::from_str::<ChatResponseChunk>(&data)
+            .map_err(|source| Error::Deserialize { source, body: data });
+        if let Ok(chunk) = &chunk {
+            if let Some(usage) = chunk.usage {
+                self.usage = Some(usage);
+            }
+            for choice in &chunk.choices {
+                self.accumulators
+                    .entry(choice.index)
+                    .or_insert_with(|| MessageAccumulator::for_choice(choice.index))
+                    .push(chunk);
+            }
+        }
+        self.pending.push_back(chunk);
+    }
+}
+
+impl Stream for ChatStream {
+    type Item = Result<ChatResponseChunk>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(chunk) = this.pending.pop_front() {
+                return Poll::Ready(Some(chunk));
+            }
+            if this.done {
+                return Poll::Ready(None);
+            }
+            match this.bytes.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(bytes))) => {
+                    for data in this.parser.push(&bytes) {
+                        if !this.done {
+                            this.handle_event(data);
+                        }
+                    }
+                }
+                Poll::Ready(Some(Err(e))) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+                Poll::Ready(None) => {
+                    if let Some(data) = this.parser.finish() {
+                        this.handle_event(data);
+                    }
+                    this.done = true;
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(data: &str) -> ChatResponseChunk {
+        serde_json::from_str(data).expect("Failed to parse the chunk")
+    }
+
+    #[test]
+    fn test_sse_parser_split_events() {
+        let mut parser = SseParser::new();
+        assert!(parser.push(b"data: {\"a\":").is_empty());
+        assert!(parser.push(b"1}\r\n").is_empty());
+        assert_eq!(
+            parser.push(b"\r\ndata: [DONE]\n\n"),
+            vec!["{\"a\":1}", "[DONE]"]
+        );
+    }
+
+    #[test]
+    fn test_sse_parser_ignores_comments_and_joins_lines() {
+        let mut parser = SseParser::new();
+        assert_eq!(
+            parser.push(b": keep-alive\n\nevent: message\ndata: first\ndata: second\n\n"),
+            vec!["first\nsecond"]
+        );
+    }
+
+    #[test]
+    fn test_sse_parser_finish() {
+        let mut parser = SseParser::new();
+        assert!(parser.push(b"data: last").is_empty());
+        assert_eq!(parser.finish(), Some("last".to_string()));
+        assert_eq!(parser.finish(), None);
+    }
+
+    #[test]
+    fn test_accumulate_content() {
+        let mut accumulator = MessageAccumulator::new();
+        accumulator.push(&chunk(
+            r#"{"id":"1","object":"chat.completion.chunk","created":0,"choices":[{"index":0,"delta":{"role":"assistant","content":""},"finish_reason":null}]}"#,
+        ));
+        accumulator.push(&chunk(
+            r#"{"id":"1","object":"chat.completion.chunk","created":0,"choices":[{"index":0,"delta":{"content":"Hello"},"finish_reason":null}]}"#,
+        ));
+        accumulator.push(&chunk(
+            r#"{"id":"1","object":"chat.completion.chunk","created":0,"choices":[{"index":0,"delta":{"content":" there\n"},"finish_reason":null}]}"#,
+        ));
+        accumulator.push(&chunk(
+            r#"{"id":"1","object":"chat.completion.chunk","created":0,"choices":[{"index":0,"delta":{},"finish_reason":"stop"}]}"#,
+        ));
+
+        let message = accumulator.message();
+        assert_eq!(message.role, Role::Assistant);
+        assert_eq!(message.content, Some("Hello there\n".to_string()));
+        assert_eq!(message.function_call, None);
+        assert_eq!(accumulator.finish_reason(), Some("stop".to_string()));
+        assert_eq!(accumulator.usage(), None);
+
+        // With stream_options include_usage, the usage comes in a last chunk without choices
+        accumulator.push(&chunk(
+            r#"{"id":"1","object":"chat.completion.chunk","created":0,"choices":[],"usage":{"prompt_tokens":9,"completion_tokens":3,"total_tokens":12}}"#,
+        ));
+        assert_eq!(accumulator.usage().map(|u| u.total_tokens), Some(12));
+        assert_eq!(
+            accumulator.message().content,
+            Some("Hello there\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_accumulate_parallel_tool_calls() {
+        let mut accumulator = MessageAccumulator::new();
+        for data in [
+            r#"{"choices":[{"index":0,"delta":{"role":"assistant","content":null,"tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"get_current_weather","arguments":""}}]},"finish_reason":null}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{\"location\": \"Mad"}}]},"finish_reason":null}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":1,"id":"call_2","type":"function","function":{"name":"get_current_weather","arguments":""}}]},"finish_reason":null}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"rid\"}"}}]},"finish_reason":null}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":1,"function":{"arguments":"{\"location\": \"Paris\"}"}}]},"finish_reason":null}]}"#,
+            r#"{"choices":[{"index":0,"delta":{},"finish_reason":"tool_calls"}]}"#,
+        ] {
+            accumulator.push(&chunk(data));
+        }
+
+        let message = accumulator.message();
+        assert_eq!(message.function_call, None);
+        assert_eq!(
+            message.tool_calls,
+            Some(vec![
+                ToolCall::new(
+                    "call_1".to_string(),
+                    FunctionCall {
+                        name: "get_current_weather".to_string(),
+                        arguments: "{\"location\": \"Madrid\"}".to_string(),
+                    }
+                ),
+                ToolCall::new(
+                    "call_2".to_string(),
+                    FunctionCall {
+                        name: "get_current_weather".to_string(),
+                        arguments: "{\"location\": \"Paris\"}".to_string(),
+                    }
+                ),
+            ])
+        );
+        assert_eq!(accumulator.finish_reason(), Some("tool_calls".to_string()));
+    }
+
+    #[test]
+    fn test_accumulate_function_call() {
+        let mut accumulator = MessageAccumulator::new();
+        accumulator.push(&chunk(
+            r#"{"choices":[{"index":0,"delta":{"role":"assistant","content":null,"function_call":{"name":"get_current_weather","arguments":""}},"finish_reason":null}]}"#,
+        ));
+        accumulator.push(&chunk(
+            r#"{"choices":[{"index":0,"delta":{"function_call":{"arguments":"{\n  \"loc"}},"finish_reason":null}]}"#,
+        ));
+        accumulator.push(&chunk(
+            r#"{"choices":[{"index":0,"delta":{"function_call":{"arguments":"ation\": \"Madrid\"\n}"}},"finish_reason":null}]}"#,
+        ));
+        accumulator.push(&chunk(
+            r#"{"choices":[{"index":0,"delta":{},"finish_reason":"function_call"}]}"#,
+        ));
+
+        let message = accumulator.message();
+        assert_eq!(message.content, None);
+        assert_eq!(
+            message.function_call,
+            Some(FunctionCall {
+                name: "get_current_weather".to_string(),
+                arguments: "{\n  \"location\": \"Madrid\"\n}".to_string(),
+            })
+        );
+        assert_eq!(
+            accumulator.finish_reason(),
+            Some("function_call".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_several_choices() {
+        use futures_util::StreamExt;
+
+        let events = [
+            r#"{"choices":[{"index":0,"delta":{"role":"assistant","content":"Hi"},"finish_reason":null}]}"#,
+            r#"{"choices":[{"index":1,"delta":{"role":"assistant","content":"Hello"},"finish_reason":null}]}"#,
+            r#"{"choices":[{"index":1,"delta":{"content":" there"},"finish_reason":"stop"}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"content":"!"},"finish_reason":"length"}]}"#,
+            "[DONE]",
+        ];
+        let body: String = events.iter().map(|e| format!("data: {}\n\n", e)).collect();
+        let mut stream = ChatStream::new(crate::transport::TransportResponse::new(200, body).body);
+        while let Some(chunk) = stream.next().await {
+            chunk.expect("Failed to parse the chunk");
+        }
+
+        assert_eq!(stream.message().content, Some("Hi!".to_string()));
+        assert_eq!(stream.finish_reason(), Some("length".to_string()));
+        let choices = stream.choices();
+        assert_eq!(choices.len(), 2);
+        assert_eq!(choices[1].index, 1);
+        assert_eq!(choices[1].message.content, Some("Hello there".to_string()));
+        assert_eq!(choices[1].finish_reason, "stop");
+    }
+}
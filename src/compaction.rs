@@ -0,0 +1,263 @@
+use crate::{
+    chat_context::ChatContext,
+    message::{Message, Role},
+    truncation::{first_recent_group, message_groups},
+};
+
+/// The name of the system message that holds the summary of the compacted messages
+pub const SUMMARY_NAME: &str = "conversation_summary";
+
+const DEFAULT_KEEP_RECENT: usize = 10;
+const DEFAULT_PROMPT: &str = "You summarize conversations between a user and an assistant. \
+Write a concise summary of the conversation below, so that the assistant can continue it without the original messages. \
+Keep every fact, name, number, preference and decision the user mentioned, and the results of the functions that were called. \
+Reply only with the summary.";
+
+/// Replaces the older messages of a long conversation with a summary written by the model,
+/// instead of dropping them like the truncation strategies do
+///
+/// When the prompt of the context has more than `max_tokens` tokens, the messages older than
+/// the last `keep_recent` are sent to the model with the summarization `prompt`, and they are
+/// replaced by one system message with the summary. The system prompt, the recent messages and
+/// the pinned messages (see Message::pinned) are kept as they are.
+/// A previous summary is summarized again with the new messages, so there is only one.
+///
+/// # Example
+/// ```
+/// use chatgpt_functions::{chat_gpt::ChatGPTBuilder, compaction::Compaction};
+///
+/// let gpt = ChatGPTBuilder::new()
+///     .openai_api_token("key".to_string())
+///     .compaction(Compaction::new(3000).keep_recent(6))
+///     .build();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Compaction {
+    /// The context is compacted when its prompt has more tokens than this
+    pub max_tokens: usize,
+    /// The number of recent messages that are never summarized. It can keep less,
+    /// when keeping them all would split a function call from its results
+    pub keep_recent: usize,
+    /// The instructions sent to the model to summarize the messages
+    pub prompt: String,
+}
+
+impl Compaction {
+    /// Compacts the context when its prompt has more than `max_tokens` tokens,
+    /// keeping the last 10 messages
+    pub fn new(max_tokens: usize) -> Compaction {
+        Compaction {
+            max_tokens,
+            keep_recent: DEFAULT_KEEP_RECENT,
+            prompt: DEFAULT_PROMPT.to_string(),
+        }
+    }
+
+    pub fn keep_recent(mut self, keep_recent: usize) -> Compaction {
+        self.keep_recent = keep_recent;
+        self
+    }
+
+    pub fn prompt(mut self, prompt: String) -> Compaction {
+        self.prompt = prompt;
+        self
+    }
+
+    /// Whether the prompt of the context has more tokens than the threshold
+    pub fn is_needed(&self, chat_context: &ChatContext) -> bool {
+        chat_context.count_tokens() > self.max_tokens
+    }
+
+    /// Returns the indexes of the messages of the context that would be summarized:
+    /// the previous summary, and the messages before the recent ones that are not pinned.
+    /// It is empty when there are no new messages to summarize
+    pub fn messages_to_summarize(&self, chat_context: &ChatContext) -> Vec<usize> {
+        let messages = &chat_context.messages;
+        let groups = message_groups(messages);
+        let first_recent = first_recent_group(&groups, self.keep_recent);
+        let older = &groups[..first_recent];
+
+        let mut indexes: Vec<usize> = older
+            .iter()
+            // A pinned message keeps its whole group, not to orphan a function result
+            .filter(|group| !messages[(*group).clone()].iter().any(|m| m.pinned))
+            .flat_map(|group| group.clone())
+            .collect();
+        if indexes.is_empty() {
+            return indexes;
+        }
+        let recent_start = groups.get(first_recent).map_or(messages.len(), |g| g.start);
+        indexes.extend((0..recent_start).filter(|i| is_summary(&messages[*i])));
+        indexes.sort_unstable();
+        indexes
+    }
+
+    /// Builds the request that asks the model to summarize the messages
+    pub fn summary_request(&self, chat_context: &ChatContext, indexes: &[usize]) -> ChatContext {
+        let mut request = ChatContext::new(chat_context.model.clone());
+        request.push_message(Message::system(self.prompt.clone()));
+        let lines: Vec<String> = indexes
+            .iter()
+            .map(|i| transcript_line(&chat_context.messages[*i]))
+            .collect();
+        request.push_message(Message::new_user_message(lines.join("\n")));
+        request
+    }
+
+    /// Replaces the messages with a system message with the summary,
+    /// in the position of the first of them
+    pub fn replace_with_summary(
+        &self,
+        chat_context: &mut ChatContext,
+        indexes: &[usize],
+        summary: String,
+    ) {
+        let first = match indexes.first() {
+            Some(first) => *first,
+            None => return,
+        };
+        let mut i = 0;
+        chat_context.messages.retain(|_| {
+            let keep = !indexes.contains(&i);
+            i += 1;
+            keep
+        });
+        let mut message = Message::system(summary);
+        message.set_name(SUMMARY_NAME.to_string());
+        chat_context.messages.insert(first, message);
+    }
+}
+
+fn is_summary(message: &Message) -> bool {
+    message.role == Role::System && message.name.as_deref() == Some(SUMMARY_NAME)
+}
+
+// A message of the conversation as the model reads it in the summary request
+fn transcript_line(message: &Message) -> String {
+    let content = message.content.as_deref().unwrap_or_default();
+    if is_summary(message) {
+        return format!("Summary of the earlier conversation: {}", content);
+    }
+    let calls: Vec<String> = message
+        .function_call
+        .iter()
+        .chain(message.tool_calls.iter().flatten().map(|t| &t.function))
+        .map(|f| format!("{} called {}({})", message.role, f.name, f.arguments))
+        .collect();
+    if !calls.is_empty() {
+        return calls.join("\n");
+    }
+    match (message.role, &message.name) {
+        (Role::Function, Some(name)) => format!("{} returned: {}", name, content),
+        (Role::Tool, _) => format!("tool returned: {}", content),
+        (role, _) => format!("{}: {}", role, content),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::FunctionCall;
+
+    fn context() -> ChatContext {
+        let mut function_call = Message::new(Role::Assistant);
+        function_call.set_function_call(FunctionCall {
+            name: "track_order".to_string(),
+            arguments: r#"{"id": 42}"#.to_string(),
+        });
+        let mut pinned = Message::user("My name is Ana".to_string());
+        pinned.set_pinned(true);
+
+        let mut chat_context = ChatContext::new("gpt-3.5-turbo-0613".to_string());
+        chat_context.set_messages(vec![
+            Message::system("You are a support bot".to_string()),
+            pinned,
+            Message::user("Where is order 42?".to_string()),
+            function_call,
+            Message::function_result("track_order".to_string(), "In transit".to_string()),
+            Message::assistant("It is in transit".to_string()),
+            Message::user("Thanks".to_string()),
+            Message::assistant("You're welcome".to_string()),
+        ]);
+        chat_context
+    }
+
+    #[test]
+    fn test_messages_to_summarize() {
+        let compaction = Compaction::new(0).keep_recent(2);
+        assert_eq!(
+            compaction.messages_to_summarize(&context()),
+            vec![2, 3, 4, 5]
+        );
+        // The function result is not split from its call
+        let compaction = Compaction::new(0).keep_recent(5);
+        assert_eq!(compaction.messages_to_summarize(&context()), vec![2]);
+        let compaction = Compaction::new(0).keep_recent(7);
+        assert!(compaction.messages_to_summarize(&context()).is_empty());
+    }
+
+    #[test]
+    fn test_summary_request() {
+        let compaction = Compaction::new(0).keep_recent(2);
+        let chat_context = context();
+        let indexes = compaction.messages_to_summarize(&chat_context);
+        let request = compaction.summary_request(&chat_context, &indexes);
+        assert_eq!(request.model, "gpt-3.5-turbo-0613");
+        assert_eq!(
+            request.messages[0].content,
+            Some(DEFAULT_PROMPT.to_string())
+        );
+        assert_eq!(
+            request.messages[1].content,
+            Some(
+                "user: Where is order 42?\n\
+                 assistant called track_order({\"id\": 42})\n\
+                 track_order returned: In transit\n\
+                 assistant: It is in transit"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_replace_with_summary() {
+        let compaction = Compaction::new(0).keep_recent(2);
+        let mut chat_context = context();
+        let indexes = compaction.messages_to_summarize(&chat_context);
+        compaction.replace_with_summary(
+            &mut chat_context,
+            &indexes,
+            "Order 42 is in transit".to_string(),
+        );
+        let contents: Vec<&str> = chat_context
+            .messages
+            .iter()
+            .map(|m| m.content.as_deref().unwrap_or_default())
+            .collect();
+        assert_eq!(
+            contents,
+            vec![
+                "You are a support bot",
+                "My name is Ana",
+                "Order 42 is in transit",
+                "Thanks",
+                "You're welcome"
+            ]
+        );
+        assert!(is_summary(&chat_context.messages[2]));
+
+        // The summary is summarized again with the next messages
+        chat_context.push_message(Message::user("Bye".to_string()));
+        chat_context.push_message(Message::assistant("Bye!".to_string()));
+        assert_eq!(
+            compaction.messages_to_summarize(&chat_context),
+            vec![2, 3, 4]
+        );
+        let request = compaction.summary_request(&chat_context, &[2, 3, 4]);
+        assert!(request.messages[1]
+            .content
+            .as_deref()
+            .unwrap_or_default()
+            .starts_with("Summary of the earlier conversation: Order 42 is in transit\n"));
+    }
+}
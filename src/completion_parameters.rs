@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// The sampling parameters of a chat completion
+///
+/// Every parameter is optional, the ones that are not set are not sent to the API,
+/// so it uses its own defaults.
+/// https://platform.openai.com/docs/api-reference/chat/create
+///
+/// They are part of the ChatContext, and they can also be used to override the parameters
+/// of the context for a single request, see ChatGPT::completion_with_parameters
+///
+/// # Example
+/// ```
+/// use chatgpt_functions::completion_parameters::CompletionParameters;
+///
+/// let parameters = CompletionParameters::new()
+///     .temperature(0.2)
+///     .max_tokens(256)
+///     .stop(vec!["\n\n".to_string()]);
+/// assert_eq!(parameters.temperature, Some(0.2));
+/// ```
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct CompletionParameters {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f64>,
+    /// Maps token ids to a bias between -100 and 100
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logit_bias: Option<BTreeMap<u32, i32>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+}
+
+impl CompletionParameters {
+    pub fn new() -> CompletionParameters {
+        CompletionParameters::default()
+    }
+
+    pub fn temperature(mut self, temperature: f64) -> CompletionParameters {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    pub fn top_p(mut self, top_p: f64) -> CompletionParameters {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    pub fn max_tokens(mut self, max_tokens: u32) -> CompletionParameters {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    pub fn n(mut self, n: u32) -> CompletionParameters {
+        self.n = Some(n);
+        self
+    }
+
+    pub fn stop(mut self, stop: Vec<String>) -> CompletionParameters {
+        self.stop = Some(stop);
+        self
+    }
+
+    pub fn presence_penalty(mut self, presence_penalty: f64) -> CompletionParameters {
+        self.presence_penalty = Some(presence_penalty);
+        self
+    }
+
+    pub fn frequency_penalty(mut self, frequency_penalty: f64) -> CompletionParameters {
+        self.frequency_penalty = Some(frequency_penalty);
+        self
+    }
+
+    pub fn logit_bias(mut self, logit_bias: BTreeMap<u32, i32>) -> CompletionParameters {
+        self.logit_bias = Some(logit_bias);
+        self
+    }
+
+    pub fn user(mut self, user: String) -> CompletionParameters {
+        self.user = Some(user);
+        self
+    }
+
+    pub fn seed(mut self, seed: i64) -> CompletionParameters {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Returns a copy of these parameters, replaced by the ones set in `overrides`
+    pub fn merge(&self, overrides: &CompletionParameters) -> CompletionParameters {
+        CompletionParameters {
+            temperature: overrides.temperature.or(self.temperature),
+            top_p: overrides.top_p.or(self.top_p),
+            max_tokens: overrides.max_tokens.or(self.max_tokens),
+            n: overrides.n.or(self.n),
+            stop: overrides.stop.clone().or_else(|| self.stop.clone()),
+            presence_penalty: overrides.presence_penalty.or(self.presence_penalty),
+            frequency_penalty: overrides.frequency_penalty.or(self.frequency_penalty),
+            logit_bias: overrides
+                .logit_bias
+                .clone()
+                .or_else(|| self.logit_bias.clone()),
+            user: overrides.user.clone().or_else(|| self.user.clone()),
+            seed: overrides.seed.or(self.seed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let parameters = CompletionParameters::new()
+            .temperature(0.7)
+            .max_tokens(100)
+            .user("user-1".to_string());
+        let overrides = CompletionParameters::new().temperature(0.0).seed(42);
+        assert_eq!(
+            parameters.merge(&overrides),
+            CompletionParameters::new()
+                .temperature(0.0)
+                .max_tokens(100)
+                .user("user-1".to_string())
+                .seed(42)
+        );
+        assert_eq!(parameters.merge(&CompletionParameters::new()), parameters);
+    }
+
+    #[test]
+    fn test_deserialize_skips_missing_fields() {
+        let parameters: CompletionParameters =
+            serde_json::from_str(r#"{"temperature":0.5,"stop":["END"]}"#)
+                .expect("Failed to parse the parameters");
+        assert_eq!(
+            parameters,
+            CompletionParameters::new()
+                .temperature(0.5)
+                .stop(vec!["END".to_string()])
+        );
+        assert_eq!(
+            serde_json::to_string(&parameters).expect("Failed to serialize"),
+            r#"{"temperature":0.5,"stop":["END"]}"#
+        );
+    }
+}
@@ -0,0 +1,427 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chat_context::ChatContext,
+    chat_response::Usage,
+    error::{Error, Result},
+    message::Message,
+};
+
+/// Keeps the conversations between runs, keyed on the session_id of ChatGPT
+///
+/// ChatGPTBuilder::resume_session loads the context of a session from the store,
+/// and the managed completions (completion_managed, run_until_answer...) save it after every turn.
+///
+/// # Example
+/// ```no_run
+/// use std::sync::Arc;
+/// use chatgpt_functions::{chat_gpt::ChatGPTBuilder, conversation_store::FileStore};
+///
+/// # fn main() -> chatgpt_functions::Result<()> {
+/// let store = Arc::new(FileStore::new("conversations")?);
+/// let gpt = ChatGPTBuilder::new()
+///     .openai_api_token("key".to_string())
+///     .conversation_store(store)
+///     .resume_session("user-42".to_string())
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait ConversationStore: Send + Sync {
+    /// Returns the context of the session, or None if it was never saved
+    fn load(&self, session_id: &str) -> Result<Option<ChatContext>>;
+    /// Saves the context of the session, replacing the previous one
+    fn save(&self, session_id: &str, chat_context: &ChatContext) -> Result<()>;
+    /// Returns the ids of the saved sessions, sorted
+    fn list(&self) -> Result<Vec<String>>;
+    /// Deletes the session. It returns whether it existed
+    fn delete(&self, session_id: &str) -> Result<bool>;
+}
+
+// What the built-in stores save for a session.
+// Message::pinned, ChatContext::usage and ChatContext::cost are not serialized
+// because they can't be sent to the API, they are kept apart.
+// So are the messages without content, which are sent with an empty content
+#[derive(Serialize, Deserialize)]
+struct StoredConversation {
+    chat_context: ChatContext,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pinned: Vec<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    without_content: Vec<usize>,
+    #[serde(default, skip_serializing_if = "Usage::is_empty")]
+    usage: Usage,
+    #[serde(default)]
+    cost: f64,
+}
+
+/// Serializes the context as it is saved by the built-in stores, including the pinned messages,
+/// the messages without content, and the usage and cost of the session
+pub fn to_json(chat_context: &ChatContext) -> Result<String> {
+    let indexes = |filter: fn(&Message) -> bool| {
+        chat_context
+            .messages
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| filter(m))
+            .map(|(i, _)| i)
+            .collect()
+    };
+    let conversation = StoredConversation {
+        chat_context: chat_context.clone(),
+        pinned: indexes(|m| m.pinned),
+        without_content: indexes(|m| m.content.is_none()),
+        usage: chat_context.usage,
+        cost: chat_context.cost,
+    };
+    serde_json::to_string_pretty(&conversation).map_err(|e| Error::Store(e.into()))
+}
+
+/// Parses a context serialized with to_json
+pub fn from_json(json: &str) -> Result<ChatContext> {
+    let StoredConversation {
+        mut chat_context,
+        pinned,
+        without_content,
+        usage,
+        cost,
+    } = serde_json::from_str(json).map_err(|source| Error::Deserialize {
+        source,
+        body: json.to_string(),
+    })?;
+    for i in pinned {
+        if let Some(message) = chat_context.messages.get_mut(i) {
+            message.pinned = true;
+        }
+    }
+    for i in without_content {
+        if let Some(message) = chat_context.messages.get_mut(i) {
+            message.content = None;
+        }
+    }
+    chat_context.usage = usage;
+    chat_context.cost = cost;
+    Ok(chat_context)
+}
+
+// Writes the file through a temporary file renamed over it,
+// so that a crash doesn't leave half a file behind
+pub(crate) fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    fs::write(&temporary, contents)?;
+    fs::rename(&temporary, path)
+}
+
+/// Stores every conversation as a JSON file named after the session_id in a directory
+///
+/// The session ids can only have ASCII letters, digits, `-`, `_` and `.`,
+/// so that they can't point out of the directory.
+#[derive(Clone, Debug)]
+pub struct FileStore {
+    directory: PathBuf,
+}
+
+impl FileStore {
+    /// Creates the store, and the directory if it doesn't exist
+    pub fn new(directory: impl AsRef<Path>) -> Result<FileStore> {
+        let directory = directory.as_ref().to_path_buf();
+        fs::create_dir_all(&directory).map_err(|e| Error::Store(e.into()))?;
+        Ok(FileStore { directory })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    fn path(&self, session_id: &str) -> Result<PathBuf> {
+        let valid = !session_id.is_empty()
+            && !session_id.starts_with('.')
+            && session_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            return Err(Error::Store(anyhow::anyhow!(
+                "Invalid session id for a file name: {:?}",
+                session_id
+            )));
+        }
+        Ok(self.directory.join(format!("{}.json", session_id)))
+    }
+}
+
+impl ConversationStore for FileStore {
+    fn load(&self, session_id: &str) -> Result<Option<ChatContext>> {
+        match fs::read_to_string(self.path(session_id)?) {
+            Ok(json) => from_json(&json).map(Some),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(Error::Store(e.into())),
+        }
+    }
+
+    fn save(&self, session_id: &str, chat_context: &ChatContext) -> Result<()> {
+        write_atomically(&self.path(session_id)?, &to_json(chat_context)?)
+            .map_err(|e| Error::Store(e.into()))
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        let entries = fs::read_dir(&self.directory).map_err(|e| Error::Store(e.into()))?;
+        let mut session_ids = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(|e| Error::Store(e.into()))?.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                    session_ids.push(stem.to_string());
+                }
+            }
+        }
+        session_ids.sort();
+        Ok(session_ids)
+    }
+
+    fn delete(&self, session_id: &str) -> Result<bool> {
+        match fs::remove_file(self.path(session_id)?) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(Error::Store(e.into())),
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use std::{path::Path, sync::Mutex};
+
+    use rusqlite::{params, Connection, OptionalExtension};
+
+    use super::{from_json, to_json, ConversationStore};
+    use crate::{
+        chat_context::ChatContext,
+        error::{Error, Result},
+    };
+
+    /// Stores the conversations in the `conversations` table of a SQLite database
+    /// (feature `sqlite`)
+    ///
+    /// The table is created if it doesn't exist. The contexts are saved as JSON, like FileStore does.
+    pub struct SqliteStore {
+        connection: Mutex<Connection>,
+    }
+
+    impl SqliteStore {
+        /// Opens the database, creating it if it doesn't exist
+        pub fn open(path: impl AsRef<Path>) -> Result<SqliteStore> {
+            let connection = Connection::open(path).map_err(|e| Error::Store(e.into()))?;
+            SqliteStore::new(connection)
+        }
+
+        /// Uses a database in memory, that is lost when the store is dropped
+        pub fn open_in_memory() -> Result<SqliteStore> {
+            let connection = Connection::open_in_memory().map_err(|e| Error::Store(e.into()))?;
+            SqliteStore::new(connection)
+        }
+
+        /// Uses a connection opened by the application
+        pub fn new(connection: Connection) -> Result<SqliteStore> {
+            connection
+                .execute(
+                    "CREATE TABLE IF NOT EXISTS conversations (
+                        session_id TEXT PRIMARY KEY,
+                        chat_context TEXT NOT NULL,
+                        updated_at INTEGER NOT NULL
+                    )",
+                    [],
+                )
+                .map_err(|e| Error::Store(e.into()))?;
+            Ok(SqliteStore {
+                connection: Mutex::new(connection),
+            })
+        }
+
+        fn with_connection<T>(
+            &self,
+            f: impl FnOnce(&Connection) -> rusqlite::Result<T>,
+        ) -> Result<T> {
+            let connection = self
+                .connection
+                .lock()
+                .map_err(|_| Error::Store(anyhow::anyhow!("The connection lock is poisoned")))?;
+            f(&connection).map_err(|e| Error::Store(e.into()))
+        }
+    }
+
+    impl ConversationStore for SqliteStore {
+        fn load(&self, session_id: &str) -> Result<Option<ChatContext>> {
+            let json: Option<String> = self.with_connection(|c| {
+                c.query_row(
+                    "SELECT chat_context FROM conversations WHERE session_id = ?1",
+                    params![session_id],
+                    |row| row.get(0),
+                )
+                .optional()
+            })?;
+            json.map(|json| from_json(&json)).transpose()
+        }
+
+        fn save(&self, session_id: &str, chat_context: &ChatContext) -> Result<()> {
+            let json = to_json(chat_context)?;
+            self.with_connection(|c| {
+                c.execute(
+                    "INSERT INTO conversations (session_id, chat_context, updated_at)
+                     VALUES (?1, ?2, unixepoch())
+                     ON CONFLICT(session_id) DO UPDATE
+                     SET chat_context = excluded.chat_context, updated_at = excluded.updated_at",
+                    params![session_id, json],
+                )
+            })?;
+            Ok(())
+        }
+
+        fn list(&self) -> Result<Vec<String>> {
+            self.with_connection(|c| {
+                let mut statement =
+                    c.prepare("SELECT session_id FROM conversations ORDER BY session_id")?;
+                let session_ids = statement.query_map([], |row| row.get(0))?;
+                session_ids.collect()
+            })
+        }
+
+        fn delete(&self, session_id: &str) -> Result<bool> {
+            let deleted = self.with_connection(|c| {
+                c.execute(
+                    "DELETE FROM conversations WHERE session_id = ?1",
+                    params![session_id],
+                )
+            })?;
+            Ok(deleted > 0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{FunctionCall, Role, ToolCall};
+
+    fn chat_context() -> ChatContext {
+        let mut chat_context = ChatContext::new("gpt-4".to_string());
+        let mut pinned = Message::new_user_message("My name is Ana".to_string());
+        pinned.set_pinned(true);
+        chat_context.push_message(pinned);
+        chat_context.push_message(Message::new_user_message("Hello".to_string()));
+        let mut tool_call = Message::new(Role::Assistant);
+        tool_call.set_tool_calls(vec![ToolCall::new(
+            "call_1".to_string(),
+            FunctionCall {
+                name: "get_current_weather".to_string(),
+                arguments: "{\"location\":\"Madrid\"}".to_string(),
+            },
+        )]);
+        chat_context.push_message(tool_call);
+        chat_context.push_message(Message::new_tool_message(
+            "call_1".to_string(),
+            "Sunny".to_string(),
+        ));
+        chat_context.parameters.temperature = Some(0.5);
+        chat_context.usage = Usage {
+            prompt_tokens: 9,
+            completion_tokens: 2,
+            total_tokens: 11,
+        };
+        chat_context.cost = 0.25;
+        chat_context
+    }
+
+    fn temporary_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "chatgpt-functions-{}-{}",
+            name,
+            uuid::Uuid::new_v4()
+        ));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    // Runs the operations of the trait against any store
+    fn assert_store_round_trip(store: &dyn ConversationStore) {
+        assert!(store.load("session-1").expect("Failed to load").is_none());
+        assert_eq!(store.list().expect("Failed to list"), Vec::<String>::new());
+
+        let chat_context = chat_context();
+        store
+            .save("session-1", &chat_context)
+            .expect("Failed to save");
+        store
+            .save("session-0", &ChatContext::new("gpt-4".to_string()))
+            .expect("Failed to save");
+        let loaded = store
+            .load("session-1")
+            .expect("Failed to load")
+            .expect("The session was not saved");
+        assert_eq!(loaded.messages, chat_context.messages);
+        assert!(loaded.messages[0].pinned);
+        // The content of the tool call is sent as "", but it is still missing once loaded
+        assert!(to_json(&chat_context)
+            .expect("Failed to serialize")
+            .contains("\"content\": \"\""));
+        assert_eq!(loaded.messages[2].content, None);
+        assert!(loaded.messages[2].tool_calls.is_some());
+        assert_eq!(loaded.parameters.temperature, Some(0.5));
+        assert_eq!(loaded.usage, chat_context.usage);
+        assert_eq!(loaded.cost, 0.25);
+        assert_eq!(
+            store.list().expect("Failed to list"),
+            vec!["session-0".to_string(), "session-1".to_string()]
+        );
+
+        // Saving again replaces the conversation
+        let mut updated = loaded;
+        updated.push_message(Message::new_user_message("Bye".to_string()));
+        store.save("session-1", &updated).expect("Failed to save");
+        let loaded = store
+            .load("session-1")
+            .expect("Failed to load")
+            .expect("The session was not saved");
+        assert_eq!(loaded.messages.len(), 5);
+
+        assert!(store.delete("session-1").expect("Failed to delete"));
+        assert!(!store.delete("session-1").expect("Failed to delete"));
+        assert_eq!(
+            store.list().expect("Failed to list"),
+            vec!["session-0".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_file_store() {
+        let directory = temporary_directory("file-store");
+        let store = FileStore::new(&directory).expect("Failed to create the store");
+        assert_store_round_trip(&store);
+        fs::remove_dir_all(directory).expect("Failed to remove the directory");
+    }
+
+    #[test]
+    fn test_file_store_rejects_paths() {
+        let directory = temporary_directory("file-store-paths");
+        let store = FileStore::new(&directory).expect("Failed to create the store");
+        for session_id in ["", "../secret", ".hidden", "a/b"] {
+            assert!(matches!(store.load(session_id), Err(Error::Store(_))));
+        }
+        fs::remove_dir_all(directory).expect("Failed to remove the directory");
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_store() {
+        let store = SqliteStore::open_in_memory().expect("Failed to create the store");
+        assert_store_round_trip(&store);
+    }
+}
@@ -0,0 +1,431 @@
+use std::{fmt, time::Duration};
+
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::validation::Violation;
+
+/// The result type used by the library
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The errors returned by the library
+///
+/// It allows to tell apart the errors coming from the API (authentication, rate limits,
+/// context length...) from the network and parsing errors.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The API replied with an error status and an OpenAI error object
+    #[error("The API replied with status {status}: {error}")]
+    Api {
+        status: u16,
+        error: ApiError,
+        rate_limit: Box<RateLimit>,
+    },
+    /// The API replied with an error status, but the body is not an OpenAI error object
+    #[error("The API replied with status {status}: {body}")]
+    Http {
+        status: u16,
+        body: String,
+        rate_limit: Box<RateLimit>,
+    },
+    /// The request could not be sent or the response could not be received
+    #[error("Failed to communicate with the API: {0}")]
+    Transport(#[from] reqwest::Error),
+    /// A custom Transport could not send the request or receive the response
+    #[error("Failed to communicate with the API: {0}")]
+    Connection(anyhow::Error),
+    /// The request could not be serialized
+    #[error("Could not serialize the request: {0}")]
+    Serialize(serde_json::Error),
+    /// The response could not be parsed
+    #[error("Could not parse the response: {source}. The object to parse: \n{body}")]
+    Deserialize {
+        source: serde_json::Error,
+        body: String,
+    },
+    /// The message can't be sent to the API, see MessageBuilder::build
+    #[error("Invalid message: {0}")]
+    InvalidMessage(String),
+    /// A required setting was not provided
+    #[error("Missing configuration: {0}")]
+    MissingConfiguration(String),
+    /// The response is valid, but it does not contain what was expected, e.g. a function call
+    #[error("Unexpected response: {0}")]
+    UnexpectedResponse(String),
+    /// The model called a function that is not in the FunctionRegistry
+    #[error("The model called an unknown function: {0}")]
+    UnknownFunction(String),
+    /// The handler of a function returned an error
+    #[error("The function {name} failed: {source}")]
+    FunctionFailed { name: String, source: anyhow::Error },
+    /// The model called a function with arguments that don't match its specification
+    #[error("The arguments of the call to {name} are not valid: {}", format_violations(.violations))]
+    InvalidArguments {
+        name: String,
+        violations: Vec<Violation>,
+    },
+    /// The model kept calling functions after the maximum number of iterations
+    #[error("The model did not answer after {0} rounds of function calls")]
+    MaxIterationsReached(u32),
+    /// The ConversationStore could not load, save, list or delete a conversation
+    #[error("The conversation store failed: {0}")]
+    Store(anyhow::Error),
+    /// The request was not sent because it could cost more than what is left of the budget of the session
+    #[error("The budget of ${limit} of the session would be exceeded: ${spent:.4} spent, and the request could cost ${estimated:.4}")]
+    BudgetExceeded {
+        limit: f64,
+        spent: f64,
+        estimated: f64,
+    },
+    /// The CassetteTransport could not read or write the cassette, or replay a request
+    #[error("The cassette failed: {0}")]
+    Cassette(anyhow::Error),
+}
+
+/// The error object returned by the OpenAI API
+///
+/// # Example
+/// {
+///   "error": {
+///     "message": "'parameters' is a required property - 'functions.0'",
+///     "type": "invalid_request_error",
+///     "param": null,
+///     "code": null
+///   }
+/// }
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ApiError {
+    pub message: String,
+    #[serde(rename = "type")]
+    pub type_: Option<String>,
+    pub param: Option<String>,
+    // Some OpenAI compatible servers send the code as a number
+    #[serde(default, deserialize_with = "string_or_number")]
+    pub code: Option<String>,
+}
+
+/// The rate limit information sent by the API in the headers of the response
+///
+/// https://platform.openai.com/docs/guides/rate-limits/rate-limits-in-headers
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RateLimit {
+    /// How long to wait before retrying, from the `Retry-After` or `retry-after-ms` headers
+    pub retry_after: Option<Duration>,
+    /// `x-ratelimit-limit-requests`
+    pub limit_requests: Option<u64>,
+    /// `x-ratelimit-limit-tokens`
+    pub limit_tokens: Option<u64>,
+    /// `x-ratelimit-remaining-requests`
+    pub remaining_requests: Option<u64>,
+    /// `x-ratelimit-remaining-tokens`
+    pub remaining_tokens: Option<u64>,
+    /// `x-ratelimit-reset-requests`
+    pub reset_requests: Option<Duration>,
+    /// `x-ratelimit-reset-tokens`
+    pub reset_tokens: Option<Duration>,
+}
+
+// The API wraps the error object in an "error" field
+#[derive(Deserialize)]
+struct ApiErrorResponse {
+    error: ApiError,
+}
+
+impl Error {
+    /// Builds the error for a response with an error status,
+    /// parsing the OpenAI error object if the body contains one
+    pub(crate) fn from_response(status: u16, rate_limit: RateLimit, body: String) -> Error {
+        match Error::parse_api_error(&body) {
+            Some(error) => Error::Api {
+                status,
+                error,
+                rate_limit: Box::new(rate_limit),
+            },
+            None => Error::Http {
+                status,
+                body,
+                rate_limit: Box::new(rate_limit),
+            },
+        }
+    }
+
+    /// Returns the OpenAI error object contained in the body, if any
+    pub(crate) fn parse_api_error(body: &str) -> Option<ApiError> {
+        serde_json::from_str::<ApiErrorResponse>(body)
+            .ok()
+            .map(|response| response.error)
+    }
+
+    /// Returns the HTTP status of the response, if the API replied with an error status
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Error::Api { status, .. } | Error::Http { status, .. } => Some(*status),
+            Error::Transport(e) => e.status().map(|s| s.as_u16()),
+            _ => None,
+        }
+    }
+
+    /// Returns the error object sent by the API, if any
+    pub fn api_error(&self) -> Option<&ApiError> {
+        match self {
+            Error::Api { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+
+    /// Returns the rate limit headers of the response, if the API replied with an error status
+    pub fn rate_limit(&self) -> Option<&RateLimit> {
+        match self {
+            Error::Api { rate_limit, .. } | Error::Http { rate_limit, .. } => Some(rate_limit),
+            _ => None,
+        }
+    }
+
+    /// Returns how long the API asked to wait before retrying, if it did
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.rate_limit().and_then(|r| r.retry_after)
+    }
+
+    /// Returns the error code sent by the API, e.g. `context_length_exceeded` or `invalid_api_key`
+    pub fn code(&self) -> Option<&str> {
+        self.api_error().and_then(|e| e.code.as_deref())
+    }
+
+    /// Returns true if the API replied with 429 Too Many Requests.
+    /// This can be a rate limit or an exhausted quota, check `code()` to tell them apart
+    pub fn is_rate_limited(&self) -> bool {
+        self.status() == Some(429)
+    }
+
+    /// Returns true if the API rejected the API token
+    pub fn is_authentication(&self) -> bool {
+        self.status() == Some(401)
+    }
+
+    /// Returns true if the messages and functions don't fit in the context window of the model
+    pub fn is_context_length_exceeded(&self) -> bool {
+        self.code() == Some("context_length_exceeded")
+    }
+
+    /// Returns true if the request timed out
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Error::Transport(e) if e.is_timeout())
+    }
+}
+
+impl RateLimit {
+    /// Reads the rate limit information from the headers of a response
+    pub fn from_headers(headers: &HeaderMap) -> RateLimit {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+        let number = |name: &str| header(name).and_then(|v| v.trim().parse::<u64>().ok());
+        let duration = |name: &str| header(name).and_then(parse_duration);
+
+        // retry-after-ms is more precise, Retry-After is the standard one (in seconds)
+        let retry_after = header("retry-after-ms")
+            .and_then(|v| v.trim().parse::<f64>().ok())
+            .and_then(|ms| seconds(ms / 1000.0))
+            .or_else(|| {
+                header("retry-after")
+                    .and_then(|v| v.trim().parse::<f64>().ok())
+                    .and_then(seconds)
+            });
+
+        RateLimit {
+            retry_after,
+            limit_requests: number("x-ratelimit-limit-requests"),
+            limit_tokens: number("x-ratelimit-limit-tokens"),
+            remaining_requests: number("x-ratelimit-remaining-requests"),
+            remaining_tokens: number("x-ratelimit-remaining-tokens"),
+            reset_requests: duration("x-ratelimit-reset-requests"),
+            reset_tokens: duration("x-ratelimit-reset-tokens"),
+        }
+    }
+}
+
+// Parses the durations used by the API in the reset headers, e.g. "1s", "6m0s", "20ms" or "1h2m3.5s"
+fn parse_duration(value: &str) -> Option<Duration> {
+    let mut total = 0.0;
+    let mut number = String::new();
+    let mut chars = value.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            'h' => 3600.0,
+            'm' if chars.peek() == Some(&'s') => {
+                chars.next();
+                0.001
+            }
+            'm' => 60.0,
+            's' => 1.0,
+            _ => return None,
+        };
+        total += number.parse::<f64>().ok()? * unit;
+        number.clear();
+    }
+    if !number.is_empty() {
+        // A bare number is in seconds
+        total += number.parse::<f64>().ok()?;
+    }
+    seconds(total)
+}
+
+// The values sent by the server can't be trusted: "inf", "NaN" or "1e30" are valid floats
+// but not durations, they are ignored like the values that can't be parsed
+fn seconds(value: f64) -> Option<Duration> {
+    let value = if value < 0.0 { 0.0 } else { value };
+    Duration::try_from_secs_f64(value).ok()
+}
+
+fn format_violations(violations: &[Violation]) -> String {
+    violations
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn string_or_number<'de, D>(deserializer: D) -> std::result::Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(
+        match Option::<serde_json::Value>::deserialize(deserializer)? {
+            Some(serde_json::Value::String(s)) => Some(s),
+            Some(serde_json::Value::Null) | None => None,
+            Some(other) => Some(other.to_string()),
+        },
+    )
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(type_) = &self.type_ {
+            write!(f, " (type: {}", type_)?;
+            if let Some(code) = &self.code {
+                write!(f, ", code: {}", code)?;
+            }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_response_with_api_error() {
+        let error = Error::from_response(
+            400,
+            RateLimit::default(),
+            r#"{
+  "error": {
+    "message": "This model's maximum context length is 4097 tokens.",
+    "type": "invalid_request_error",
+    "param": "messages",
+    "code": "context_length_exceeded"
+  }
+}"#
+            .to_string(),
+        );
+        assert_eq!(error.status(), Some(400));
+        assert_eq!(
+            error.api_error(),
+            Some(&ApiError {
+                message: "This model's maximum context length is 4097 tokens.".to_string(),
+                type_: Some("invalid_request_error".to_string()),
+                param: Some("messages".to_string()),
+                code: Some("context_length_exceeded".to_string()),
+            })
+        );
+        assert_eq!(
+            error.to_string(),
+            "The API replied with status 400: This model's maximum context length is 4097 tokens. (type: invalid_request_error, code: context_length_exceeded)"
+        );
+    }
+
+    #[test]
+    fn test_from_response_without_api_error() {
+        let error = Error::from_response(502, RateLimit::default(), "Bad gateway".to_string());
+        assert_eq!(error.status(), Some(502));
+        assert_eq!(error.api_error(), None);
+        assert!(
+            matches!(error, Error::Http { status: 502, ref body, .. } if body == "Bad gateway")
+        );
+    }
+
+    #[test]
+    fn test_api_error_with_numeric_code() {
+        let error = Error::parse_api_error(
+            r#"{"error":{"message":"Not found","type":"NotFoundError","param":null,"code":404}}"#,
+        )
+        .expect("Failed to parse the error");
+        assert_eq!(error.code, Some("404".to_string()));
+    }
+
+    #[test]
+    fn test_rate_limit_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", "20".parse().unwrap());
+        headers.insert("x-ratelimit-limit-requests", "60".parse().unwrap());
+        headers.insert("x-ratelimit-limit-tokens", "150000".parse().unwrap());
+        headers.insert("x-ratelimit-remaining-requests", "0".parse().unwrap());
+        headers.insert("x-ratelimit-remaining-tokens", "149984".parse().unwrap());
+        headers.insert("x-ratelimit-reset-requests", "1m0.5s".parse().unwrap());
+        headers.insert("x-ratelimit-reset-tokens", "6ms".parse().unwrap());
+
+        assert_eq!(
+            RateLimit::from_headers(&headers),
+            RateLimit {
+                retry_after: Some(Duration::from_secs(20)),
+                limit_requests: Some(60),
+                limit_tokens: Some(150000),
+                remaining_requests: Some(0),
+                remaining_tokens: Some(149984),
+                reset_requests: Some(Duration::from_millis(60500)),
+                reset_tokens: Some(Duration::from_millis(6)),
+            }
+        );
+
+        headers.insert("retry-after-ms", "1500".parse().unwrap());
+        assert_eq!(
+            RateLimit::from_headers(&headers).retry_after,
+            Some(Duration::from_millis(1500))
+        );
+
+        // The invalid values are ignored, retry-after is used when retry-after-ms is invalid
+        for value in ["inf", "NaN", "1e30", "9".repeat(400).as_str()] {
+            headers.insert("retry-after-ms", value.parse().unwrap());
+            headers.insert("x-ratelimit-reset-requests", value.parse().unwrap());
+            let rate_limit = RateLimit::from_headers(&headers);
+            assert_eq!(rate_limit.retry_after, Some(Duration::from_secs(20)));
+            assert_eq!(rate_limit.reset_requests, None);
+        }
+        headers.insert("retry-after", "inf".parse().unwrap());
+        assert_eq!(RateLimit::from_headers(&headers).retry_after, None);
+        headers.insert("retry-after", "-5".parse().unwrap());
+        assert_eq!(
+            RateLimit::from_headers(&headers).retry_after,
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("1s"), Some(Duration::from_secs(1)));
+        assert_eq!(parse_duration("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(parse_duration("1h2m3s"), Some(Duration::from_secs(3723)));
+        assert_eq!(parse_duration("250ms"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_duration("2"), Some(Duration::from_secs(2)));
+        assert_eq!(parse_duration("soon"), None);
+        assert_eq!(parse_duration(""), Some(Duration::ZERO));
+        assert_eq!(parse_duration("."), None);
+        assert_eq!(parse_duration(&format!("{}h", "9".repeat(400))), None);
+        assert_eq!(parse_duration("99999999999999999999h"), None);
+    }
+}
@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 /// Escape a string for JSON.
 ///
 /// This is useful to escape the content of a message, for example.
@@ -31,10 +33,78 @@ impl EscapeJson for String {
     }
 }
 
+/// Escapes the control characters, like newlines or tabs, that appear unescaped inside the strings
+/// of a JSON document. They are not valid JSON, but some models and proxies send them,
+/// e.g. in the arguments of a function call.
+///
+/// The characters outside the strings are whitespace and they are kept as they are,
+/// so the strings of the document are parsed with exactly the same content.
+/// It returns the same document, borrowed, if there is nothing to escape
+pub fn escape_control_characters(json: &str) -> Cow<'_, str> {
+    let needs_escaping = |c: char| c < ' ';
+    if !json.chars().any(needs_escaping) {
+        return Cow::Borrowed(json);
+    }
+
+    let mut escaped = String::with_capacity(json.len());
+    let mut in_string = false;
+    let mut after_backslash = false;
+    for c in json.chars() {
+        if !in_string {
+            in_string = c == '"';
+            escaped.push(c);
+            continue;
+        }
+        if after_backslash {
+            after_backslash = false;
+            escaped.push(c);
+            continue;
+        }
+        match c {
+            '\\' => {
+                after_backslash = true;
+                escaped.push(c);
+            }
+            '"' => {
+                in_string = false;
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if needs_escaping(c) => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_escape_control_characters() {
+        assert!(matches!(
+            escape_control_characters("{\"a\": \"b\\nc\"}"),
+            Cow::Borrowed(_)
+        ));
+        // The whitespace between the values is not changed
+        assert_eq!(
+            escape_control_characters("{\n  \"a\": \"b\\nc\"\n}"),
+            "{\n  \"a\": \"b\\nc\"\n}"
+        );
+        assert_eq!(
+            escape_control_characters("{\n  \"a\": \"line 1\nline 2\t\u{1}\"\n}"),
+            "{\n  \"a\": \"line 1\\nline 2\\t\\u0001\"\n}"
+        );
+        // Escaped quotes don't end the string
+        assert_eq!(
+            escape_control_characters("[\"say \\\"hi\\\"\n\", \"\r\"]"),
+            "[\"say \\\"hi\\\"\\n\", \"\\r\"]"
+        );
+    }
+
     #[test]
     fn test_escape_json() {
         assert_eq!("\"".escape_json(), "\\\"");
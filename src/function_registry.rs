@@ -0,0 +1,234 @@
+use std::{fmt, future::Future, pin::Pin};
+
+use crate::{
+    chat_function::ChatFunction,
+    error::{Error, Result},
+    function_specification::{FunctionSpecification, Tool},
+};
+
+/// The future returned by a function handler. It resolves to the result sent back to the model
+pub type FunctionFuture = Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send>>;
+
+/// An async Rust function that can be called by the model.
+/// It receives the arguments of the call as a JSON string
+pub type FunctionHandler = Box<dyn Fn(String) -> FunctionFuture + Send + Sync>;
+
+/// The functions that the model can call, each one with the handler that runs it
+///
+/// It is used by ChatGPT::run_until_answer to execute the function calls of the model
+/// and send their results back until the model answers with content.
+///
+/// # Example
+/// ```
+/// use chatgpt_functions::{
+///     function_registry::FunctionRegistry,
+///     function_specification::FunctionSpecification,
+/// };
+///
+/// let mut registry = FunctionRegistry::new();
+/// registry.register(
+///     FunctionSpecification::new("get_time".to_string(), None, None),
+///     |_arguments| async { Ok("12:00".to_string()) },
+/// );
+/// assert!(registry.contains("get_time"));
+/// ```
+#[derive(Default)]
+pub struct FunctionRegistry {
+    functions: Vec<(FunctionSpecification, FunctionHandler)>,
+}
+
+impl FunctionRegistry {
+    pub fn new() -> FunctionRegistry {
+        FunctionRegistry::default()
+    }
+
+    /// Registers a function with the handler that runs it
+    /// If there is already a function with the same name, it is replaced
+    /// # Arguments
+    /// * `specification` - The specification sent to the model
+    /// * `handler` - The async function called with the arguments of the call, as a JSON string
+    pub fn register<F, Fut>(&mut self, specification: FunctionSpecification, handler: F)
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<String>> + Send + 'static,
+    {
+        let handler: FunctionHandler = Box::new(move |arguments| Box::pin(handler(arguments)));
+        self.functions
+            .retain(|(spec, _)| spec.name != specification.name);
+        self.functions.push((specification, handler));
+    }
+
+    /// Registers a ChatFunction, with its generated specification,
+    /// and a handler that receives the arguments already deserialized into the type
+    /// # Arguments
+    /// * `handler` - The async function called with the arguments of the call
+    pub fn register_function<T, F, Fut>(&mut self, handler: F)
+    where
+        T: ChatFunction + Send + 'static,
+        F: Fn(T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<String>> + Send + 'static,
+    {
+        let handler = std::sync::Arc::new(handler);
+        self.register(T::function_specification(), move |arguments| {
+            let handler = handler.clone();
+            async move { handler(T::from_arguments(&arguments)?).await }
+        });
+    }
+
+    /// Returns true if there is a function registered with this name
+    pub fn contains(&self, name: &str) -> bool {
+        self.functions.iter().any(|(spec, _)| spec.name == name)
+    }
+
+    /// Returns the specification of the function registered with this name
+    pub fn specification(&self, name: &str) -> Option<&FunctionSpecification> {
+        self.functions
+            .iter()
+            .find(|(spec, _)| spec.name == name)
+            .map(|(spec, _)| spec)
+    }
+
+    /// The specifications of the registered functions, in the order they were registered,
+    /// to be set as the functions of the context
+    pub fn specifications(&self) -> Vec<FunctionSpecification> {
+        self.functions
+            .iter()
+            .map(|(spec, _)| spec.clone())
+            .collect()
+    }
+
+    /// The registered functions as tools, to be set as the tools of the context
+    pub fn tools(&self) -> Vec<Tool> {
+        self.functions
+            .iter()
+            .map(|(spec, _)| Tool::function(spec.clone()))
+            .collect()
+    }
+
+    /// Runs the function registered with this name
+    /// # Arguments
+    /// * `name` - The name of the function called by the model
+    /// * `arguments` - The arguments of the call, as a JSON string
+    /// # Errors
+    /// It returns Error::UnknownFunction if there is no function with this name
+    /// It returns Error::FunctionFailed if the handler returns an error
+    pub async fn call(&self, name: &str, arguments: String) -> Result<String> {
+        let (_, handler) = self
+            .functions
+            .iter()
+            .find(|(spec, _)| spec.name == name)
+            .ok_or_else(|| Error::UnknownFunction(name.to_string()))?;
+        handler(arguments)
+            .await
+            .map_err(|source| Error::FunctionFailed {
+                name: name.to_string(),
+                source,
+            })
+    }
+}
+
+impl fmt::Debug for FunctionRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FunctionRegistry")
+            .field("functions", &self.specifications())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> FunctionRegistry {
+        let mut registry = FunctionRegistry::new();
+        registry.register(
+            FunctionSpecification::new("echo".to_string(), None, None),
+            |arguments| async move { Ok(arguments) },
+        );
+        registry.register(
+            FunctionSpecification::new("fail".to_string(), None, None),
+            |_| async { Err(anyhow::anyhow!("the service is down")) },
+        );
+        registry
+    }
+
+    #[tokio::test]
+    async fn test_call() {
+        let registry = registry();
+        assert_eq!(
+            registry
+                .call("echo", "{\"a\":1}".to_string())
+                .await
+                .expect("Failed to call the function"),
+            "{\"a\":1}"
+        );
+        assert!(matches!(
+            registry.call("missing", "{}".to_string()).await,
+            Err(Error::UnknownFunction(name)) if name == "missing"
+        ));
+        let error = registry
+            .call("fail", "{}".to_string())
+            .await
+            .expect_err("The function should fail");
+        assert_eq!(
+            error.to_string(),
+            "The function fail failed: the service is down"
+        );
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Add {
+        a: i64,
+        b: i64,
+    }
+
+    impl ChatFunction for Add {
+        fn function_specification() -> FunctionSpecification {
+            FunctionSpecification::new("add".to_string(), None, None)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_register_function() {
+        let mut registry = FunctionRegistry::new();
+        registry.register_function(|add: Add| async move { Ok((add.a + add.b).to_string()) });
+        assert!(registry.contains("add"));
+        assert_eq!(
+            registry
+                .call("add", "{\"a\": 2, \"b\": 3}".to_string())
+                .await
+                .expect("Failed to call the function"),
+            "5"
+        );
+        assert!(matches!(
+            registry.call("add", "{\"a\": 2}".to_string()).await,
+            Err(Error::FunctionFailed { .. })
+        ));
+    }
+
+    #[test]
+    fn test_register_replaces_by_name() {
+        let mut registry = registry();
+        registry.register(
+            FunctionSpecification::new(
+                "echo".to_string(),
+                Some("Repeats the arguments".to_string()),
+                None,
+            ),
+            |arguments| async move { Ok(arguments) },
+        );
+        let names: Vec<String> = registry
+            .specifications()
+            .into_iter()
+            .map(|spec| spec.name)
+            .collect();
+        assert_eq!(names, vec!["fail".to_string(), "echo".to_string()]);
+        assert_eq!(
+            registry
+                .specification("echo")
+                .and_then(|s| s.description.clone()),
+            Some("Repeats the arguments".to_string())
+        );
+        assert_eq!(registry.tools()[1].function.name, "echo");
+    }
+}
@@ -1,6 +1,5 @@
-use serde::{Deserialize, Serialize};
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
 use std::collections::HashMap;
-use std::fmt;
 
 /// The documentation for a function
 ///
@@ -49,7 +48,9 @@ use std::fmt;
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct FunctionSpecification {
     pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    #[serde(default, serialize_with = "parameters_or_empty")]
     pub parameters: Option<Parameters>,
 }
 
@@ -60,17 +61,125 @@ pub struct FunctionSpecification {
 pub struct Parameters {
     #[serde(rename = "type")]
     pub type_: String,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub properties: HashMap<String, Property>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub required: Vec<String>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+/// The schema of a parameter, in the subset of JSON Schema accepted by OpenAI
+/// https://json-schema.org/understanding-json-schema/reference
+///
+/// It is recursive, so it can describe arrays (`items`) and nested objects (`properties`),
+/// as well as alternatives with `any_of` and `one_of`.
+/// Only the fields that are set are sent, `type_` is not sent when it is empty,
+/// e.g. for a schema that only has `any_of`.
+///
+/// # Example
+/// ```
+/// use chatgpt_functions::function_specification::Property;
+///
+/// let ids = Property {
+///     type_: "array".to_string(),
+///     description: Some("The ids of the orders".to_string()),
+///     items: Some(Box::new(Property {
+///         type_: "integer".to_string(),
+///         minimum: Some(1.0),
+///         ..Property::default()
+///     })),
+///     ..Property::default()
+/// };
+/// assert_eq!(
+///     ids.to_string(),
+///     r#"{"type":"array","description":"The ids of the orders","items":{"type":"integer","minimum":1.0}}"#
+/// );
+/// ```
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
 pub struct Property {
-    #[serde(rename = "type")]
+    #[serde(rename = "type", default, skip_serializing_if = "String::is_empty")]
     pub type_: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
-    #[serde(rename = "enum")]
-    pub enum_: Option<Vec<String>>,
+    /// The allowed values, they can be strings, numbers, booleans or null
+    #[serde(rename = "enum", default, skip_serializing_if = "Option::is_none")]
+    pub enum_: Option<Vec<serde_json::Value>>,
+    /// The schema of the elements of an array
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub items: Option<Box<Property>>,
+    /// The fields of an object
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub properties: Option<HashMap<String, Property>>,
+    /// The fields of an object that are required
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub required: Option<Vec<String>>,
+    #[serde(
+        rename = "additionalProperties",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub additional_properties: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minimum: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maximum: Option<f64>,
+    #[serde(rename = "minItems", default, skip_serializing_if = "Option::is_none")]
+    pub min_items: Option<u64>,
+    #[serde(rename = "maxItems", default, skip_serializing_if = "Option::is_none")]
+    pub max_items: Option<u64>,
+    #[serde(rename = "minLength", default, skip_serializing_if = "Option::is_none")]
+    pub min_length: Option<u64>,
+    #[serde(rename = "maxLength", default, skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<u64>,
+    /// The format of a string, e.g. "date-time", "email" or "uuid"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    /// A regular expression that a string has to match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// The value used when the parameter is not provided
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<serde_json::Value>,
+    /// The value has to match at least one of these schemas
+    #[serde(rename = "anyOf", default, skip_serializing_if = "Option::is_none")]
+    pub any_of: Option<Vec<Property>>,
+    /// The value has to match exactly one of these schemas
+    #[serde(rename = "oneOf", default, skip_serializing_if = "Option::is_none")]
+    pub one_of: Option<Vec<Property>>,
+}
+
+impl Property {
+    /// Creates a property of the given type, e.g. "string", "integer", "array" or "object"
+    pub fn new(type_: String) -> Property {
+        Property {
+            type_,
+            ..Property::default()
+        }
+    }
+}
+
+/// A tool that the model can call, for the tools API of the newer models.
+/// The only type of tool supported by the API at the moment is "function"
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Tool {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub function: FunctionSpecification,
+}
+
+impl Tool {
+    /// Creates a function tool
+    pub fn function(function: FunctionSpecification) -> Tool {
+        Tool {
+            type_: "function".to_string(),
+            function,
+        }
+    }
+}
+
+impl From<FunctionSpecification> for Tool {
+    fn from(function: FunctionSpecification) -> Self {
+        Tool::function(function)
+    }
 }
 
 impl FunctionSpecification {
@@ -91,71 +200,23 @@ impl FunctionSpecification {
 // Display functions
 // ------------------------------------------------------------------------------
 
-// Print valid JSON for FunctionSpecification, no commas if last field, no field if None
-impl fmt::Display for FunctionSpecification {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{{\"name\":\"{}\"", self.name)?;
-        if let Some(description) = &self.description {
-            write!(f, ",\"description\":\"{}\"", description)?;
-        }
-        if let Some(parameters) = &self.parameters {
-            write!(f, ",\"parameters\":{}", parameters)?;
-        } else {
-            write!(
-                f,
-                ",\"parameters\":{{\"type\":\"object\",\"properties\":{{}}}}"
-            )?;
-        }
-        write!(f, "}}")
-    }
-}
-
-impl fmt::Display for Parameters {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{{\"type\":\"{}\"", self.type_)?;
-        if !self.properties.is_empty() {
-            write!(f, ",\"properties\":{{")?;
-            for (i, (key, value)) in self.properties.iter().enumerate() {
-                write!(f, "\"{}\":{}", key, value)?;
-                if i < self.properties.len() - 1 {
-                    write!(f, ",")?;
-                }
-            }
-            write!(f, "}}")?;
-        }
-        if !self.required.is_empty() {
-            write!(f, ",\"required\":[")?;
-            for (i, required) in self.required.iter().enumerate() {
-                write!(f, "\"{}\"", required)?;
-                if i < self.required.len() - 1 {
-                    write!(f, ",")?;
-                }
-            }
-            write!(f, "]")?;
+// Missing parameters are sent as an object without properties, the API requires them
+fn parameters_or_empty<S: Serializer>(
+    parameters: &Option<Parameters>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match parameters {
+        Some(parameters) => parameters.serialize(serializer),
+        None => {
+            let mut empty = serializer.serialize_struct("Parameters", 2)?;
+            empty.serialize_field("type", "object")?;
+            empty.serialize_field("properties", &HashMap::<String, Property>::new())?;
+            empty.end()
         }
-        write!(f, "}}")
     }
 }
 
-impl fmt::Display for Property {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{{\"type\":\"{}\"", self.type_)?;
-        if let Some(description) = &self.description {
-            write!(f, ",\"description\":\"{}\"", description)?;
-        }
-        if let Some(enum_) = &self.enum_ {
-            write!(f, ",\"enum\":[")?;
-            for (i, enum_value) in enum_.iter().enumerate() {
-                write!(f, "\"{}\"", enum_value)?;
-                if i < enum_.len() - 1 {
-                    write!(f, ",")?;
-                }
-            }
-            write!(f, "]")?;
-        }
-        write!(f, "}}")
-    }
-}
+display_as_json!(FunctionSpecification, Tool, Parameters, Property);
 
 // ------------------------------------------------------------------------------
 // Tests
@@ -163,6 +224,7 @@ impl fmt::Display for Property {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
 
     #[test]
     fn test_function_specification_new() {
@@ -235,7 +297,7 @@ mod tests {
         assert_eq!(unit.description, None);
         assert_eq!(
             unit.enum_,
-            Some(vec!["celsius".to_string(), "fahrenheit".to_string()])
+            Some(vec![json!("celsius"), json!("fahrenheit")])
         );
     }
 
@@ -260,7 +322,8 @@ mod tests {
             Property {
                 type_: "string".to_string(),
                 description: None,
-                enum_: Some(vec!["celsius".to_string(), "fahrenheit".to_string()]),
+                enum_: Some(vec![json!("celsius"), json!("fahrenheit")]),
+                ..Property::default()
             },
         );
         let parameters = Parameters {
@@ -292,7 +355,8 @@ mod tests {
         let property = Property {
             type_: "string".to_string(),
             description: Some("The city and state, e.g. San Francisco, CA".to_string()),
-            enum_: Some(vec!["celsius".to_string(), "fahrenheit".to_string()]),
+            enum_: Some(vec![json!("celsius"), json!("fahrenheit")]),
+            ..Property::default()
         };
         assert_eq!(
             property.to_string(),
@@ -306,6 +370,7 @@ mod tests {
             type_: "string".to_string(),
             description: Some("The city and state, e.g. San Francisco, CA".to_string()),
             enum_: None,
+            ..Property::default()
         };
         assert_eq!(
             property.to_string(),
@@ -318,7 +383,8 @@ mod tests {
         let property = Property {
             type_: "string".to_string(),
             description: None,
-            enum_: Some(vec!["celsius".to_string(), "fahrenheit".to_string()]),
+            enum_: Some(vec![json!("celsius"), json!("fahrenheit")]),
+            ..Property::default()
         };
         assert_eq!(
             property.to_string(),
@@ -326,6 +392,90 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_nested_schema_round_trip() {
+        let json = json!({
+            "type": "object",
+            "properties": {
+                "ids": {
+                    "type": "array",
+                    "description": "The ids of the orders",
+                    "items": {"type": "integer", "minimum": 1.0},
+                    "minItems": 1,
+                    "maxItems": 50
+                },
+                "filter": {
+                    "type": "object",
+                    "properties": {
+                        "since": {"type": "string", "format": "date-time"},
+                        "code": {"type": "string", "pattern": "^[A-Z]{3}$", "maxLength": 3}
+                    },
+                    "required": ["since"],
+                    "additionalProperties": false
+                },
+                "priority": {"type": "integer", "enum": [1, 2, 3], "default": 2},
+                "amount": {
+                    "anyOf": [
+                        {"type": "number", "minimum": 0.0, "maximum": 100.0},
+                        {"type": "null"}
+                    ]
+                },
+                "target": {
+                    "oneOf": [
+                        {"type": "string"},
+                        {"type": "array", "items": {"type": "string"}}
+                    ]
+                }
+            },
+            "required": ["ids"]
+        });
+        let parameters: Parameters =
+            serde_json::from_value(json.clone()).expect("Failed to parse the schema");
+
+        let ids = &parameters.properties["ids"];
+        assert_eq!(
+            ids.items,
+            Some(Box::new(Property {
+                minimum: Some(1.0),
+                ..Property::new("integer".to_string())
+            }))
+        );
+        assert_eq!(ids.min_items, Some(1));
+        let filter = &parameters.properties["filter"];
+        assert_eq!(filter.required, Some(vec!["since".to_string()]));
+        assert_eq!(filter.additional_properties, Some(false));
+        let since = &filter.properties.as_ref().expect("No properties")["since"];
+        assert_eq!(since.format, Some("date-time".to_string()));
+        let priority = &parameters.properties["priority"];
+        assert_eq!(priority.enum_, Some(vec![json!(1), json!(2), json!(3)]));
+        assert_eq!(priority.default, Some(json!(2)));
+        let amount = &parameters.properties["amount"];
+        assert_eq!(amount.type_, "");
+        assert_eq!(amount.any_of.as_ref().map(|a| a.len()), Some(2));
+
+        // Both serde and Display keep the whole schema
+        assert_eq!(
+            serde_json::to_value(&parameters).expect("Failed to serialize"),
+            json
+        );
+        let displayed: serde_json::Value =
+            serde_json::from_str(&parameters.to_string()).expect("Invalid JSON");
+        assert_eq!(displayed, json);
+    }
+
+    #[test]
+    fn test_display_tool() {
+        let tool = Tool::function(FunctionSpecification::new(
+            "get_current_weather".to_string(),
+            None,
+            None,
+        ));
+        assert_eq!(
+            tool.to_string(),
+            "{\"type\":\"function\",\"function\":{\"name\":\"get_current_weather\",\"parameters\":{\"type\":\"object\",\"properties\":{}}}}"
+        );
+    }
+
     #[test]
     fn test_display_function_specification() {
         let mut properties = HashMap::new();
@@ -334,7 +484,8 @@ mod tests {
             Property {
                 type_: "string".to_string(),
                 description: None,
-                enum_: Some(vec!["celsius".to_string(), "fahrenheit".to_string()]),
+                enum_: Some(vec![json!("celsius"), json!("fahrenheit")]),
+                ..Property::default()
             },
         );
         let parameters = Parameters {
@@ -23,13 +23,53 @@
 //!     Ok(())
 //! }
 
+// Implements Display as the JSON serialization of the types, which is what is sent to the API.
+// serde escapes every string, so any content round-trips exactly
+macro_rules! display_as_json {
+    ($($type_:ty),*) => {
+        $(
+            impl std::fmt::Display for $type_ {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    let json = serde_json::to_string(self).map_err(|_| std::fmt::Error)?;
+                    f.write_str(&json)
+                }
+            }
+        )*
+    };
+}
+
 // The main module to use, most of the use cases will only need this
 pub mod chat_gpt;
+// The errors returned by the library
+pub mod error;
+pub use error::{Error, Result};
+// Generate the function specifications from Rust types
+pub mod chat_function;
+pub use chat_function::{ChatFunction, ChatParameter};
+#[cfg(feature = "derive")]
+pub use chatgpt_functions_derive::{ChatFunction, ChatParameter};
 // Internals, to be used by the library or in case more control is needed
+pub mod cassette;
 pub mod chat_context;
 pub mod chat_response;
+pub mod chat_stream;
+pub mod compaction;
+pub mod completion_parameters;
+pub mod conversation_store;
+pub mod function_registry;
 pub mod function_specification;
 pub mod message;
+pub mod pricing;
+pub mod retry;
+pub mod system_prompt;
+pub mod tokenizer;
+pub mod transport;
+pub mod truncation;
+pub mod validation;
 
 // Escape a string to be used in JSON
 pub mod escape_json;
+
+// A local stand-in for the API, used by the tests
+#[cfg(test)]
+mod mock_server;
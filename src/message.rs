@@ -1,16 +1,74 @@
-use anyhow::Result;
-use std::fmt;
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::error::{Error, Result};
+
+/// The author of a message
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// The instructions of the conversation
+    System,
+    User,
+    Assistant,
+    /// The result of a function call, with the legacy functions API
+    Function,
+    /// The result of a tool call
+    Tool,
+}
 
-use serde::{Deserialize, Serialize};
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::Function => "function",
+            Role::Tool => "tool",
+        }
+    }
+}
 
-use crate::escape_json::EscapeJson;
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = Error;
+
+    fn from_str(role: &str) -> Result<Role> {
+        match role {
+            "system" => Ok(Role::System),
+            "user" => Ok(Role::User),
+            "assistant" => Ok(Role::Assistant),
+            "function" => Ok(Role::Function),
+            "tool" => Ok(Role::Tool),
+            _ => Err(Error::InvalidMessage(format!("Unknown role: {}", role))),
+        }
+    }
+}
 
 /// Builder for Message
+///
+/// The role is required, and build checks that the fields make sense for it,
+/// e.g. a function message needs the name of the function.
 pub struct MessageBuilder {
-    role: Option<String>,
+    role: Option<Role>,
     content: Option<String>,
     name: Option<String>,
     function_call: Option<FunctionCall>,
+    tool_calls: Option<Vec<ToolCall>>,
+    tool_call_id: Option<String>,
+    pinned: bool,
+}
+
+impl Default for MessageBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MessageBuilder {
@@ -20,10 +78,13 @@ impl MessageBuilder {
             content: None,
             name: None,
             function_call: None,
+            tool_calls: None,
+            tool_call_id: None,
+            pinned: false,
         }
     }
 
-    pub fn role(mut self, role: String) -> MessageBuilder {
+    pub fn role(mut self, role: Role) -> MessageBuilder {
         self.role = Some(role);
         self
     }
@@ -43,27 +104,123 @@ impl MessageBuilder {
         self
     }
 
+    pub fn tool_calls(mut self, tool_calls: Vec<ToolCall>) -> MessageBuilder {
+        self.tool_calls = Some(tool_calls);
+        self
+    }
+
+    pub fn tool_call_id(mut self, tool_call_id: String) -> MessageBuilder {
+        self.tool_call_id = Some(tool_call_id);
+        self
+    }
+
+    pub fn pinned(mut self, pinned: bool) -> MessageBuilder {
+        self.pinned = pinned;
+        self
+    }
+
+    /// Builds the message
+    /// # Errors
+    /// It returns Error::InvalidMessage when the API would reject the message:
+    /// * There is no role
+    /// * A function message has no name, or a tool message has no tool_call_id
+    /// * A message that is not from the assistant has function_call or tool_calls
+    /// * A message that is not a tool message has a tool_call_id
+    /// * A message has no content, and it is not an assistant message calling functions
     pub fn build(self) -> Result<Message> {
-        let role = self.role.unwrap_or_else(|| "user".to_string());
-        let content = self.content.map(|c| c.escape_json());
-        let name = self.name;
-        let function_call = self.function_call;
+        let invalid = |reason: &str| Err(Error::InvalidMessage(reason.to_string()));
+        let role = match self.role {
+            Some(role) => role,
+            None => return invalid("The role is missing"),
+        };
+        let calls_functions = self.function_call.is_some() || self.tool_calls.is_some();
+        if role == Role::Function && self.name.is_none() {
+            return invalid("A function message needs the name of the function");
+        }
+        if role == Role::Tool && self.tool_call_id.is_none() {
+            return invalid("A tool message needs the id of the tool call");
+        }
+        if role != Role::Assistant && calls_functions {
+            return invalid("Only assistant messages can call functions");
+        }
+        if role != Role::Tool && self.tool_call_id.is_some() {
+            return invalid("Only tool messages reply to a tool call");
+        }
+        if self.content.is_none() && !calls_functions {
+            return invalid(&format!("A {} message needs content", role));
+        }
 
         Ok(Message {
             role,
-            content,
-            name,
-            function_call,
+            content: self.content,
+            name: self.name,
+            function_call: self.function_call,
+            tool_calls: self.tool_calls,
+            tool_call_id: self.tool_call_id,
+            pinned: self.pinned,
         })
     }
 }
 
+/// A message sent by the user or the bot
+///
+/// It is printed as the JSON sent to the API. The strings are escaped when they are printed,
+/// so the content is kept exactly as it is written
+///
+/// # Notes
+/// The API asks for content to be present in the message, even when it's an assistant message with a function call
+/// https://platform.openai.com/docs/api-reference/chat/create
+///
+/// # Examples
+///
+/// ```
+/// use chatgpt_functions::message::{FunctionCall, Message, Role};
+///
+/// let mut message = Message::new(Role::Assistant);
+/// assert_eq!(message.to_string(), "{\"role\":\"assistant\",\"content\":\"\"}".to_string());
+///
+/// message.set_content("content".to_string());
+/// assert_eq!(
+///    message.to_string(),
+///    "{\"role\":\"assistant\",\"content\":\"content\"}".to_string()
+/// );
+///
+/// message.set_name("name".to_string());
+/// assert_eq!(
+///    message.to_string(),
+///    "{\"role\":\"assistant\",\"content\":\"content\",\"name\":\"name\"}".to_string()
+/// );
+///
+/// message.set_function_call(FunctionCall {
+///    name: "name".to_string(),
+///    arguments: "arguments".to_string(),
+/// });
+/// assert_eq!(
+///    message.to_string(),
+///    "{\"role\":\"assistant\",\"content\":\"content\",\"name\":\"name\",\"function_call\":{\"name\":\"name\",\"arguments\":\"arguments\"}}".to_string()
+/// );
+/// ```
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Message {
-    pub role: String,
+    pub role: Role,
+    // The API asks for content to be present in the message, even when it's an assistant message with a function call
+    // https://platform.openai.com/docs/api-reference/chat/create
+    #[serde(default, serialize_with = "content_or_empty")]
     pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub function_call: Option<FunctionCall>,
+    /// The tools called by the assistant, newer models can call several of them at once
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    /// The id of the tool call that a `tool` message replies to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    /// Pinned messages are kept as they are when the context is compacted, see ChatGPT::compact.
+    /// It is not sent to the API
+    #[serde(skip)]
+    pub pinned: bool,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -72,26 +229,105 @@ pub struct FunctionCall {
     pub arguments: String,
 }
 
+/// A call to a tool made by the assistant.
+/// The result has to be sent back in a `tool` message with the same id
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ToolCall {
+    pub id: String,
+    // Only "function" is supported by the API at the moment
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub function: FunctionCall,
+}
+
+impl ToolCall {
+    /// Creates a call to a function tool
+    pub fn new(id: String, function: FunctionCall) -> ToolCall {
+        ToolCall {
+            id,
+            type_: "function".to_string(),
+            function,
+        }
+    }
+}
+
 impl Message {
-    pub fn new(role: String) -> Message {
+    /// Creates an empty message. Use the other constructors or MessageBuilder for complete messages
+    pub fn new(role: Role) -> Message {
         Message {
             role,
             content: None,
             name: None,
             function_call: None,
+            tool_calls: None,
+            tool_call_id: None,
+            pinned: false,
         }
     }
 
-    pub fn new_user_message(content: String) -> Message {
-        let content = content.escape_json();
+    /// Creates a system message, with instructions for the model
+    pub fn system(content: String) -> Message {
         Message {
-            role: "user".to_string(),
             content: Some(content),
-            name: None,
-            function_call: None,
+            ..Message::new(Role::System)
         }
     }
 
+    /// Creates a message from the user
+    pub fn user(content: String) -> Message {
+        Message {
+            content: Some(content),
+            ..Message::new(Role::User)
+        }
+    }
+
+    /// Creates an answer of the assistant, e.g. to add examples to the context
+    pub fn assistant(content: String) -> Message {
+        Message {
+            content: Some(content),
+            ..Message::new(Role::Assistant)
+        }
+    }
+
+    /// Creates the message with the result of a function call, to send it back to the model
+    /// # Arguments
+    /// * `name` - The name of the function that was called
+    /// * `content` - The result of the function
+    pub fn function_result(name: String, content: String) -> Message {
+        Message {
+            content: Some(content),
+            name: Some(name),
+            ..Message::new(Role::Function)
+        }
+    }
+
+    /// Creates the message with the result of a tool call, to send it back to the model
+    /// # Arguments
+    /// * `tool_call_id` - The id of the ToolCall this message replies to
+    /// * `content` - The result of the tool
+    pub fn tool_result(tool_call_id: String, content: String) -> Message {
+        Message {
+            content: Some(content),
+            tool_call_id: Some(tool_call_id),
+            ..Message::new(Role::Tool)
+        }
+    }
+
+    /// Same as Message::user
+    pub fn new_user_message(content: String) -> Message {
+        Message::user(content)
+    }
+
+    /// Same as Message::function_result
+    pub fn new_function_message(name: String, content: String) -> Message {
+        Message::function_result(name, content)
+    }
+
+    /// Same as Message::tool_result
+    pub fn new_tool_message(tool_call_id: String, content: String) -> Message {
+        Message::tool_result(tool_call_id, content)
+    }
+
     pub fn set_content(&mut self, content: String) {
         self.content = Some(content);
     }
@@ -103,77 +339,28 @@ impl Message {
     pub fn set_function_call(&mut self, function_call: FunctionCall) {
         self.function_call = Some(function_call);
     }
-}
 
-/// A message sent by the user or the bot
-///
-/// Print valid JSON for Message, no commas if last field
-/// Arguments are escaped to avoid issues with quotes and newlines
-/// They break the JSON format and the API doesn't handle them well
-///
-/// # Notes
-/// The API asks for content to be present in the message, even when it's an assistant message with a function call
-/// https://platform.openai.com/docs/api-reference/chat/create
-///
-/// # Examples
-///
-/// ```
-/// use chatgpt_functions::message::{FunctionCall, Message};
-///
-/// let mut message = Message::new("role".to_string());
-/// assert_eq!(message.to_string(), "{\"role\":\"role\",\"content\":\"\"}".to_string());
-///
-/// message.set_content("content".to_string());
-/// assert_eq!(
-///    message.to_string(),
-///    "{\"role\":\"role\",\"content\":\"content\"}".to_string()
-/// );
-///
-/// message.set_name("name".to_string());
-/// assert_eq!(
-///    message.to_string(),
-///    "{\"role\":\"role\",\"content\":\"content\",\"name\":\"name\"}".to_string()
-/// );
-///
-/// message.set_function_call(FunctionCall {
-///    name: "name".to_string(),
-///    arguments: "arguments".to_string(),
-/// });
-/// assert_eq!(
-///    message.to_string(),
-///    "{\"role\":\"role\",\"content\":\"content\",\"name\":\"name\",\"function_call\":{\"name\":\"name\",\"arguments\":\"arguments\"}}".to_string()
-/// );
-/// ```
-impl fmt::Display for Message {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{{\"role\":\"{}\"", self.role)?;
-        if let Some(content) = &self.content {
-            write!(f, ",\"content\":\"{}\"", content.escape_json())?;
-        } else {
-            write!(f, ",\"content\":\"\"")?;
-        }
-        if let Some(name) = &self.name {
-            write!(f, ",\"name\":\"{}\"", name)?;
-        }
-        if let Some(function_call) = &self.function_call {
-            write!(f, ",\"function_call\":{}", function_call)?;
-        }
-        write!(f, "}}")
+    pub fn set_tool_calls(&mut self, tool_calls: Vec<ToolCall>) {
+        self.tool_calls = Some(tool_calls);
     }
-}
 
-// Print valid JSON for FunctionCall, no commas if last field
-// Arguments are escaped to avoid issues with quotes and newlines
-// They break the JSON format and the API doesn't handle them well
-impl fmt::Display for FunctionCall {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{{\"name\":\"{}\",\"arguments\":\"{}\"}}",
-            self.name,
-            self.arguments.escape_json()
-        )
+    pub fn set_tool_call_id(&mut self, tool_call_id: String) {
+        self.tool_call_id = Some(tool_call_id);
     }
+
+    pub fn set_pinned(&mut self, pinned: bool) {
+        self.pinned = pinned;
+    }
+}
+
+display_as_json!(Message, FunctionCall, ToolCall);
+
+// A missing content is sent as an empty string
+fn content_or_empty<S: Serializer>(
+    content: &Option<String>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(content.as_deref().unwrap_or_default())
 }
 
 #[cfg(test)]
@@ -182,10 +369,10 @@ mod tests {
 
     #[test]
     fn test_display_message() {
-        let mut message = Message::new("role".to_string());
+        let mut message = Message::new(Role::Assistant);
         assert_eq!(
             message.to_string(),
-            "{\"role\":\"role\",\"content\":\"\"}".to_string()
+            "{\"role\":\"assistant\",\"content\":\"\"}".to_string()
         );
 
         message.set_content(
@@ -194,14 +381,14 @@ mod tests {
         );
         assert_eq!(
             message.to_string(),
-            "{\"role\":\"role\",\"content\":\"content with \\\"quotes\\\" and a \\nnewline, and other stuff like \\\\ \\\"\\n\\r\\t\\b\\f\\\"\"}"
+            "{\"role\":\"assistant\",\"content\":\"content with \\\"quotes\\\" and a \\nnewline, and other stuff like \\\\ \\\"\\n\\r\\t\\b\\f\\\"\"}"
                 .to_string()
         );
 
         message.set_name("name".to_string());
         assert_eq!(
             message.to_string(),
-            "{\"role\":\"role\",\"content\":\"content with \\\"quotes\\\" and a \\nnewline, and other stuff like \\\\ \\\"\\n\\r\\t\\b\\f\\\"\",\"name\":\"name\"}"
+            "{\"role\":\"assistant\",\"content\":\"content with \\\"quotes\\\" and a \\nnewline, and other stuff like \\\\ \\\"\\n\\r\\t\\b\\f\\\"\",\"name\":\"name\"}"
                 .to_string()
         );
 
@@ -212,7 +399,7 @@ mod tests {
         message.set_function_call(function_call);
         assert_eq!(
             message.to_string(),
-            "{\"role\":\"role\",\"content\":\"content with \\\"quotes\\\" and a \\nnewline, and other stuff like \\\\ \\\"\\n\\r\\t\\b\\f\\\"\",\"name\":\"name\",\"function_call\":{\"name\":\"name\",\"arguments\":\"{\\\"example\\\":\\\"this\\\"}\"}}".to_string()
+            "{\"role\":\"assistant\",\"content\":\"content with \\\"quotes\\\" and a \\nnewline, and other stuff like \\\\ \\\"\\n\\r\\t\\b\\f\\\"\",\"name\":\"name\",\"function_call\":{\"name\":\"name\",\"arguments\":\"{\\\"example\\\":\\\"this\\\"}\"}}".to_string()
         );
     }
 
@@ -253,7 +440,7 @@ mod tests {
     }
 
     #[test]
-    fn test_display_message_parsed_from_json_remove_newline() {
+    fn test_display_message_parsed_from_json_keeps_newlines() {
         let message = r#"{
             "role": "assistant",
             "content": null,
@@ -266,8 +453,7 @@ mod tests {
         let message_parsed: Message =
             serde_json::from_str(&message).expect("JSON was not well-formatted");
 
-        // When we parse the JSON, we remove the newlines
-        assert_eq!(message_parsed.role, "assistant".to_string());
+        assert_eq!(message_parsed.role, Role::Assistant);
         assert_eq!(message_parsed.content, None);
 
         // The API asks for content to be present in the message, even when it's an assistant message with a function call
@@ -277,7 +463,7 @@ mod tests {
             "{\"role\":\"assistant\",\"content\":\"\",\"function_call\":{\"name\":\"completion_managed\",\"arguments\":\"{\\n  \\\"content\\\": \\\"Hi model, how are you today?\\\"\\n}\"}}".to_string()
         );
 
-        // When we don't use our custom Display trait, the newlines are kept
+        // The newlines of the arguments are kept, and escaped in the JSON sent
         assert_eq!(
             message_parsed.function_call,
             Some(FunctionCall {
@@ -287,14 +473,92 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_display_message_with_tool_calls() {
+        let mut message = Message::new(Role::Assistant);
+        message.set_tool_calls(vec![
+            ToolCall::new(
+                "call_1".to_string(),
+                FunctionCall {
+                    name: "get_current_weather".to_string(),
+                    arguments: "{\"location\":\"Madrid\"}".to_string(),
+                },
+            ),
+            ToolCall::new(
+                "call_2".to_string(),
+                FunctionCall {
+                    name: "get_current_weather".to_string(),
+                    arguments: "{\"location\":\"Paris\"}".to_string(),
+                },
+            ),
+        ]);
+        assert_eq!(
+            message.to_string(),
+            "{\"role\":\"assistant\",\"content\":\"\",\"tool_calls\":[{\"id\":\"call_1\",\"type\":\"function\",\"function\":{\"name\":\"get_current_weather\",\"arguments\":\"{\\\"location\\\":\\\"Madrid\\\"}\"}},{\"id\":\"call_2\",\"type\":\"function\",\"function\":{\"name\":\"get_current_weather\",\"arguments\":\"{\\\"location\\\":\\\"Paris\\\"}\"}}]}"
+        );
+    }
+
+    #[test]
+    fn test_message_new_tool_message() {
+        let message = Message::new_tool_message("call_1".to_string(), "22 degrees".to_string());
+        assert_eq!(
+            message.to_string(),
+            "{\"role\":\"tool\",\"content\":\"22 degrees\",\"tool_call_id\":\"call_1\"}"
+        );
+    }
+
+    #[test]
+    fn test_deserialize_message_with_tool_calls() {
+        let message: Message = serde_json::from_str(
+            r#"{
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [
+                    {
+                        "id": "call_abc",
+                        "type": "function",
+                        "function": {"name": "get_current_weather", "arguments": "{\"location\": \"Madrid\"}"}
+                    }
+                ]
+            }"#,
+        )
+        .expect("Failed to parse the message");
+        assert_eq!(message.function_call, None);
+        assert_eq!(
+            message.tool_calls,
+            Some(vec![ToolCall::new(
+                "call_abc".to_string(),
+                FunctionCall {
+                    name: "get_current_weather".to_string(),
+                    arguments: "{\"location\": \"Madrid\"}".to_string(),
+                }
+            )])
+        );
+    }
+
     #[test]
     fn test_message_new_user_message() {
         let message =
             Message::new_user_message("content with \"quotes\" and other' stuff \\".to_string());
+        // The content is escaped only once, when it is printed
         assert_eq!(
             message.to_string(),
-            "{\"role\":\"user\",\"content\":\"content with \\\\\\\"quotes\\\\\\\" and other' stuff \\\\\\\\\"}".to_string()
+            "{\"role\":\"user\",\"content\":\"content with \\\"quotes\\\" and other' stuff \\\\\"}"
+                .to_string()
         );
+        let parsed: Message =
+            serde_json::from_str(&message.to_string()).expect("JSON was not well-formatted");
+        assert_eq!(parsed, message);
+    }
+
+    #[test]
+    fn test_display_escapes_every_field() {
+        let mut message = Message::new(Role::Function);
+        message.set_name("name \"with\" quotes".to_string());
+        message.set_content("line 1\nline 2\t\u{1}\"quoted\" \\ end".to_string());
+        let parsed: Message =
+            serde_json::from_str(&message.to_string()).expect("JSON was not well-formatted");
+        assert_eq!(parsed, message);
     }
 
     #[test]
@@ -302,7 +566,7 @@ mod tests {
         let message = MessageBuilder::new()
             .content("content with \"quotes\" and other/' stuff \\".to_string())
             .name("name".to_string())
-            .role("role".to_string())
+            .role(Role::Assistant)
             .function_call(FunctionCall {
                 name: "name".to_string(),
                 arguments: "{\"example\":\"this\"}".to_string(),
@@ -312,7 +576,94 @@ mod tests {
 
         assert_eq!(
             message.to_string(),
-            "{\"role\":\"role\",\"content\":\"content with \\\\\\\"quotes\\\\\\\" and other/' stuff \\\\\\\\\",\"name\":\"name\",\"function_call\":{\"name\":\"name\",\"arguments\":\"{\\\"example\\\":\\\"this\\\"}\"}}".to_string()
+            "{\"role\":\"assistant\",\"content\":\"content with \\\"quotes\\\" and other/' stuff \\\\\",\"name\":\"name\",\"function_call\":{\"name\":\"name\",\"arguments\":\"{\\\"example\\\":\\\"this\\\"}\"}}".to_string()
+        );
+    }
+
+    #[test]
+    fn test_role() {
+        assert_eq!(
+            serde_json::to_string(&Role::Assistant).unwrap(),
+            "\"assistant\""
+        );
+        assert_eq!(
+            serde_json::from_str::<Role>("\"tool\"").expect("Invalid role"),
+            Role::Tool
+        );
+        assert!(serde_json::from_str::<Role>("\"asistant\"").is_err());
+        assert_eq!(
+            "system".parse::<Role>().expect("Invalid role"),
+            Role::System
+        );
+        assert!(matches!(
+            "asistant".parse::<Role>(),
+            Err(Error::InvalidMessage(_))
+        ));
+        assert_eq!(Role::Function.to_string(), "function");
+    }
+
+    #[test]
+    fn test_constructors() {
+        assert_eq!(
+            Message::system("Be brief".to_string()).to_string(),
+            "{\"role\":\"system\",\"content\":\"Be brief\"}"
+        );
+        assert_eq!(
+            Message::assistant("Hi!".to_string()).to_string(),
+            "{\"role\":\"assistant\",\"content\":\"Hi!\"}"
+        );
+        assert_eq!(
+            Message::function_result("get_time".to_string(), "12:00".to_string()).to_string(),
+            "{\"role\":\"function\",\"content\":\"12:00\",\"name\":\"get_time\"}"
+        );
+        assert_eq!(
+            Message::user("Hello".to_string()),
+            Message::new_user_message("Hello".to_string())
+        );
+    }
+
+    #[test]
+    fn test_message_builder_rejects_invalid_messages() {
+        let function_call = FunctionCall {
+            name: "get_time".to_string(),
+            arguments: "{}".to_string(),
+        };
+        let invalid = [
+            MessageBuilder::new().content("Hi".to_string()),
+            MessageBuilder::new()
+                .role(Role::Function)
+                .content("12:00".to_string()),
+            MessageBuilder::new()
+                .role(Role::Tool)
+                .content("12:00".to_string()),
+            MessageBuilder::new()
+                .role(Role::User)
+                .function_call(function_call.clone()),
+            MessageBuilder::new()
+                .role(Role::User)
+                .content("Hi".to_string())
+                .tool_call_id("call_1".to_string()),
+            MessageBuilder::new().role(Role::System),
+        ];
+        for builder in invalid {
+            assert!(matches!(builder.build(), Err(Error::InvalidMessage(_))));
+        }
+
+        let message = MessageBuilder::new()
+            .role(Role::Assistant)
+            .function_call(function_call)
+            .build()
+            .expect("The assistant can call a function without content");
+        assert_eq!(message.content, None);
+        let message = MessageBuilder::new()
+            .role(Role::Tool)
+            .tool_call_id("call_1".to_string())
+            .content("12:00".to_string())
+            .build()
+            .expect("Failed to build the tool message");
+        assert_eq!(
+            message,
+            Message::tool_result("call_1".to_string(), "12:00".to_string())
         );
     }
 }
@@ -0,0 +1,227 @@
+//! A minimal HTTP server used by the tests, to exercise the library without network access.
+//!
+//! It replies to each incoming connection with the next scripted response, and records the
+//! requests it receives so the tests can assert on them.
+//! It also builds the bodies of the completions replied by the mock server and the scripted transports.
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::chat_response::Usage;
+
+/// A response that the mock server will send back
+#[derive(Clone, Debug)]
+pub(crate) struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl MockResponse {
+    pub fn new(status: u16, body: &str) -> MockResponse {
+        MockResponse {
+            status,
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: body.to_string(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> MockResponse {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// A request received by the mock server
+#[derive(Clone, Debug)]
+pub(crate) struct RecordedRequest {
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+pub(crate) struct MockServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+impl MockServer {
+    /// Starts a server on a random local port that replies with the responses in order.
+    /// Once the responses are exhausted, it replies with a 500 error.
+    pub async fn start(responses: Vec<MockResponse>) -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind the mock server");
+        let url = format!(
+            "http://{}",
+            listener.local_addr().expect("Failed to get the address")
+        );
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+
+        tokio::spawn(async move {
+            let mut responses = responses.into_iter();
+            while let Ok((stream, _)) = listener.accept().await {
+                let response = responses
+                    .next()
+                    .unwrap_or_else(|| MockResponse::new(500, "mock server exhausted"));
+                handle(stream, response, &recorded).await;
+            }
+        });
+
+        MockServer { url, requests }
+    }
+
+    /// Returns the requests received so far
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().expect("Poisoned lock").clone()
+    }
+}
+
+// The request is recorded before replying, so it is visible as soon as the client gets the response
+async fn handle(
+    mut stream: TcpStream,
+    response: MockResponse,
+    recorded: &Mutex<Vec<RecordedRequest>>,
+) -> Option<()> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+
+    // Read until the end of the headers
+    let header_end = loop {
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if let Some(position) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break position + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let path = lines
+        .next()?
+        .split(' ')
+        .nth(1)
+        .unwrap_or_default()
+        .to_string();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(n, v)| (n.trim().to_string(), v.trim().to_string()))
+        .collect();
+    let content_length = headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, v)| v.parse::<usize>().ok())
+        .unwrap_or(0);
+
+    // Read the rest of the body
+    while buffer.len() < header_end + content_length {
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+    let body = String::from_utf8_lossy(&buffer[header_end..]).to_string();
+    recorded
+        .lock()
+        .expect("Poisoned lock")
+        .push(RecordedRequest {
+            path,
+            headers,
+            body,
+        });
+
+    let mut reply = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        reply.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    reply.push_str("\r\n");
+    reply.push_str(&response.body);
+    stream.write_all(reply.as_bytes()).await.ok()?;
+    stream.shutdown().await.ok()
+}
+
+/// The body of a completion with these messages as choices, and their finish reasons
+pub(crate) fn completion(choices: &[(Value, &str)], usage: Usage) -> String {
+    let choices: Vec<Value> = choices
+        .iter()
+        .enumerate()
+        .map(|(index, (message, finish_reason))| {
+            json!({"index": index, "message": message, "finish_reason": finish_reason})
+        })
+        .collect();
+    json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion",
+        "created": 1687596091,
+        "choices": choices,
+        "usage": usage,
+    })
+    .to_string()
+}
+
+/// The body of a completion where the assistant answers with content
+pub(crate) fn answer(content: &str, usage: Usage) -> String {
+    completion(
+        &[(json!({"role": "assistant", "content": content}), "stop")],
+        usage,
+    )
+}
+
+/// The body of a completion where the assistant calls a function
+pub(crate) fn function_call(name: &str, arguments: &str, usage: Usage) -> String {
+    let function_call = json!({"name": name, "arguments": arguments});
+    completion(
+        &[(
+            json!({"role": "assistant", "content": null, "function_call": function_call}),
+            "function_call",
+        )],
+        usage,
+    )
+}
+
+/// The body of a completion where the assistant calls tools, given as (id, name, arguments)
+pub(crate) fn tool_calls(calls: &[(&str, &str, &str)], usage: Usage) -> String {
+    let tool_calls: Vec<Value> = calls
+        .iter()
+        .map(|(id, name, arguments)| {
+            json!({"id": id, "type": "function", "function": {"name": name, "arguments": arguments}})
+        })
+        .collect();
+    completion(
+        &[(
+            json!({"role": "assistant", "content": null, "tool_calls": tool_calls}),
+            "tool_calls",
+        )],
+        usage,
+    )
+}
+
+pub(crate) fn usage(prompt_tokens: u32, completion_tokens: u32) -> Usage {
+    Usage {
+        prompt_tokens,
+        completion_tokens,
+        total_tokens: prompt_tokens + completion_tokens,
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+
+use crate::{chat_context::ChatContext, chat_response::Usage, tokenizer::Tokenizer};
+
+/// The price of a model, in dollars per million tokens
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ModelPrice {
+    /// The price of the tokens sent: messages, functions and tools
+    pub prompt: f64,
+    /// The price of the tokens generated by the model
+    pub completion: f64,
+}
+
+impl ModelPrice {
+    pub fn new(prompt: f64, completion: f64) -> ModelPrice {
+        ModelPrice { prompt, completion }
+    }
+
+    /// The cost in dollars of the tokens used
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        (usage.prompt_tokens as f64 * self.prompt
+            + usage.completion_tokens as f64 * self.completion)
+            / 1_000_000.0
+    }
+}
+
+/// The prices of the models, used by ChatGPT to compute the cost of the completions
+/// and to enforce the budget of the session
+///
+/// There are no prices by default, they change too often to be hardcoded:
+/// set the ones of the models you use, as listed on the [pricing page](https://openai.com/api/pricing/)
+/// or by your provider. The models are matched by their exact name, as set in the context.
+///
+/// # Example
+/// ```
+/// use chatgpt_functions::{
+///     chat_gpt::ChatGPTBuilder,
+///     chat_response::Usage,
+///     pricing::{ModelPrice, PriceTable},
+/// };
+///
+/// let prices = PriceTable::new()
+///     .price("gpt-4o".to_string(), ModelPrice::new(2.5, 10.0))
+///     .price("gpt-4o-mini".to_string(), ModelPrice::new(0.15, 0.6));
+/// let usage = Usage {
+///     prompt_tokens: 1000,
+///     completion_tokens: 100,
+///     total_tokens: 1100,
+/// };
+/// assert_eq!(prices.cost("gpt-4o", &usage), Some(0.0035));
+///
+/// let gpt = ChatGPTBuilder::new()
+///     .openai_api_token("key".to_string())
+///     .model("gpt-4o".to_string())
+///     .price_table(prices)
+///     .budget(5.0)
+///     .build();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PriceTable {
+    pub prices: BTreeMap<String, ModelPrice>,
+}
+
+impl PriceTable {
+    pub fn new() -> PriceTable {
+        PriceTable::default()
+    }
+
+    pub fn price(mut self, model: String, price: ModelPrice) -> PriceTable {
+        self.set_price(model, price);
+        self
+    }
+
+    pub fn set_price(&mut self, model: String, price: ModelPrice) {
+        self.prices.insert(model, price);
+    }
+
+    pub fn get(&self, model: &str) -> Option<ModelPrice> {
+        self.prices.get(model).copied()
+    }
+
+    /// The cost in dollars of the tokens used by the model, if it has a price
+    pub fn cost(&self, model: &str, usage: &Usage) -> Option<f64> {
+        self.get(model).map(|price| price.cost(usage))
+    }
+
+    /// The highest cost in dollars that sending the context can have, if its model has a price:
+    /// the tokens of the prompt, counted offline, and `max_tokens` of completion when it is set.
+    /// Without `max_tokens` only the prompt is estimated, the completion has no limit
+    pub fn estimate(&self, chat_context: &ChatContext) -> Option<f64> {
+        let price = self.get(&chat_context.model)?;
+        let prompt_tokens = Tokenizer::for_model(&chat_context.model).count_context(chat_context);
+        let prompt_tokens = u32::try_from(prompt_tokens).unwrap_or(u32::MAX);
+        let completion_tokens = chat_context
+            .parameters
+            .max_tokens
+            .unwrap_or(0)
+            .saturating_mul(chat_context.parameters.n.unwrap_or(1));
+        Some(price.cost(&Usage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens.saturating_add(completion_tokens),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Message;
+
+    #[test]
+    fn test_cost() {
+        let prices = PriceTable::new().price("gpt-4o".to_string(), ModelPrice::new(2.5, 10.0));
+        let usage = Usage {
+            prompt_tokens: 2_000_000,
+            completion_tokens: 500_000,
+            total_tokens: 2_500_000,
+        };
+        assert_eq!(prices.cost("gpt-4o", &usage), Some(10.0));
+        // The models are not matched by prefix, a snapshot can have another price
+        assert_eq!(prices.cost("gpt-4o-mini", &usage), None);
+        assert_eq!(prices.cost("gpt-4o-2024-05-13", &usage), None);
+    }
+
+    #[test]
+    fn test_estimate() {
+        let prices = PriceTable::new().price("gpt-4o".to_string(), ModelPrice::new(1.0, 2.0));
+        let mut chat_context = ChatContext::new("gpt-4o".to_string());
+        chat_context.push_message(Message::user("Hello".to_string()));
+        let prompt_tokens = chat_context.count_tokens() as f64;
+        assert_eq!(prices.estimate(&chat_context), Some(prompt_tokens / 1e6));
+
+        chat_context.parameters.max_tokens = Some(100);
+        chat_context.parameters.n = Some(2);
+        assert_eq!(
+            prices.estimate(&chat_context),
+            Some((prompt_tokens + 400.0) / 1e6)
+        );
+
+        // Huge limits don't overflow, the estimate is only too high to be sent
+        chat_context.parameters.max_tokens = Some(u32::MAX);
+        chat_context.parameters.n = Some(128);
+        assert_eq!(
+            prices.estimate(&chat_context),
+            Some((prompt_tokens + 2.0 * u32::MAX as f64) / 1e6)
+        );
+        chat_context.model = "gpt-4".to_string();
+        assert_eq!(prices.estimate(&chat_context), None);
+    }
+}
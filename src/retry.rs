@@ -0,0 +1,271 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::error::Error;
+
+/// The policy used to retry the requests that fail with a transient error,
+/// like a rate limit (429) or a server error (5xx)
+///
+/// The delay between attempts grows exponentially from `base_delay`, up to `max_delay`,
+/// and it is randomised by `jitter` so that many clients don't retry at the same time.
+/// When the API sends a `Retry-After` header, it is used instead of the computed delay,
+/// also up to `max_delay` so that a server can't hold the client for hours.
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use chatgpt_functions::{chat_gpt::ChatGPTBuilder, retry::RetryPolicy};
+///
+/// let gpt = ChatGPTBuilder::new()
+///     .openai_api_token("key".to_string())
+///     .retry_policy(
+///         RetryPolicy::new()
+///             .max_attempts(5)
+///             .base_delay(Duration::from_secs(1)),
+///     )
+///     .build();
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts, including the first one. 1 means no retries
+    pub max_attempts: u32,
+    /// The delay before the first retry, doubled on every retry
+    pub base_delay: Duration,
+    /// The maximum delay between two attempts, including the ones set by Retry-After
+    pub max_delay: Duration,
+    /// The fraction of the delay that is randomised, between 0.0 (no jitter) and 1.0.
+    /// Other values are clamped to that range
+    pub jitter: f64,
+    /// The HTTP status codes that are retried
+    pub retry_statuses: Vec<u16>,
+    /// Wait for the time sent by the API in the Retry-After header, when there is one,
+    /// up to `max_delay`
+    pub respect_retry_after: bool,
+    /// Retry when the request can't be sent or times out
+    pub retry_transport_errors: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: 0.2,
+            retry_statuses: vec![408, 409, 429, 500, 502, 503, 504],
+            respect_retry_after: true,
+            retry_transport_errors: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy with sensible defaults: 3 attempts, starting at 500ms,
+    /// retrying rate limits, timeouts and server errors
+    pub fn new() -> RetryPolicy {
+        RetryPolicy::default()
+    }
+
+    /// A policy that never retries. This is the default of ChatGPT
+    pub fn none() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        }
+    }
+
+    pub fn max_attempts(mut self, max_attempts: u32) -> RetryPolicy {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    pub fn base_delay(mut self, base_delay: Duration) -> RetryPolicy {
+        self.base_delay = base_delay;
+        self
+    }
+
+    pub fn max_delay(mut self, max_delay: Duration) -> RetryPolicy {
+        self.max_delay = max_delay;
+        self
+    }
+
+    pub fn jitter(mut self, jitter: f64) -> RetryPolicy {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn retry_statuses(mut self, retry_statuses: Vec<u16>) -> RetryPolicy {
+        self.retry_statuses = retry_statuses;
+        self
+    }
+
+    pub fn respect_retry_after(mut self, respect_retry_after: bool) -> RetryPolicy {
+        self.respect_retry_after = respect_retry_after;
+        self
+    }
+
+    pub fn retry_transport_errors(mut self, retry_transport_errors: bool) -> RetryPolicy {
+        self.retry_transport_errors = retry_transport_errors;
+        self
+    }
+
+    /// Returns true if the request should be tried again after failing with `error`
+    /// `attempt` is the number of the attempt that failed, starting at 1
+    pub fn should_retry(&self, attempt: u32, error: &Error) -> bool {
+        if attempt >= self.max_attempts {
+            return false;
+        }
+        match error {
+            Error::Api { status, .. } | Error::Http { status, .. } => {
+                self.retry_statuses.contains(status)
+            }
+            Error::Transport(e) => {
+                self.retry_transport_errors && (e.is_timeout() || e.is_connect() || e.is_request())
+            }
+            Error::Connection(_) => self.retry_transport_errors,
+            _ => false,
+        }
+    }
+
+    /// Returns how long to wait before the next attempt
+    /// `attempt` is the number of the attempt that failed, starting at 1
+    pub fn delay(&self, attempt: u32, error: &Error) -> Duration {
+        if self.respect_retry_after {
+            if let Some(retry_after) = error.retry_after() {
+                return retry_after.min(self.max_delay);
+            }
+        }
+        let exponent = attempt.saturating_sub(1).min(31);
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(exponent))
+            .min(self.max_delay);
+        // The field is public, it may not have been clamped by the setter
+        let jitter = if self.jitter.is_nan() {
+            0.0
+        } else {
+            self.jitter.clamp(0.0, 1.0)
+        };
+        if jitter == 0.0 {
+            return delay;
+        }
+        let factor = 1.0 + jitter * rand::thread_rng().gen_range(-1.0..=1.0);
+        // The jitter can't go over max_delay either, nor overflow when it is near Duration::MAX
+        Duration::try_from_secs_f64(delay.as_secs_f64() * factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::RateLimit;
+
+    fn http_error(status: u16, retry_after: Option<Duration>) -> Error {
+        Error::Http {
+            status,
+            body: "error".to_string(),
+            rate_limit: Box::new(RateLimit {
+                retry_after,
+                ..RateLimit::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn test_should_retry() {
+        let policy = RetryPolicy::new().max_attempts(3);
+        assert!(policy.should_retry(1, &http_error(429, None)));
+        assert!(policy.should_retry(2, &http_error(503, None)));
+        assert!(!policy.should_retry(3, &http_error(503, None)));
+        assert!(!policy.should_retry(1, &http_error(400, None)));
+        assert!(!policy.should_retry(1, &http_error(401, None)));
+        assert!(!RetryPolicy::none().should_retry(1, &http_error(429, None)));
+        assert!(!policy.should_retry(
+            1,
+            &Error::MissingConfiguration("OpenAI API token is missing".to_string())
+        ));
+    }
+
+    #[test]
+    fn test_exponential_delay() {
+        let policy = RetryPolicy::new()
+            .base_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(1000))
+            .jitter(0.0);
+        let error = http_error(500, None);
+        assert_eq!(policy.delay(1, &error), Duration::from_millis(100));
+        assert_eq!(policy.delay(2, &error), Duration::from_millis(200));
+        assert_eq!(policy.delay(3, &error), Duration::from_millis(400));
+        assert_eq!(policy.delay(5, &error), Duration::from_millis(1000));
+        assert_eq!(policy.delay(100, &error), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn test_delay_with_jitter() {
+        let policy = RetryPolicy::new()
+            .base_delay(Duration::from_millis(100))
+            .jitter(0.5);
+        for _ in 0..100 {
+            let delay = policy.delay(1, &http_error(500, None));
+            assert!(delay >= Duration::from_millis(50));
+            assert!(delay <= Duration::from_millis(150));
+        }
+
+        // The values out of range set on the field are clamped too
+        let error = http_error(500, None);
+        for jitter in [-3.0, f64::NAN, f64::NEG_INFINITY] {
+            let policy = RetryPolicy {
+                jitter,
+                ..policy.clone()
+            };
+            assert_eq!(policy.delay(1, &error), Duration::from_millis(100));
+        }
+        let policy = RetryPolicy {
+            jitter: 5.0,
+            ..policy
+        };
+        for _ in 0..100 {
+            assert!(policy.delay(1, &error) <= Duration::from_millis(200));
+        }
+    }
+
+    #[test]
+    fn test_delay_with_jitter_at_max_delay() {
+        let error = http_error(500, None);
+        let policy = RetryPolicy::new()
+            .base_delay(Duration::from_secs(1))
+            .max_delay(Duration::from_secs(30))
+            .jitter(0.2);
+        for _ in 0..1000 {
+            let delay = policy.delay(10, &error);
+            assert!(delay <= Duration::from_secs(30));
+            assert!(delay >= Duration::from_secs(24));
+        }
+
+        // Duration::MAX can be used as no cap, the jitter doesn't overflow
+        let policy = RetryPolicy::new()
+            .base_delay(Duration::MAX)
+            .max_delay(Duration::MAX)
+            .jitter(1.0);
+        for _ in 0..1000 {
+            policy.delay(3, &error);
+        }
+    }
+
+    #[test]
+    fn test_delay_with_retry_after() {
+        let policy = RetryPolicy::new().base_delay(Duration::from_millis(100));
+        let error = http_error(429, Some(Duration::from_secs(20)));
+        assert_eq!(policy.delay(1, &error), Duration::from_secs(20));
+
+        // A Retry-After longer than max_delay is capped
+        let error = http_error(429, Some(Duration::from_secs(3600)));
+        assert_eq!(policy.delay(1, &error), Duration::from_secs(30));
+
+        let policy = policy.respect_retry_after(false).jitter(0.0);
+        assert_eq!(policy.delay(1, &error), Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+
+use crate::error::{Error, Result};
+
+/// The template of the system prompt, with `{{variable}}` placeholders
+///
+/// The placeholders are replaced with the values of the variables when the prompt is rendered.
+/// Spaces inside the braces are ignored, `{{ name }}` is the same as `{{name}}`.
+///
+/// # Example
+/// ```
+/// use chatgpt_functions::system_prompt::SystemPrompt;
+///
+/// let prompt = SystemPrompt::new("You help {{user}} with the orders of {{ shop }}.".to_string())
+///     .variable("user".to_string(), "Ana".to_string())
+///     .variable("shop".to_string(), "ACME".to_string());
+/// assert_eq!(prompt.render().unwrap(), "You help Ana with the orders of ACME.");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SystemPrompt {
+    pub template: String,
+    pub variables: BTreeMap<String, String>,
+}
+
+impl SystemPrompt {
+    pub fn new(template: String) -> SystemPrompt {
+        SystemPrompt {
+            template,
+            variables: BTreeMap::new(),
+        }
+    }
+
+    pub fn variable(mut self, name: String, value: String) -> SystemPrompt {
+        self.set_variable(name, value);
+        self
+    }
+
+    pub fn set_variable(&mut self, name: String, value: String) {
+        self.variables.insert(name, value);
+    }
+
+    /// Replaces the placeholders of the template with the values of the variables
+    /// # Errors
+    /// It returns Error::InvalidMessage if a placeholder has no value, or it is not closed
+    pub fn render(&self) -> Result<String> {
+        let mut rendered = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+        while let Some(start) = rest.find("{{") {
+            rendered.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let end = after.find("}}").ok_or_else(|| {
+                Error::InvalidMessage(format!(
+                    "The system prompt has a placeholder that is not closed: {{{{{}",
+                    after
+                ))
+            })?;
+            let name = after[..end].trim();
+            let value = self.variables.get(name).ok_or_else(|| {
+                Error::InvalidMessage(format!(
+                    "The variable {} of the system prompt has no value",
+                    name
+                ))
+            })?;
+            rendered.push_str(value);
+            rest = &after[end + 2..];
+        }
+        rendered.push_str(rest);
+        Ok(rendered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let prompt = SystemPrompt::new("Hi {{name}}, {{ name }}! {{greeting}}".to_string())
+            .variable("name".to_string(), "Ana".to_string())
+            .variable("greeting".to_string(), "{{name}}".to_string());
+        // The values are not rendered again
+        assert_eq!(
+            prompt.render().expect("Failed to render"),
+            "Hi Ana, Ana! {{name}}"
+        );
+        assert_eq!(
+            SystemPrompt::new("No variables { }".to_string())
+                .render()
+                .expect("Failed to render"),
+            "No variables { }"
+        );
+    }
+
+    #[test]
+    fn test_render_errors() {
+        let missing = SystemPrompt::new("Hi {{name}}".to_string());
+        assert!(matches!(missing.render(), Err(Error::InvalidMessage(_))));
+        let not_closed = SystemPrompt::new("Hi {{name".to_string())
+            .variable("name".to_string(), "Ana".to_string());
+        assert!(matches!(not_closed.render(), Err(Error::InvalidMessage(_))));
+    }
+}
@@ -0,0 +1,443 @@
+use std::sync::OnceLock;
+
+use tiktoken_rs::CoreBPE;
+
+use crate::{
+    chat_context::{ChatContext, FunctionCallMode, ToolChoice},
+    function_specification::{FunctionSpecification, Property},
+    message::{Message, Role},
+};
+
+// Every message is wrapped in `<|start|>{role}\n{content}<|end|>\n`
+const TOKENS_PER_MESSAGE: usize = 3;
+// The name replaces the role, but it still costs one token
+const TOKENS_PER_NAME: usize = 1;
+// Every reply is primed with `<|start|>assistant<|message|>`
+const TOKENS_PER_REPLY: usize = 3;
+// The function definitions are sent in a hidden system message
+const TOKENS_PER_FUNCTIONS: usize = 9;
+
+/// The byte pair encodings used by the chat models
+///
+/// The ranks of both encodings are embedded in the binary, no network access is needed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// Used by gpt-3.5-turbo and gpt-4
+    Cl100kBase,
+    /// Used by gpt-4o and the o-series models
+    O200kBase,
+}
+
+impl Encoding {
+    /// Returns the encoding used by the model. Unknown models use cl100k_base
+    pub fn for_model(model: &str) -> Encoding {
+        let o200k = ["gpt-4o", "gpt-4.1", "gpt-4.5", "gpt-5", "o1", "o3", "o4"];
+        if o200k.iter().any(|prefix| model.starts_with(prefix)) {
+            Encoding::O200kBase
+        } else {
+            Encoding::Cl100kBase
+        }
+    }
+
+    // The ranks are parsed once, the first time the encoding is used
+    fn bpe(self) -> &'static CoreBPE {
+        static CL100K_BASE: OnceLock<CoreBPE> = OnceLock::new();
+        static O200K_BASE: OnceLock<CoreBPE> = OnceLock::new();
+        match self {
+            Encoding::Cl100kBase => CL100K_BASE.get_or_init(|| {
+                tiktoken_rs::cl100k_base().expect("The embedded cl100k_base ranks are valid")
+            }),
+            Encoding::O200kBase => O200K_BASE.get_or_init(|| {
+                tiktoken_rs::o200k_base().expect("The embedded o200k_base ranks are valid")
+            }),
+        }
+    }
+}
+
+/// Counts tokens locally, to know whether a context fits the context window of the model
+/// before sending it
+///
+/// The counts of messages and functions add the overhead the OpenAI API adds to the prompt,
+/// so they can be compared with `prompt_tokens` in the usage of the response.
+/// OpenAI doesn't document the overhead of the functions, it may change with new models.
+///
+/// # Example
+/// ```
+/// use chatgpt_functions::{message::Message, tokenizer::Tokenizer};
+///
+/// let tokenizer = Tokenizer::for_model("gpt-3.5-turbo");
+/// assert_eq!(tokenizer.count("Hello world"), 2);
+/// let message = Message::new_user_message("Hello world".to_string());
+/// assert_eq!(tokenizer.count_message(&message), 6);
+/// ```
+#[derive(Clone, Copy)]
+pub struct Tokenizer {
+    encoding: Encoding,
+    bpe: &'static CoreBPE,
+}
+
+impl Tokenizer {
+    /// Creates a tokenizer for the encoding
+    pub fn new(encoding: Encoding) -> Tokenizer {
+        Tokenizer {
+            encoding,
+            bpe: encoding.bpe(),
+        }
+    }
+
+    /// Creates a tokenizer for the encoding used by the model
+    pub fn for_model(model: &str) -> Tokenizer {
+        Tokenizer::new(Encoding::for_model(model))
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Returns the tokens of the text. Special tokens are encoded as plain text
+    pub fn encode(&self, text: &str) -> Vec<usize> {
+        self.bpe.encode_ordinary(text)
+    }
+
+    /// Counts the tokens of the text
+    pub fn count(&self, text: &str) -> usize {
+        self.encode(text).len()
+    }
+
+    /// Counts the tokens of a message, including the tokens that wrap it
+    pub fn count_message(&self, message: &Message) -> usize {
+        let mut tokens = TOKENS_PER_MESSAGE + self.count(message.role.as_str());
+        if let Some(content) = &message.content {
+            tokens += self.count(content);
+        }
+        if let Some(name) = &message.name {
+            tokens += self.count(name) + TOKENS_PER_NAME;
+        }
+        if message.role == Role::Function {
+            // The name of the function replaces the role
+            tokens -= 2;
+        }
+        let function_calls = message
+            .function_call
+            .iter()
+            .chain(message.tool_calls.iter().flatten().map(|t| &t.function));
+        for function_call in function_calls {
+            tokens += self.count(&function_call.name) + self.count(&function_call.arguments) + 3;
+        }
+        tokens
+    }
+
+    /// Counts the tokens of the function definitions
+    ///
+    /// The API sends them to the model as TypeScript declarations in a system message,
+    /// they are counted in the same format.
+    pub fn count_functions(&self, functions: &[FunctionSpecification]) -> usize {
+        if functions.is_empty() {
+            return 0;
+        }
+        self.count(&format_functions(functions)) + TOKENS_PER_FUNCTIONS
+    }
+
+    /// Counts the tokens of the prompt the context will send: the messages,
+    /// the functions and tools, and how the model is asked to call them
+    pub fn count_context(&self, chat_context: &ChatContext) -> usize {
+        let functions: Vec<FunctionSpecification> = chat_context
+            .functions
+            .iter()
+            .chain(chat_context.tools.iter().map(|tool| &tool.function))
+            .cloned()
+            .collect();
+
+        let mut tokens = TOKENS_PER_REPLY;
+        let mut padded_system = false;
+        for message in &chat_context.messages {
+            tokens += self.count_message(message);
+            // The definitions are appended to the first system message, after a newline
+            if message.role == Role::System && !functions.is_empty() && !padded_system {
+                tokens += 1;
+                padded_system = true;
+            }
+        }
+        tokens += self.count_functions(&functions);
+        if padded_system {
+            // The definitions share the system message instead of having their own
+            tokens -= 4;
+        }
+
+        let forced_function = match (&chat_context.function_call, &chat_context.tool_choice) {
+            (Some(FunctionCallMode::None), _) | (_, Some(ToolChoice::None)) => return tokens + 1,
+            (Some(FunctionCallMode::Named(name)), _) | (_, Some(ToolChoice::Function(name))) => {
+                Some(name)
+            }
+            _ => None,
+        };
+        if let Some(name) = forced_function {
+            tokens += self.count(name) + 4;
+        }
+        tokens
+    }
+}
+
+impl std::fmt::Debug for Tokenizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tokenizer")
+            .field("encoding", &self.encoding)
+            .finish()
+    }
+}
+
+// The functions as the model sees them:
+// namespace functions {
+//
+// // Get the current weather
+// type get_current_weather = (_: {
+// location: string,
+// unit?: "celsius" | "fahrenheit",
+// }) => any;
+//
+// } // namespace functions
+fn format_functions(functions: &[FunctionSpecification]) -> String {
+    let mut lines = vec!["namespace functions {".to_string(), String::new()];
+    for function in functions {
+        if let Some(description) = &function.description {
+            lines.push(format!("// {}", description));
+        }
+        match &function.parameters {
+            Some(parameters) if !parameters.properties.is_empty() => {
+                lines.push(format!("type {} = (_: {{", function.name));
+                let object = Property {
+                    properties: Some(parameters.properties.clone()),
+                    required: Some(parameters.required.clone()),
+                    ..Property::default()
+                };
+                lines.push(format_properties(&object, 0));
+                lines.push("}) => any;".to_string());
+            }
+            _ => lines.push(format!("type {} = () => any;", function.name)),
+        }
+        lines.push(String::new());
+    }
+    lines.push("} // namespace functions".to_string());
+    lines.join("\n")
+}
+
+fn format_properties(object: &Property, indent: usize) -> String {
+    let properties = match &object.properties {
+        Some(properties) => properties,
+        None => return String::new(),
+    };
+    // The order of the properties is lost in the HashMap, sorting keeps the count stable
+    let mut names: Vec<&String> = properties.keys().collect();
+    names.sort();
+
+    let required = object.required.as_deref().unwrap_or_default();
+    let mut lines = Vec::new();
+    for name in names {
+        let property = &properties[name];
+        if let (Some(description), true) = (&property.description, indent < 2) {
+            lines.push(format!("// {}", description));
+        }
+        let optional = if required.contains(name) { "" } else { "?" };
+        lines.push(format!(
+            "{}{}: {},",
+            name,
+            optional,
+            format_type(property, indent)
+        ));
+    }
+    lines
+        .iter()
+        .map(|line| format!("{}{}", " ".repeat(indent), line))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn format_type(property: &Property, indent: usize) -> String {
+    if let Some(values) = &property.enum_ {
+        let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        return values.join(" | ");
+    }
+    if let Some(schemas) = property.any_of.as_ref().or(property.one_of.as_ref()) {
+        let types: Vec<String> = schemas.iter().map(|s| format_type(s, indent)).collect();
+        return types.join(" | ");
+    }
+    match property.type_.as_str() {
+        "string" | "number" | "boolean" | "null" => property.type_.clone(),
+        "integer" => "number".to_string(),
+        "array" => match &property.items {
+            Some(items) => format!("{}[]", format_type(items, indent)),
+            None => "any[]".to_string(),
+        },
+        "object" => format!("{{\n{}\n}}", format_properties(property, indent + 2)),
+        _ => "any".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        function_specification::{Parameters, Tool},
+        message::{FunctionCall, ToolCall},
+    };
+    use serde_json::json;
+
+    fn weather() -> FunctionSpecification {
+        serde_json::from_value(json!({
+            "name": "get_current_weather",
+            "description": "Get the current weather in a given location",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "location": {"type": "string", "description": "The city and state, e.g. San Francisco, CA"},
+                    "unit": {"type": "string", "enum": ["celsius", "fahrenheit"]}
+                },
+                "required": ["location"]
+            }
+        }))
+        .expect("Invalid specification")
+    }
+
+    #[test]
+    fn test_encoding_for_model() {
+        assert_eq!(
+            Encoding::for_model("gpt-3.5-turbo-0613"),
+            Encoding::Cl100kBase
+        );
+        assert_eq!(Encoding::for_model("gpt-4"), Encoding::Cl100kBase);
+        assert_eq!(Encoding::for_model("gpt-4o-mini"), Encoding::O200kBase);
+        assert_eq!(Encoding::for_model("unknown"), Encoding::Cl100kBase);
+    }
+
+    #[test]
+    fn test_count() {
+        let cl100k = Tokenizer::new(Encoding::Cl100kBase);
+        assert_eq!(cl100k.encode("hello world"), vec![15339, 1917]);
+        assert_eq!(cl100k.count(""), 0);
+        // Special tokens are not interpreted
+        assert_eq!(cl100k.count("<|endoftext|>"), 7);
+
+        let o200k = Tokenizer::new(Encoding::O200kBase);
+        assert_eq!(o200k.encode("hello world"), vec![24912, 2375]);
+    }
+
+    #[test]
+    fn test_count_message() {
+        let tokenizer = Tokenizer::new(Encoding::Cl100kBase);
+        let mut message = Message::new_user_message("Hello world".to_string());
+        assert_eq!(tokenizer.count_message(&message), 6);
+        message.set_name("example_user".to_string());
+        assert_eq!(
+            tokenizer.count_message(&message),
+            6 + tokenizer.count("example_user") + 1
+        );
+
+        let mut message = Message::new(Role::Assistant);
+        message.function_call = Some(FunctionCall {
+            name: "get_current_weather".to_string(),
+            arguments: r#"{"location": "Madrid"}"#.to_string(),
+        });
+        assert_eq!(
+            tokenizer.count_message(&message),
+            3 + 1 + (tokenizer.count("get_current_weather") + 7 + 3)
+        );
+
+        // A tool call costs the same as the function call
+        let function_call = tokenizer.count_message(&message);
+        message.tool_calls = Some(vec![ToolCall::new(
+            "call_1".to_string(),
+            message
+                .function_call
+                .take()
+                .expect("There is a function call"),
+        )]);
+        assert_eq!(tokenizer.count_message(&message), function_call);
+    }
+
+    #[test]
+    fn test_format_functions() {
+        assert_eq!(
+            format_functions(&[weather()]),
+            "namespace functions {\n\n\
+             // Get the current weather in a given location\n\
+             type get_current_weather = (_: {\n\
+             // The city and state, e.g. San Francisco, CA\n\
+             location: string,\n\
+             unit?: \"celsius\" | \"fahrenheit\",\n\
+             }) => any;\n\n\
+             } // namespace functions"
+        );
+    }
+
+    #[test]
+    fn test_format_nested_types() {
+        let specification: FunctionSpecification = serde_json::from_value(json!({
+            "name": "search",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "ids": {"type": "array", "items": {"type": "integer"}},
+                    "filter": {
+                        "type": "object",
+                        "properties": {"since": {"type": "string", "description": "Not shown"}},
+                        "required": ["since"]
+                    }
+                }
+            }
+        }))
+        .expect("Invalid specification");
+        let formatted = format_functions(&[specification]);
+        assert!(formatted.contains("filter?: {\n  since: string,\n},"));
+        assert!(formatted.contains("ids?: number[],"));
+
+        let empty = FunctionSpecification {
+            name: "now".to_string(),
+            description: None,
+            parameters: Some(Parameters {
+                type_: "object".to_string(),
+                properties: Default::default(),
+                required: vec![],
+            }),
+        };
+        assert!(format_functions(&[empty]).contains("type now = () => any;"));
+    }
+
+    #[test]
+    fn test_count_context() {
+        let mut chat_context = ChatContext::new("gpt-3.5-turbo-0613".to_string());
+        chat_context.push_message(Message::new_user_message("hello".to_string()));
+        assert_eq!(chat_context.count_tokens(), 8);
+
+        chat_context.push_function(weather());
+        let with_function = chat_context.count_tokens();
+        assert_eq!(
+            with_function,
+            8 + Tokenizer::new(Encoding::Cl100kBase).count_functions(&[weather()])
+        );
+
+        // A system message shares its tokens with the definitions
+        let mut with_system = chat_context.clone();
+        let system = Message::system("You are a helpful assistant".to_string());
+        with_system.messages.insert(0, system.clone());
+        let tokenizer = Tokenizer::new(Encoding::Cl100kBase);
+        assert_eq!(
+            with_system.count_tokens(),
+            with_function + tokenizer.count_message(&system) + 1 - 4
+        );
+
+        // Tools are counted as functions
+        let mut with_tool = ChatContext::new("gpt-3.5-turbo-0613".to_string());
+        with_tool.push_message(Message::new_user_message("hello".to_string()));
+        with_tool.push_tool(Tool::function(weather()));
+        assert_eq!(with_tool.count_tokens(), with_function);
+
+        chat_context.function_call =
+            Some(FunctionCallMode::Named("get_current_weather".to_string()));
+        assert_eq!(
+            chat_context.count_tokens(),
+            with_function + tokenizer.count("get_current_weather") + 4
+        );
+        chat_context.function_call = Some(FunctionCallMode::None);
+        assert_eq!(chat_context.count_tokens(), with_function + 1);
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+use futures_util::{stream, Stream, StreamExt};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+use crate::error::{Error, Result};
+
+/// The future returned by Transport::send
+pub type TransportFuture<'a> = Pin<Box<dyn Future<Output = Result<TransportResponse>> + Send + 'a>>;
+
+/// The body of a response, received as a stream of bytes
+pub type ByteStream = Pin<Box<dyn Stream<Item = Result<Vec<u8>>> + Send>>;
+
+/// Sends the requests of ChatGPT to the API
+///
+/// ChatGPT uses ReqwestTransport by default. Another transport can be set with
+/// ChatGPTBuilder::transport, e.g. an instrumented HTTP client, or a ScriptedTransport
+/// to test the logic of a chatbot without network access.
+///
+/// The transport only moves bytes: it returns the response whatever its status,
+/// ChatGPT takes care of the retries and of turning the error statuses into errors.
+/// The errors of a custom transport can be reported with Error::Connection.
+pub trait Transport: Send + Sync {
+    fn send(&self, request: TransportRequest) -> TransportFuture<'_>;
+}
+
+/// A request to the chat completions endpoint
+#[derive(Clone, PartialEq, Eq)]
+pub struct TransportRequest {
+    pub url: String,
+    /// The API token, sent as a bearer token
+    pub api_key: String,
+    /// The JSON of the request
+    pub body: String,
+}
+
+impl fmt::Debug for TransportRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The API token is not printed, so the requests can be logged
+        f.debug_struct("TransportRequest")
+            .field("url", &self.url)
+            .field("body", &self.body)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The response of the API, with the body not read yet, so that it can be streamed
+pub struct TransportResponse {
+    pub status: u16,
+    pub headers: HeaderMap,
+    pub body: ByteStream,
+}
+
+impl TransportResponse {
+    /// A response with the whole body at once
+    pub fn new(status: u16, body: String) -> TransportResponse {
+        TransportResponse::from_chunks(status, vec![body])
+    }
+
+    /// A response with the body split in chunks, received one at a time like a streamed response
+    pub fn from_chunks(status: u16, chunks: Vec<String>) -> TransportResponse {
+        let chunks = chunks.into_iter().map(|chunk| Ok(chunk.into_bytes()));
+        TransportResponse {
+            status,
+            headers: HeaderMap::new(),
+            body: Box::pin(stream::iter(chunks)),
+        }
+    }
+
+    /// Adds a header to the response. Headers with an invalid name or value are ignored
+    pub fn header(mut self, name: &str, value: &str) -> TransportResponse {
+        if let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+            self.headers.append(name, value);
+        }
+        self
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Reads the whole body
+    pub async fn text(mut self) -> Result<String> {
+        let mut bytes = Vec::new();
+        while let Some(chunk) = self.body.next().await {
+            bytes.extend(chunk?);
+        }
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+impl fmt::Debug for TransportResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransportResponse")
+            .field("status", &self.status)
+            .field("headers", &self.headers)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Sends the requests over HTTP with reqwest. It is the transport used by default
+#[derive(Clone, Debug, Default)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+impl ReqwestTransport {
+    /// Uses the client provided, e.g. one with a proxy or custom timeouts
+    pub fn new(client: reqwest::Client) -> ReqwestTransport {
+        ReqwestTransport { client }
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn send(&self, request: TransportRequest) -> TransportFuture<'_> {
+        Box::pin(async move {
+            let response = self
+                .client
+                .post(&request.url)
+                .bearer_auth(&request.api_key)
+                .header("Content-Type", "application/json")
+                .body(request.body)
+                .send()
+                .await?;
+            let status = response.status().as_u16();
+            let headers = response.headers().clone();
+            let body = response
+                .bytes_stream()
+                .map(|chunk| chunk.map(|b| b.to_vec()).map_err(Error::from));
+            Ok(TransportResponse {
+                status,
+                headers,
+                body: Box::pin(body),
+            })
+        })
+    }
+}
+
+/// A transport that replies with a script of responses, without network access.
+/// It records the requests it receives, so the tests can assert on them
+///
+/// Each request gets the next response of the script, in order.
+/// When the script is exhausted, the requests fail with Error::Connection.
+///
+/// # Example
+/// ```
+/// use std::sync::Arc;
+/// use chatgpt_functions::{
+///     chat_gpt::ChatGPTBuilder,
+///     transport::{ScriptedTransport, TransportResponse},
+/// };
+///
+/// # #[tokio::main]
+/// # async fn main() -> chatgpt_functions::Result<()> {
+/// let body = r#"{"id":"1","object":"chat.completion","created":0,"model":"gpt-4o",
+///     "choices":[{"index":0,"message":{"role":"assistant","content":"Hi!"},"finish_reason":"stop"}],
+///     "usage":{"prompt_tokens":9,"completion_tokens":2,"total_tokens":11}}"#;
+/// let transport = Arc::new(ScriptedTransport::new().response(TransportResponse::new(200, body.to_string())));
+/// let mut gpt = ChatGPTBuilder::new()
+///     .openai_api_token("key".to_string())
+///     .transport(transport.clone())
+///     .build()?;
+///
+/// let answer = gpt.completion_managed("Hello".to_string()).await?;
+/// assert_eq!(answer.content(), Some("Hi!".to_string()));
+/// assert_eq!(transport.requests().len(), 1);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct ScriptedTransport {
+    script: Arc<Mutex<VecDeque<Result<TransportResponse>>>>,
+    requests: Arc<Mutex<Vec<TransportRequest>>>,
+}
+
+impl ScriptedTransport {
+    pub fn new() -> ScriptedTransport {
+        ScriptedTransport::default()
+    }
+
+    /// Adds a response to the end of the script
+    pub fn response(self, response: TransportResponse) -> ScriptedTransport {
+        self.push(Ok(response));
+        self
+    }
+
+    /// Adds an error to the end of the script, e.g. to simulate a connection failure
+    pub fn error(self, error: Error) -> ScriptedTransport {
+        self.push(Err(error));
+        self
+    }
+
+    /// Adds a response or an error to the end of the script.
+    /// It can be called while the transport is in use
+    pub fn push(&self, response: Result<TransportResponse>) {
+        self.script
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push_back(response);
+    }
+
+    /// The requests received so far, oldest first
+    pub fn requests(&self) -> Vec<TransportRequest> {
+        self.requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// The number of responses of the script that were not sent yet
+    pub fn remaining(&self) -> usize {
+        self.script.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+}
+
+impl Transport for ScriptedTransport {
+    fn send(&self, request: TransportRequest) -> TransportFuture<'_> {
+        self.requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(request);
+        let next = self
+            .script
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop_front();
+        Box::pin(async move {
+            next.unwrap_or_else(|| {
+                Err(Error::Connection(anyhow::anyhow!(
+                    "The scripted transport has no more responses"
+                )))
+            })
+        })
+    }
+}
+
+impl fmt::Debug for ScriptedTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScriptedTransport")
+            .field("remaining", &self.remaining())
+            .field("requests", &self.requests())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{MockResponse, MockServer};
+
+    #[tokio::test]
+    async fn test_reqwest_transport() {
+        let server = MockServer::start(vec![
+            MockResponse::new(500, "boom").header("Retry-After", "1")
+        ])
+        .await;
+        let response = ReqwestTransport::default()
+            .send(TransportRequest {
+                url: format!("{}/chat/completions", server.url),
+                api_key: "key".to_string(),
+                body: "{}".to_string(),
+            })
+            .await
+            .expect("Failed to send");
+        // The error statuses are returned as responses
+        assert_eq!(response.status, 500);
+        assert!(!response.is_success());
+        assert_eq!(response.headers["retry-after"], "1");
+        assert_eq!(response.text().await.expect("Failed to read"), "boom");
+
+        let request = &server.requests()[0];
+        assert_eq!(request.path, "/chat/completions");
+        assert_eq!(request.header("Authorization"), Some("Bearer key"));
+        assert_eq!(request.body, "{}");
+    }
+
+    #[tokio::test]
+    async fn test_scripted_transport() {
+        let transport = ScriptedTransport::new()
+            .response(
+                TransportResponse::from_chunks(200, vec!["a".to_string(), "b".to_string()])
+                    .header("x-request-id", "42"),
+            )
+            .error(Error::MissingConfiguration("offline".to_string()));
+        let request = TransportRequest {
+            url: "http://localhost/chat/completions".to_string(),
+            api_key: "secret".to_string(),
+            body: "{}".to_string(),
+        };
+
+        let response = transport
+            .send(request.clone())
+            .await
+            .expect("Failed to send");
+        assert_eq!(response.headers["x-request-id"], "42");
+        assert_eq!(response.text().await.expect("Failed to read"), "ab");
+        assert!(matches!(
+            transport.send(request.clone()).await,
+            Err(Error::MissingConfiguration(_))
+        ));
+        assert!(matches!(
+            transport.send(request.clone()).await,
+            Err(Error::Connection(_))
+        ));
+        assert_eq!(transport.requests(), vec![request.clone(); 3]);
+        assert_eq!(transport.remaining(), 0);
+        assert!(!format!("{:?}", request).contains("secret"));
+    }
+}
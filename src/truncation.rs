@@ -0,0 +1,264 @@
+use std::ops::Range;
+
+use crate::{
+    chat_context::ChatContext,
+    message::{Message, Role},
+    tokenizer::Tokenizer,
+};
+
+/// Removes messages from the context before it is sent, so that long conversations
+/// don't exceed the context window of the model
+///
+/// The strategies are applied by ChatGPT before every request, in the order they were added
+/// with ChatGPTBuilder::truncation_strategy. The messages removed are lost for the next requests too.
+///
+/// Implementations have to keep the context valid for the API: a function or tool result
+/// can't be sent without the assistant message that called it. The strategies of this module
+/// remove whole exchanges, see message_groups.
+///
+/// # Example
+/// ```
+/// use chatgpt_functions::{
+///     chat_gpt::ChatGPTBuilder,
+///     truncation::{FitTokenBudget, KeepLastMessages},
+/// };
+///
+/// let gpt = ChatGPTBuilder::new()
+///     .openai_api_token("key".to_string())
+///     .truncation_strategy(KeepLastMessages::new(50))
+///     .truncation_strategy(FitTokenBudget::new(3000))
+///     .build();
+/// ```
+pub trait TruncationStrategy: Send + Sync {
+    fn truncate(&self, chat_context: &mut ChatContext);
+}
+
+/// Keeps the system messages and the last `count` messages of the conversation
+///
+/// It can keep less than `count` messages, when keeping `count` would split a function call
+/// from its results.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeepLastMessages {
+    pub count: usize,
+}
+
+impl KeepLastMessages {
+    pub fn new(count: usize) -> KeepLastMessages {
+        KeepLastMessages { count }
+    }
+}
+
+impl TruncationStrategy for KeepLastMessages {
+    fn truncate(&self, chat_context: &mut ChatContext) {
+        let groups = message_groups(&chat_context.messages);
+        let first_kept = first_recent_group(&groups, self.count);
+        remove_groups(chat_context, &groups[..first_kept]);
+    }
+}
+
+/// Keeps the system messages and removes the oldest messages of the conversation
+/// until the prompt fits in `max_tokens`, counted with the Tokenizer of the model of the context
+///
+/// The last exchange is always kept, even if it doesn't fit on its own.
+/// Leave room for the answer: `max_tokens` plus the maximum tokens of the completion
+/// has to fit in the context window of the model.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FitTokenBudget {
+    pub max_tokens: usize,
+}
+
+impl FitTokenBudget {
+    pub fn new(max_tokens: usize) -> FitTokenBudget {
+        FitTokenBudget { max_tokens }
+    }
+}
+
+impl TruncationStrategy for FitTokenBudget {
+    fn truncate(&self, chat_context: &mut ChatContext) {
+        let tokenizer = Tokenizer::for_model(&chat_context.model);
+        let mut tokens = tokenizer.count_context(chat_context);
+        let groups = message_groups(&chat_context.messages);
+        let mut removed = 0;
+        // The messages of a group are counted on their own, it is the same as in the whole context
+        // because the system messages, that carry the extra tokens of the functions, are never removed
+        while tokens > self.max_tokens && removed + 1 < groups.len() {
+            tokens -= chat_context.messages[groups[removed].clone()]
+                .iter()
+                .map(|m| tokenizer.count_message(m))
+                .sum::<usize>();
+            removed += 1;
+        }
+        remove_groups(chat_context, &groups[..removed]);
+    }
+}
+
+/// Splits the messages of the conversation in the groups that have to be kept or removed together,
+/// returned as ranges of indexes of `messages`, oldest first
+///
+/// A message starts a new group, unless it is the result of a function or tool call:
+/// the results stay in the group of the assistant message that called them.
+/// The system messages are not part of any group, they are never removed.
+pub fn message_groups(messages: &[Message]) -> Vec<Range<usize>> {
+    let mut groups: Vec<Range<usize>> = Vec::new();
+    for (i, message) in messages.iter().enumerate() {
+        match message.role {
+            Role::System => {}
+            Role::Function | Role::Tool => match groups.last_mut() {
+                Some(group) if group.end == i => group.end = i + 1,
+                // A result without its call is already orphaned, it goes on its own
+                _ => groups.push(i..i + 1),
+            },
+            _ => groups.push(i..i + 1),
+        }
+    }
+    groups
+}
+
+// The index of the first of the last groups that together have at most `count` messages
+pub(crate) fn first_recent_group(groups: &[Range<usize>], count: usize) -> usize {
+    let mut kept = 0;
+    let mut first_kept = groups.len();
+    for (i, group) in groups.iter().enumerate().rev() {
+        if kept + group.len() > count {
+            break;
+        }
+        kept += group.len();
+        first_kept = i;
+    }
+    first_kept
+}
+
+fn remove_groups(chat_context: &mut ChatContext, groups: &[Range<usize>]) {
+    let mut i = 0;
+    chat_context.messages.retain(|_| {
+        let keep = !groups.iter().any(|group| group.contains(&i));
+        i += 1;
+        keep
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::FunctionCall;
+
+    fn message(role: Role, content: &str) -> Message {
+        let mut message = Message::new(role);
+        message.set_content(content.to_string());
+        message
+    }
+
+    fn function_call(name: &str) -> Message {
+        let mut message = Message::new(Role::Assistant);
+        message.function_call = Some(FunctionCall {
+            name: name.to_string(),
+            arguments: "{}".to_string(),
+        });
+        message
+    }
+
+    fn context(messages: Vec<Message>) -> ChatContext {
+        let mut chat_context = ChatContext::new("gpt-3.5-turbo-0613".to_string());
+        chat_context.set_messages(messages);
+        chat_context
+    }
+
+    fn contents(chat_context: &ChatContext) -> Vec<String> {
+        chat_context
+            .messages
+            .iter()
+            .map(|m| {
+                m.content
+                    .clone()
+                    .or(m.function_call.as_ref().map(|f| f.name.clone()))
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    fn conversation() -> Vec<Message> {
+        vec![
+            message(Role::System, "You are a support bot"),
+            message(Role::User, "Hi"),
+            message(Role::Assistant, "Hello, how can I help?"),
+            message(Role::User, "Where is my order?"),
+            function_call("track_order"),
+            message(Role::Function, "In transit"),
+            message(Role::Assistant, "It is in transit"),
+        ]
+    }
+
+    #[test]
+    fn test_message_groups() {
+        assert_eq!(
+            message_groups(&conversation()),
+            vec![1..2, 2..3, 3..4, 4..6, 6..7]
+        );
+        assert_eq!(
+            message_groups(&[message(Role::Tool, "orphan"), message(Role::User, "Hi")]),
+            vec![0..1, 1..2]
+        );
+    }
+
+    #[test]
+    fn test_keep_last_messages() {
+        let mut chat_context = context(conversation());
+        KeepLastMessages::new(4).truncate(&mut chat_context);
+        assert_eq!(
+            contents(&chat_context),
+            vec![
+                "You are a support bot",
+                "Where is my order?",
+                "track_order",
+                "In transit",
+                "It is in transit"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keep_last_messages_does_not_orphan_results() {
+        let mut chat_context = context(conversation());
+        // The last 2 messages would be the function result and the answer
+        KeepLastMessages::new(2).truncate(&mut chat_context);
+        assert_eq!(
+            contents(&chat_context),
+            vec!["You are a support bot", "It is in transit"]
+        );
+    }
+
+    #[test]
+    fn test_fit_token_budget() {
+        let mut chat_context = context(conversation());
+        let tokens = chat_context.count_tokens();
+        FitTokenBudget::new(tokens).truncate(&mut chat_context);
+        assert_eq!(chat_context.messages.len(), 7);
+
+        // Removing the first user message and the first answer is enough
+        let tokenizer = Tokenizer::for_model(&chat_context.model);
+        let first_exchange = tokenizer.count_message(&chat_context.messages[1])
+            + tokenizer.count_message(&chat_context.messages[2]);
+        FitTokenBudget::new(tokens - first_exchange).truncate(&mut chat_context);
+        assert_eq!(chat_context.count_tokens(), tokens - first_exchange);
+        assert_eq!(
+            contents(&chat_context),
+            vec![
+                "You are a support bot",
+                "Where is my order?",
+                "track_order",
+                "In transit",
+                "It is in transit"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fit_token_budget_keeps_the_last_exchange() {
+        let mut chat_context = context(conversation());
+        FitTokenBudget::new(0).truncate(&mut chat_context);
+        assert_eq!(
+            contents(&chat_context),
+            vec!["You are a support bot", "It is in transit"]
+        );
+    }
+}
@@ -0,0 +1,361 @@
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::function_specification::{FunctionSpecification, Property};
+
+/// What to do when the model calls a function with arguments that don't match its specification
+///
+/// It is used by ChatGPT::run_until_answer, before running the handler of the function.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ValidationMode {
+    /// The arguments are not validated, the handler receives them as they are
+    #[default]
+    Off,
+    /// The call fails with Error::InvalidArguments
+    Reject,
+    /// The violations are sent back to the model as the result of the call,
+    /// asking it to call the function again with valid arguments.
+    /// Every correction counts as an iteration, see ChatGPTBuilder::max_function_iterations
+    Correct,
+}
+
+/// A problem found in the arguments of a function call
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+    /// The path of the argument, e.g. `filter.ids[2]`. Empty for the arguments as a whole
+    pub path: String,
+    pub kind: ViolationKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ViolationKind {
+    /// The arguments are not valid JSON
+    InvalidJson(String),
+    /// The function is not declared in the context
+    UnknownFunction(String),
+    /// A required field is missing
+    MissingRequired,
+    /// The field is not declared, and the object doesn't allow additional properties
+    UnknownField,
+    /// The value does not have the declared type
+    WrongType { expected: String, found: String },
+    /// The value is not one of the values declared in `enum`
+    NotInEnum { allowed: Vec<Value> },
+    /// The number is out of the declared bounds
+    OutOfRange {
+        minimum: Option<f64>,
+        maximum: Option<f64>,
+    },
+    /// The value does not match any of the schemas of `anyOf`, or exactly one of `oneOf`
+    NoMatchingSchema,
+}
+
+/// Checks the arguments of a function call against the specification of the function
+/// It returns every violation found, the arguments are valid if it is empty
+/// # Arguments
+/// * `specification` - The specification of the function that was called
+/// * `arguments` - The arguments of the call, as sent by the model
+pub fn validate_arguments(
+    specification: &FunctionSpecification,
+    arguments: &str,
+) -> Vec<Violation> {
+    let value: Value = match serde_json::from_str(arguments) {
+        Ok(value) => value,
+        Err(e) => {
+            return vec![Violation {
+                path: String::new(),
+                kind: ViolationKind::InvalidJson(e.to_string()),
+            }]
+        }
+    };
+    let mut violations = Vec::new();
+    let parameters = match &specification.parameters {
+        Some(parameters) => parameters,
+        None => return violations,
+    };
+    let object = Property {
+        type_: parameters.type_.clone(),
+        properties: Some(parameters.properties.clone()),
+        required: Some(parameters.required.clone()),
+        ..Property::default()
+    };
+    validate_value(&object, &value, "", &mut violations);
+    violations
+}
+
+// Appends to `violations` the problems of `value` against `schema`
+fn validate_value(schema: &Property, value: &Value, path: &str, violations: &mut Vec<Violation>) {
+    let mut violation = |path: &str, kind: ViolationKind| {
+        violations.push(Violation {
+            path: path.to_string(),
+            kind,
+        })
+    };
+
+    if !schema.type_.is_empty() && !has_type(value, &schema.type_) {
+        violation(
+            path,
+            ViolationKind::WrongType {
+                expected: schema.type_.clone(),
+                found: type_of(value).to_string(),
+            },
+        );
+        return;
+    }
+    if let Some(allowed) = &schema.enum_ {
+        if !allowed.iter().any(|a| equals(a, value)) {
+            violation(
+                path,
+                ViolationKind::NotInEnum {
+                    allowed: allowed.clone(),
+                },
+            );
+        }
+    }
+    if let Some(number) = value.as_f64() {
+        let below = schema.minimum.is_some_and(|minimum| number < minimum);
+        let above = schema.maximum.is_some_and(|maximum| number > maximum);
+        if below || above {
+            violation(
+                path,
+                ViolationKind::OutOfRange {
+                    minimum: schema.minimum,
+                    maximum: schema.maximum,
+                },
+            );
+        }
+    }
+    if let Some(any_of) = &schema.any_of {
+        if !any_of.iter().any(|s| is_valid(s, value)) {
+            violation(path, ViolationKind::NoMatchingSchema);
+        }
+    }
+    if let Some(one_of) = &schema.one_of {
+        if one_of.iter().filter(|s| is_valid(s, value)).count() != 1 {
+            violation(path, ViolationKind::NoMatchingSchema);
+        }
+    }
+
+    match value {
+        Value::Object(object) => {
+            for required in schema.required.iter().flatten() {
+                if !object.contains_key(required) {
+                    violation(&join(path, required), ViolationKind::MissingRequired);
+                }
+            }
+            let properties = schema.properties.as_ref();
+            for (key, field) in object {
+                match properties.and_then(|p| p.get(key)) {
+                    Some(property) => validate_value(property, field, &join(path, key), violations),
+                    None if schema.additional_properties == Some(false) => {
+                        violations.push(Violation {
+                            path: join(path, key),
+                            kind: ViolationKind::UnknownField,
+                        })
+                    }
+                    None => {}
+                }
+            }
+        }
+        Value::Array(array) => {
+            if let Some(items) = &schema.items {
+                for (i, item) in array.iter().enumerate() {
+                    validate_value(items, item, &format!("{}[{}]", path, i), violations);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_valid(schema: &Property, value: &Value) -> bool {
+    let mut violations = Vec::new();
+    validate_value(schema, value, "", &mut violations);
+    violations.is_empty()
+}
+
+fn has_type(value: &Value, type_: &str) -> bool {
+    match type_ {
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        "null" => value.is_null(),
+        // Unknown types are not checked
+        _ => true,
+    }
+}
+
+fn type_of(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+// 1 and 1.0 are the same value for JSON Schema
+fn equals(a: &Value, b: &Value) -> bool {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+// The message sent back to the model in ValidationMode::Correct
+pub(crate) fn correction_message(name: &str, violations: &[Violation]) -> String {
+    let mut message = format!("The arguments of the call to {} are not valid:\n", name);
+    for violation in violations {
+        message.push_str(&format!("- {}\n", violation));
+    }
+    message.push_str("Call the function again with arguments that match its parameters.");
+    message
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.path.is_empty() {
+            write!(f, "`{}` ", self.path)?;
+        }
+        match &self.kind {
+            ViolationKind::InvalidJson(e) => write!(f, "invalid JSON: {}", e),
+            ViolationKind::UnknownFunction(name) => {
+                write!(f, "the function {} does not exist", name)
+            }
+            ViolationKind::MissingRequired => write!(f, "is required"),
+            ViolationKind::UnknownField => write!(f, "is not a parameter"),
+            ViolationKind::WrongType { expected, found } => {
+                write!(f, "must be of type {}, not {}", expected, found)
+            }
+            ViolationKind::NotInEnum { allowed } => {
+                let allowed: Vec<String> = allowed.iter().map(|a| a.to_string()).collect();
+                write!(f, "must be one of {}", allowed.join(", "))
+            }
+            ViolationKind::OutOfRange { minimum, maximum } => match (minimum, maximum) {
+                (Some(minimum), Some(maximum)) => {
+                    write!(f, "must be between {} and {}", minimum, maximum)
+                }
+                (Some(minimum), None) => write!(f, "must be at least {}", minimum),
+                (None, Some(maximum)) => write!(f, "must be at most {}", maximum),
+                (None, None) => write!(f, "is out of range"),
+            },
+            ViolationKind::NoMatchingSchema => {
+                write!(f, "does not match any of the allowed schemas")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn specification() -> FunctionSpecification {
+        serde_json::from_value(json!({
+            "name": "search_orders",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "status": {"type": "string", "enum": ["open", "closed"]},
+                    "limit": {"type": "integer", "minimum": 1, "maximum": 100},
+                    "ids": {"type": "array", "items": {"type": "integer"}},
+                    "filter": {
+                        "type": "object",
+                        "properties": {"since": {"type": "string"}},
+                        "required": ["since"],
+                        "additionalProperties": false
+                    }
+                },
+                "required": ["status"]
+            }
+        }))
+        .expect("Invalid specification")
+    }
+
+    #[test]
+    fn test_valid_arguments() {
+        let violations = validate_arguments(
+            &specification(),
+            r#"{"status": "open", "limit": 10, "ids": [1, 2], "filter": {"since": "2023-01-01"}}"#,
+        );
+        assert_eq!(violations, vec![]);
+    }
+
+    #[test]
+    fn test_invalid_json() {
+        let violations = validate_arguments(&specification(), r#"{"status": "open""#);
+        assert!(matches!(
+            violations.as_slice(),
+            [Violation {
+                kind: ViolationKind::InvalidJson(_),
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_violations() {
+        let violations = validate_arguments(
+            &specification(),
+            r#"{"limit": 0, "ids": [1, "2"], "filter": {"until": "2023-01-01"}, "status": "pending"}"#,
+        );
+        let described: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+        assert_eq!(violations.len(), 5, "{:?}", described);
+        assert!(described.contains(&"`status` must be one of \"open\", \"closed\"".to_string()));
+        assert!(described.contains(&"`limit` must be between 1 and 100".to_string()));
+        assert!(described.contains(&"`ids[1]` must be of type integer, not string".to_string()));
+        assert!(described.contains(&"`filter.since` is required".to_string()));
+        assert!(described.contains(&"`filter.until` is not a parameter".to_string()));
+    }
+
+    #[test]
+    fn test_missing_required() {
+        let violations = validate_arguments(&specification(), "{}");
+        assert_eq!(
+            violations,
+            vec![Violation {
+                path: "status".to_string(),
+                kind: ViolationKind::MissingRequired,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_any_of() {
+        let specification: FunctionSpecification = serde_json::from_value(json!({
+            "name": "set_amount",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "amount": {"anyOf": [{"type": "number"}, {"type": "null"}]}
+                },
+                "required": []
+            }
+        }))
+        .expect("Invalid specification");
+        assert!(validate_arguments(&specification, r#"{"amount": null}"#).is_empty());
+        assert!(validate_arguments(&specification, r#"{"amount": 2.5}"#).is_empty());
+        assert_eq!(
+            validate_arguments(&specification, r#"{"amount": "2.5"}"#)[0].kind,
+            ViolationKind::NoMatchingSchema
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use std::fs;
+
+use chatgpt_functions::chat_response::ChatResponse;
+use serde_json::Value;
+
+// Real-world response bodies, kept as they were received
+fn fixture(name: &str) -> String {
+    let path = format!(
+        "{}/tests/fixtures/responses/{}.json",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    fs::read_to_string(&path).unwrap_or_else(|e| panic!("Could not read {}: {}", path, e))
+}
+
+// Checks that the parsed response has exactly the content and arguments of the body
+fn assert_intact(name: &str) -> ChatResponse {
+    let body = fixture(name);
+    let response = ChatResponse::from_json(&body)
+        .unwrap_or_else(|e| panic!("Could not parse {}: {}", name, e));
+    let expected: Value = serde_json::from_str(&body).expect("The fixture is not valid JSON");
+    let message = &expected["choices"][0]["message"];
+
+    assert_eq!(response.content().as_deref(), message["content"].as_str());
+    assert_eq!(
+        response.function_call().map(|(_, arguments)| arguments),
+        message["function_call"]["arguments"]
+            .as_str()
+            .map(str::to_string)
+    );
+    let arguments: Vec<String> = response
+        .tool_calls()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|tool_call| tool_call.function.arguments)
+        .collect();
+    let expected_arguments: Vec<String> = message["tool_calls"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|t| {
+            t["function"]["arguments"]
+                .as_str()
+                .unwrap_or("")
+                .to_string()
+        })
+        .collect();
+    assert_eq!(arguments, expected_arguments);
+    response
+}
+
+#[test]
+fn test_multiline_code() {
+    let response = assert_intact("multiline_code");
+    let content = response.content().expect("There is no content");
+    assert!(content.contains("```rust\nuse std::fs::File;\n"));
+    assert!(content.contains("\tprintln!(\"{}\", line?);"));
+    assert!(content.contains(r#"File::open("C:\\data\\input.txt")"#));
+}
+
+#[test]
+fn test_unicode() {
+    let response = assert_intact("unicode");
+    let content = response.content().expect("There is no content");
+    assert!(content.contains("👋 En japonés se dice こんにちは, en árabe مرحبا"));
+    assert!(content.contains("éè 😀 ☃ \u{0} end."));
+}
+
+#[test]
+fn test_nested_arguments() {
+    let response = assert_intact("nested_arguments");
+    let (name, arguments) = response.function_call().expect("There is no function call");
+    assert_eq!(name, "create_ticket");
+    let arguments: Value = serde_json::from_str(&arguments).expect("Invalid arguments");
+    assert_eq!(arguments["title"], "Login fails with \"invalid token\"");
+    assert_eq!(arguments["details"]["steps"], "1. Open the app\n2. Log in");
+    assert_eq!(arguments["details"]["metadata"]["path"], "C:\\Users\\ana");
+    assert_eq!(arguments["details"]["metadata"]["retries"], 3);
+}
+
+#[test]
+fn test_tool_calls() {
+    let response = assert_intact("tool_calls");
+    let tool_calls = response.tool_calls().expect("There are no tool calls");
+    assert_eq!(tool_calls.len(), 2);
+    assert_eq!(tool_calls[0].id, "call_Wd3zQ0Jx1GKl2h6c");
+    assert_eq!(
+        tool_calls[1].function.arguments,
+        r#"{"location": "東京", "unit": "celsius"}"#
+    );
+}
+
+// The model sometimes sends raw newlines inside the arguments, which is not valid JSON
+#[test]
+fn test_raw_newlines_in_arguments() {
+    let body = fixture("raw_newlines_in_arguments");
+    assert!(serde_json::from_str::<Value>(&body).is_err());
+
+    let response = ChatResponse::from_json(&body).expect("Could not parse the response");
+    let (name, arguments) = response.function_call().expect("There is no function call");
+    assert_eq!(name, "write_file");
+    let arguments: Value = serde_json::from_str(&arguments).expect("Invalid arguments");
+    assert_eq!(arguments["path"], "src/main.rs");
+    assert_eq!(
+        arguments["content"],
+        "fn main() {\n    println!(\"Hello\");\n}"
+    );
+}
+
+#[test]
+fn test_raw_newlines_in_content() {
+    let body = fixture("raw_newlines_in_content");
+    let response = ChatResponse::from_json(&body).expect("Could not parse the response");
+    assert_eq!(
+        response.content(),
+        Some("A table:\n| a\t| b |\n|---|---|\n| 1\t| 2 |".to_string())
+    );
+}